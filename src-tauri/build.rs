@@ -1,3 +1,15 @@
 fn main() {
+    // Embed the commit for `/version`; builds outside a git checkout still work.
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    if let Ok(out) = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+    {
+        if out.status.success() {
+            let sha = String::from_utf8_lossy(&out.stdout);
+            println!("cargo:rustc-env=DIFFRANT_GIT_SHA={}", sha.trim());
+        }
+    }
+
     tauri_build::build()
 }
@@ -0,0 +1,144 @@
+//! Bounded in-memory cache of decoded frames, shared by the HTTP handlers so
+//! flipping between nearby frames doesn't decode the same data repeatedly.
+//!
+//! Entries are keyed by reader generation and frame index. The cache holds
+//! frames of one generation at a time: the first access with a newer
+//! generation (i.e. after `open_file` replaced the reader) empties it, and
+//! accesses with an older one are ignored.
+//!
+//! Eviction is least-recently-used by total pixel bytes: after an insert,
+//! the entries used longest ago are dropped until the total fits the budget.
+//! A frame larger than the whole budget is never cached. Finding the oldest
+//! entry is a linear scan, which is fine for the few dozen frames a
+//! realistic budget holds.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A decoded frame as returned by [`crate::readers::Reader::read_frame`].
+#[derive(Clone)]
+pub struct CachedFrame {
+    pub pixels: Arc<Vec<u16>>,
+    pub width: usize,
+    pub height: usize,
+}
+
+pub struct FrameCache {
+    budget_bytes: usize,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    generation: u64,
+    bytes: usize,
+    /// Incremented on every access; an entry's `last_used` is the tick of
+    /// its most recent access.
+    tick: u64,
+    entries: HashMap<usize, Entry>,
+}
+
+struct Entry {
+    frame: CachedFrame,
+    last_used: u64,
+}
+
+impl FrameCache {
+    /// A cache holding at most `budget_bytes` of pixel data; 0 disables it.
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            budget_bytes,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// The cache contents for `generation`, emptying them first if they are
+    /// from an older one. `None` if `generation` is older than the contents,
+    /// i.e. the caller's reader has already been replaced.
+    fn lock(&self, generation: u64) -> Option<std::sync::MutexGuard<'_, Inner>> {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if generation < inner.generation {
+            return None;
+        }
+        if inner.generation != generation {
+            inner.entries.clear();
+            inner.bytes = 0;
+            inner.generation = generation;
+        }
+        Some(inner)
+    }
+
+    /// Whether the cache can hold anything at all.
+    pub fn is_enabled(&self) -> bool {
+        self.budget_bytes > 0
+    }
+
+    /// Whether `frame` is cached, without marking it as used.
+    pub fn contains(&self, generation: u64, frame: usize) -> bool {
+        self.lock(generation)
+            .is_some_and(|inner| inner.entries.contains_key(&frame))
+    }
+
+    /// The cached frame, if any, marking it as recently used.
+    pub fn get(&self, generation: u64, frame: usize) -> Option<CachedFrame> {
+        if self.budget_bytes == 0 {
+            return None;
+        }
+        let mut inner = self.lock(generation)?;
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(&frame)?;
+        entry.last_used = tick;
+        Some(entry.frame.clone())
+    }
+
+    /// Drop every cached frame, returning how many frames and pixel bytes
+    /// were freed.
+    pub fn clear(&self) -> (usize, usize) {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let freed = (inner.entries.len(), inner.bytes);
+        inner.entries.clear();
+        inner.bytes = 0;
+        freed
+    }
+
+    /// Add a decoded frame, evicting least recently used frames to stay
+    /// within the budget.
+    pub fn insert(&self, generation: u64, frame: usize, cached: CachedFrame) {
+        let size = cached.pixels.len() * std::mem::size_of::<u16>();
+        if size > self.budget_bytes {
+            return;
+        }
+        let Some(mut inner) = self.lock(generation) else {
+            return;
+        };
+        inner.tick += 1;
+        let entry = Entry {
+            frame: cached,
+            last_used: inner.tick,
+        };
+        if let Some(old) = inner.entries.insert(frame, entry) {
+            inner.bytes -= old.frame.pixels.len() * std::mem::size_of::<u16>();
+        }
+        inner.bytes += size;
+        while inner.bytes > self.budget_bytes {
+            let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(&k, _)| k)
+            else {
+                break;
+            };
+            if let Some(evicted) = inner.entries.remove(&oldest) {
+                inner.bytes -= evicted.frame.pixels.len() * std::mem::size_of::<u16>();
+            }
+        }
+    }
+}
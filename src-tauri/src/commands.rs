@@ -1,11 +1,192 @@
-use serde::Serialize;
-use tauri::State;
+use std::sync::atomic::Ordering;
 
-use crate::{AppState, readers};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, State};
+
+use crate::export::{self, ExportFormat, ExportJob};
+use crate::readers::ImageMetadata;
+use crate::stats::{self, FrameStats, MaxPixel};
+use crate::{AppState, CancelToken, readers};
+
+/// Why opening a file failed, serialized as `{"kind": "not_found",
+/// "message": "..."}` so the frontend can show guidance for each kind.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommandError {
+    /// The file doesn't exist, or a command needs an open file and there is
+    /// none.
+    NotFound { message: String },
+    /// No reader handles this kind of file.
+    UnsupportedFormat { message: String },
+    /// A supported kind of file that can't be opened as one, e.g. truncated
+    /// or not really HDF5, or a session file that can't be parsed.
+    Unreadable { message: String },
+    /// The file opened, but its frames or metadata can't be read.
+    BadDataset { message: String },
+    /// The file's frames are compressed with a filter whose HDF5 plugin
+    /// isn't installed.
+    MissingFilter { message: String },
+    /// Reading from disk failed, e.g. permission denied.
+    Io { message: String },
+    /// The command was called with arguments it can't act on, e.g. an
+    /// empty list of files.
+    InvalidArgument { message: String },
+    /// A failure in the app itself rather than the file.
+    Internal { message: String },
+}
+
+impl CommandError {
+    pub fn message(&self) -> &str {
+        match self {
+            Self::NotFound { message }
+            | Self::UnsupportedFormat { message }
+            | Self::Unreadable { message }
+            | Self::BadDataset { message }
+            | Self::MissingFilter { message }
+            | Self::Io { message }
+            | Self::InvalidArgument { message }
+            | Self::Internal { message } => message,
+        }
+    }
+
+    /// Classify an error from opening a file with `readers::open`.
+    fn opening(e: anyhow::Error) -> Self {
+        let message = format!("failed to open file: {e}");
+        if e.downcast_ref::<readers::UnsupportedFormat>().is_some() {
+            Self::UnsupportedFormat { message }
+        } else if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            Self::io(io, message)
+        } else {
+            Self::Unreadable { message }
+        }
+    }
+
+    /// Classify an error from reading an already opened file.
+    fn reading(e: anyhow::Error) -> Self {
+        let message = format!("failed to read file: {e}");
+        if e.downcast_ref::<readers::nxs::MissingFilter>().is_some() {
+            Self::MissingFilter { message }
+        } else if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            Self::io(io, message)
+        } else {
+            Self::BadDataset { message }
+        }
+    }
+
+    fn io(e: &std::io::Error, message: String) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => Self::NotFound { message },
+            _ => Self::Io { message },
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
 
 #[derive(Serialize)]
 pub struct OpenFileResult {
     pub frame_count: usize,
+    /// The same metadata `/metadata` serves, so the first view can be drawn
+    /// without another round trip.
+    pub metadata: ImageMetadata,
+    /// Only present when `open_file` was asked to check frame 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame0_overloads: Option<OverloadReport>,
+}
+
+/// How many pixels of a frame are above `trusted_range_max`. If nearly all
+/// of them are, the trusted range is probably wrong for this file.
+#[derive(Serialize)]
+pub struct OverloadReport {
+    pub overloads: usize,
+    pub pixels: usize,
+}
+
+/// The file most recently opened with `open_file`, and how it was opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveFile {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vfd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_mask_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub planar_rows: Option<bool>,
+    /// Files continuing `path`'s frames, for a series opened with
+    /// `open_file_series`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub series: Vec<String>,
+}
+
+/// Contents of a session file written by `save_session`.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub file: ActiveFile,
+    /// Frontend view state (current frame, applied transforms, ...), stored
+    /// as given and handed back unchanged by `load_session`.
+    #[serde(default)]
+    pub view: serde_json::Value,
+}
+
+const SESSION_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct LoadSessionResult {
+    pub file: ActiveFile,
+    pub open: OpenFileResult,
+    pub view: serde_json::Value,
+}
+
+/// Payload of the `export-progress` event.
+#[derive(Clone, Serialize)]
+pub struct ExportProgress {
+    pub written: usize,
+    pub total: usize,
+}
+
+/// Payload of the `open-progress` event, emitted as a file is opened:
+/// `{"path": "...", "stage": "counting_frames", "elapsed_ms": 1520}`.
+/// Every open emits `started`, then `finished` or `failed`.
+#[derive(Clone, Serialize)]
+pub struct OpenProgress {
+    /// The file being opened; the first file for a series.
+    pub path: String,
+    pub stage: OpenStage,
+    /// Time since `started`.
+    pub elapsed_ms: u64,
+    /// Why the open failed; only present for `failed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Stages of `open-progress`, in the order they are reached.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenStage {
+    Started,
+    /// Validating the file and resolving its image dataset.
+    Opening,
+    /// Counting frames, which can mean touching every data file.
+    CountingFrames,
+    ReadingMetadata,
+    /// Decoding frame 0 for `check_overloads`.
+    CheckingOverloads,
+    Finished,
+    Failed,
+}
+
+#[derive(Serialize)]
+pub struct InspectFileResult {
+    pub metadata: ImageMetadata,
+    pub frame_count: usize,
+    pub format: &'static str,
 }
 
 /// Returns the port the embedded HTTP server is listening on.
@@ -16,25 +197,470 @@ pub fn get_server_port(state: State<'_, AppState>) -> u16 {
 }
 
 /// Open an NXS/HDF5 file and make it the active file for the embedded server.
-/// Returns the number of frames in the file.
+/// Returns the number of frames in the file and its metadata; the file is
+/// not opened if either cannot be read.
+///
+/// `vfd` optionally selects the HDF5 virtual file driver (`sec2`, `stdio` or
+/// `core`) for storage where the default performs badly. `data_path` names
+/// the HDF5 image dataset for layouts the usual search doesn't find.
+/// `extra_mask_path` names a bad-pixel mask file (a text list of pixels or
+/// a mask image) to merge into the detector's mask. `planar_rows` says
+/// whether the detector stores even and odd rows apart, overriding the
+/// detectors listed in [`crate::PLANAR_DETECTORS_ENV`].
+///
+/// With `check_overloads`, frame 0 is also decoded and its overloaded pixel
+/// count returned, so a mis-set trusted range can be flagged straight away.
+/// This costs one frame read, so it is off by default.
+///
+/// Emits `open-progress` events as the file is opened; see [`OpenProgress`].
+/// Failures are a [`CommandError`] saying what kind of problem it was.
 #[tauri::command]
 pub async fn open_file(
     path: String,
+    vfd: Option<String>,
+    data_path: Option<String>,
+    extra_mask_path: Option<String>,
+    planar_rows: Option<bool>,
+    check_overloads: Option<bool>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<OpenFileResult, CommandError> {
+    let file = ActiveFile {
+        path,
+        vfd,
+        data_path,
+        extra_mask_path,
+        planar_rows,
+        series: Vec::new(),
+    };
+    open_active(file, check_overloads.unwrap_or(false), &app, &state).await
+}
+
+/// Open several files holding consecutive parts of one scan (e.g.
+/// `sweep_0001.nxs`, `sweep_0002.nxs`, ...) as a single active file, their
+/// frames numbered end to end in the order given. Fails if the files'
+/// pixel dimensions or pixel size differ. Otherwise behaves like `open_file`.
+#[tauri::command]
+pub async fn open_file_series(
+    paths: Vec<String>,
+    vfd: Option<String>,
+    data_path: Option<String>,
+    extra_mask_path: Option<String>,
+    planar_rows: Option<bool>,
+    check_overloads: Option<bool>,
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
-) -> Result<OpenFileResult, String> {
-    tracing::info!("Opening file: {path}");
+) -> Result<OpenFileResult, CommandError> {
+    let mut paths = paths.into_iter();
+    let Some(path) = paths.next() else {
+        return Err(CommandError::InvalidArgument {
+            message: "A file series needs at least one file".to_string(),
+        });
+    };
+    let file = ActiveFile {
+        path,
+        vfd,
+        data_path,
+        extra_mask_path,
+        planar_rows,
+        series: paths.collect(),
+    };
+    open_active(file, check_overloads.unwrap_or(false), &app, &state).await
+}
+
+/// Close the active file, releasing its handle (which some network
+/// filesystems keep locked) and the server's caches. The server then answers
+/// as if no file had been opened. Closing with no file open does nothing.
+#[tauri::command]
+pub async fn close_file(state: State<'_, AppState>) -> Result<(), String> {
+    let mut guard = state.reader.lock().await;
+    if guard.take().is_none() {
+        return Ok(());
+    }
+    state.generation.fetch_add(1, Ordering::SeqCst);
+    *state.active_file.lock().await = None;
+    state.activity.set_closed_idle(false);
+    drop(guard);
+    let freed = state.server.clear_caches();
+    tracing::info!("Closed file, freeing {} cached bytes", freed.bytes_freed);
+    Ok(())
+}
+
+/// Recently opened files, most recent first. Every file opened successfully
+/// is added, so this is the list for a recent-files menu.
+#[tauri::command]
+pub async fn get_recent_files(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    Ok(state.recent_files.lock().await.paths().to_vec())
+}
+
+/// Put `path` at the top of the recent-files list without opening it, and
+/// return the updated list.
+#[tauri::command]
+pub async fn add_recent_file(
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let mut recent = state.recent_files.lock().await;
+    recent
+        .add(path)
+        .map_err(|e| format!("failed to save recent files: {e}"))?;
+    Ok(recent.paths().to_vec())
+}
 
-    let (reader, frame_count) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
-        let reader = readers::open(std::path::Path::new(&path))?;
-        let frame_count = reader.frame_count()?;
-        Ok((reader, frame_count))
+/// Open `file` and make it the active file; shared by `open_file`,
+/// `open_file_series` and `load_session`.
+async fn open_active(
+    file: ActiveFile,
+    check_overloads: bool,
+    app: &tauri::AppHandle,
+    state: &AppState,
+) -> Result<OpenFileResult, CommandError> {
+    tracing::info!("Opening file: {}", file.path);
+    let t0 = std::time::Instant::now();
+    let progress = {
+        let app = app.clone();
+        let path = file.path.clone();
+        move |stage, error| {
+            let event = OpenProgress {
+                path: path.clone(),
+                stage,
+                elapsed_ms: t0.elapsed().as_millis() as u64,
+                error,
+            };
+            let _ = app.emit("open-progress", event);
+        }
+    };
+    progress(OpenStage::Started, None);
+    let options = readers::OpenOptions {
+        vfd: file.vfd.clone(),
+        data_path: file.data_path.clone(),
+        extra_mask_path: file.extra_mask_path.clone(),
+        planar_rows: file.planar_rows,
+        planar_detectors: std::env::var(crate::PLANAR_DETECTORS_ENV)
+            .map(|list| list.split(',').map(str::to_owned).collect())
+            .unwrap_or_default(),
+    };
+    let paths: Vec<_> = std::iter::once(&file.path)
+        .chain(&file.series)
+        .map(std::path::PathBuf::from)
+        .collect();
+
+    let blocking_progress = progress.clone();
+    let result = tokio::task::spawn_blocking(move || -> Result<_, CommandError> {
+        let progress = |stage| blocking_progress(stage, None);
+        progress(OpenStage::Opening);
+        for path in &paths {
+            if let Err(e) = std::fs::metadata(readers::archive::file_on_disk(path)) {
+                let message = format!("failed to open file {}: {e}", path.display());
+                return Err(CommandError::io(&e, message));
+            }
+        }
+        let reader = match &paths[..] {
+            [path] => readers::open(path, &options),
+            _ => {
+                let paths: Vec<_> = paths.iter().map(|p| p.as_path()).collect();
+                readers::open_series(&paths, &options)
+            }
+        }
+        .map_err(CommandError::opening)?;
+        progress(OpenStage::CountingFrames);
+        let frame_count = reader.frame_count().map_err(CommandError::reading)?;
+        progress(OpenStage::ReadingMetadata);
+        let metadata = reader.metadata().map_err(CommandError::reading)?;
+        let frame0_overloads = if check_overloads && frame_count > 0 {
+            progress(OpenStage::CheckingOverloads);
+            let (pixels, _, _) = reader.read_frame(0).map_err(CommandError::reading)?;
+            let stats = FrameStats::compute(0, &pixels, metadata.trusted_range_max);
+            Some(OverloadReport {
+                overloads: stats.overloads,
+                pixels: pixels.len(),
+            })
+        } else {
+            None
+        };
+        Ok((reader, frame_count, metadata, frame0_overloads))
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(CommandError::Internal {
+            message: format!("task error: {e}"),
+        })
+    });
+    let (reader, frame_count, metadata, frame0_overloads) = match result {
+        Ok(opened) => opened,
+        Err(e) => {
+            progress(OpenStage::Failed, Some(e.to_string()));
+            return Err(e);
+        }
+    };
+
+    if file.series.is_empty() {
+        tracing::info!("Opened file: {frame_count} frames");
+    } else {
+        tracing::info!(
+            "Opened series of {} files: {frame_count} frames",
+            file.series.len() + 1
+        );
+    }
+    if let Some(report) = &frame0_overloads {
+        tracing::info!(
+            "Frame 0: {} of {} pixels overloaded",
+            report.overloads,
+            report.pixels
+        );
+    }
+    let recent_path = file.path.clone();
+    let mut guard = state.reader.lock().await;
+    *guard = Some(reader);
+    state.generation.fetch_add(1, Ordering::SeqCst);
+    state.server.cache_metadata(frame_count, metadata.clone());
+    *state.active_file.lock().await = Some(file);
+    state.activity.touch();
+    state.activity.set_closed_idle(false);
+    drop(guard);
+    if let Err(e) = state.recent_files.lock().await.add(recent_path) {
+        tracing::warn!("Cannot save recent files: {e}");
+    }
+    progress(OpenStage::Finished, None);
+
+    Ok(OpenFileResult {
+        frame_count,
+        metadata,
+        frame0_overloads,
+    })
+}
+
+/// Stop all running aggregate analyses (montages, stats streams and
+/// mean/variance builds for z-scores). They end early with an error.
+#[tauri::command]
+pub fn cancel_analysis(state: State<'_, AppState>) {
+    tracing::info!("Cancelling running analyses");
+    state.analysis_epoch.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Write the active file, how it was opened, and the frontend's `view` state
+/// to `dest` as JSON, so the same view can be restored later or shared.
+#[tauri::command]
+pub async fn save_session(
+    dest: String,
+    view: Option<serde_json::Value>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let Some(file) = state.active_file.lock().await.clone() else {
+        return Err("No file open".to_string());
+    };
+    let session = Session {
+        version: SESSION_VERSION,
+        file,
+        view: view.unwrap_or_default(),
+    };
+    let json = serde_json::to_vec_pretty(&session).map_err(|e| e.to_string())?;
+    tokio::fs::write(&dest, json)
+        .await
+        .map_err(|e| format!("failed to write session {dest}: {e}"))?;
+    tracing::info!("Saved session to {dest}");
+    Ok(())
+}
+
+/// Restore a session written by `save_session`: reopen its file the same way
+/// and return the saved `view` state for the frontend to reapply. Emits
+/// `open-progress` events like `open_file`.
+#[tauri::command]
+pub async fn load_session(
+    path: String,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<LoadSessionResult, CommandError> {
+    let json = tokio::fs::read(&path).await.map_err(|e| {
+        let message = format!("failed to read session {path}: {e}");
+        CommandError::io(&e, message)
+    })?;
+    let session: Session = serde_json::from_slice(&json).map_err(|e| CommandError::Unreadable {
+        message: format!("invalid session {path}: {e}"),
+    })?;
+    if session.version != SESSION_VERSION {
+        return Err(CommandError::UnsupportedFormat {
+            message: format!(
+                "Unsupported session version {} (expected {SESSION_VERSION})",
+                session.version
+            ),
+        });
+    }
+
+    let open = open_active(session.file.clone(), false, &app, &state).await?;
+    Ok(LoadSessionResult {
+        file: session.file,
+        open,
+        view: session.view,
+    })
+}
+
+/// Write frames `[start, end)` of the active file to `dest` as a multi-page
+/// TIFF or an HDF5 stack (`format` = `tiff` or `hdf5`), with the geometry
+/// embedded. Emits `export-progress` events as frames are written. Stops with
+/// an error if the file is replaced or `cancel_analysis` is called, and
+/// refuses a `dest` that is one of the files being read.
+#[tauri::command]
+pub async fn export_frames(
+    start: usize,
+    end: usize,
+    dest: String,
+    format: ExportFormat,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    let Some(source) = state.active_file.lock().await.clone() else {
+        return Err("No file open".to_string());
+    };
+    let reader_arc = state.reader.clone();
+    let generation = state.generation.clone();
+    let server = state.server.clone();
+    let cancel = CancelToken::new(&state.analysis_epoch);
+
+    tokio::task::spawn_blocking(move || -> anyhow::Result<usize> {
+        let dest_resolved = resolved_path(std::path::Path::new(&dest));
+        let inputs = std::iter::once(&source.path)
+            .chain(&source.series)
+            .chain(&source.extra_mask_path);
+        for input in inputs {
+            if resolved_path(std::path::Path::new(input)) == dest_resolved {
+                anyhow::bail!("Cannot export over {input}, which is being read");
+            }
+        }
+        let (metadata, frame_count, export_generation) = {
+            let guard = reader_arc.blocking_lock();
+            let Some(reader) = guard.as_ref() else {
+                anyhow::bail!("No file open");
+            };
+            let generation = generation.load(Ordering::SeqCst);
+            let metadata = server.metadata(reader.as_ref())?;
+            (metadata, reader.frame_count()?, generation)
+        };
+        if start >= end || end > frame_count {
+            anyhow::bail!("Invalid frame range {start}..{end} (file has {frame_count} frames)");
+        }
+
+        let job = ExportJob {
+            format,
+            dest: std::path::Path::new(&dest),
+            source: std::path::Path::new(&source.path),
+            metadata: &metadata,
+            frames: start..end,
+        };
+        // Lock per frame so the viewer stays responsive during a long export.
+        let read_frame = |frame| {
+            if cancel.is_cancelled() {
+                anyhow::bail!("Export cancelled");
+            }
+            let guard = reader_arc.blocking_lock();
+            match guard.as_ref() {
+                Some(reader) if generation.load(Ordering::SeqCst) == export_generation => {
+                    reader.read_frame(frame)
+                }
+                _ => anyhow::bail!("File changed during export"),
+            }
+        };
+        let total = end - start;
+        let progress = |written| {
+            let _ = app.emit("export-progress", ExportProgress { written, total });
+        };
+        export::export_frames(&job, read_frame, progress)?;
+        tracing::info!("Exported {total} frames to {dest}");
+        Ok(total)
     })
     .await
     .map_err(|e| format!("task error: {e}"))?
-    .map_err(|e| format!("failed to open file: {e}"))?;
+    .map_err(|e| format!("export failed: {e}"))
+}
+
+/// `path` made absolute with symlinks resolved, so two names for one file
+/// compare equal. Only its directory has to exist.
+fn resolved_path(path: &std::path::Path) -> std::path::PathBuf {
+    if let Ok(path) = std::fs::canonicalize(path) {
+        return path;
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    match (std::fs::canonicalize(dir), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
 
-    tracing::info!("Opened file: {frame_count} frames");
-    *state.reader.lock().await = Some(reader);
+/// Locate the brightest unmasked pixel of `frame` in the active file, as
+/// `/max_pixel/{frame}` does. Overloads are skipped unless
+/// `include_overloads`. Returns `None` if no pixel qualifies.
+#[tauri::command]
+pub async fn max_pixel(
+    frame: usize,
+    include_overloads: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Option<MaxPixel>, String> {
+    let reader_arc = state.reader.clone();
+    let server = state.server.clone();
+    tokio::task::spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            anyhow::bail!("No file open");
+        };
+        let trusted_max = server.metadata(reader.as_ref())?.trusted_range_max;
+        let include_overloads = include_overloads.unwrap_or(false);
+        stats::max_pixel(reader.as_ref(), frame, trusted_max, include_overloads)
+    })
+    .await
+    .map_err(|e| format!("task error: {e}"))?
+    .map_err(|e| format!("failed to locate max pixel: {e}"))
+}
 
-    Ok(OpenFileResult { frame_count })
+/// Read `frame` of the active file as little-endian u16 bytes, the same
+/// pixels `/image/{frame}` serves, over IPC instead of the embedded server.
+/// A fallback for when the localhost binding is blocked. The bytes are sent
+/// as a raw `ArrayBuffer` rather than a JSON array of numbers. Fails with
+/// `not_found` if no file is open, as `/image/{frame}` does with 404.
+#[tauri::command]
+pub async fn read_frame(
+    frame: usize,
+    state: State<'_, AppState>,
+) -> Result<tauri::ipc::Response, CommandError> {
+    let reader_arc = state.reader.clone();
+    let server = state.server.clone();
+    tokio::task::spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err(CommandError::NotFound {
+                message: "No file open".to_string(),
+            });
+        };
+        let cached = server
+            .read_frame(reader.as_ref(), frame)
+            .map_err(CommandError::reading)?;
+        let bytes: Vec<u8> = cached.pixels.iter().flat_map(|v| v.to_le_bytes()).collect();
+        Ok(tauri::ipc::Response::new(bytes))
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(CommandError::Internal {
+            message: format!("task error: {e}"),
+        })
+    })
+}
+
+/// Read the full metadata of a file without making it the active file.
+/// The file is opened, inspected and closed; `state.reader` is untouched, so
+/// this can be used to preview several candidate files.
+#[tauri::command]
+pub async fn inspect_file(path: String) -> Result<InspectFileResult, String> {
+    tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let reader = readers::open(std::path::Path::new(&path), &Default::default())?;
+        Ok(InspectFileResult {
+            metadata: reader.metadata()?,
+            frame_count: reader.frame_count()?,
+            format: reader.format_name(),
+        })
+    })
+    .await
+    .map_err(|e| format!("task error: {e}"))?
+    .map_err(|e| format!("failed to inspect file: {e}"))
 }
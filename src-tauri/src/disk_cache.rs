@@ -0,0 +1,282 @@
+//! Optional on-disk cache of decoded frames, so the frames of a file
+//! revisited in a later session don't all have to be decoded again. It sits
+//! behind the in-memory [`crate::cache::FrameCache`]: a frame missing there
+//! is looked for here before the reader decodes it.
+//!
+//! Each frame is one file in the cache directory, named by a hash of its
+//! key: the path and modification time of the file the frame is stored in
+//! and its index there (see [`Reader::frame_source`]), the image dataset,
+//! and the reader's reordering of the stored pixels (see
+//! [`Reader::applied_transforms`]). So a frame of a file series is keyed by
+//! the member holding it, whatever else is in the series, and a file opened
+//! with and without planar row reordering has separate entries. A file
+//! changed since is modified later, so its old frames are never matched
+//! again and age out. The full key is stored in the entry too and checked on
+//! read, so a hash collision is a miss rather than a wrong frame.
+//!
+//! Eviction is least-recently-used by total file size: a hit bumps the
+//! entry's modification time, and after each insert the entries modified
+//! longest ago are deleted until the directory fits the budget. The cache
+//! outlives sessions, so only [`DiskCache::clear`] empties it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+use crate::cache::CachedFrame;
+use crate::readers::Reader;
+use crate::readers::archive::split_member;
+
+/// Start of every entry, so files that aren't entries are never read as one.
+const MAGIC: &[u8; 4] = b"DFC1";
+
+pub struct DiskCache {
+    dir: PathBuf,
+    budget_bytes: u64,
+}
+
+impl DiskCache {
+    /// A cache of at most `budget_bytes` in `dir`, which is created on the
+    /// first insert.
+    pub fn new(dir: PathBuf, budget_bytes: u64) -> Self {
+        Self { dir, budget_bytes }
+    }
+
+    /// The cached `frame` of `reader`'s file, if any, marking it as recently
+    /// used. Unreadable entries are misses.
+    pub fn get(&self, reader: &dyn Reader, frame: usize) -> Option<CachedFrame> {
+        let key = entry_key(reader, frame)?;
+        let path = self.entry_path(&key);
+        let bytes = std::fs::read(&path).ok()?;
+        let cached = decode_entry(&bytes, &key)?;
+        if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(cached)
+    }
+
+    /// Store a decoded frame, then evict the least recently used entries
+    /// to stay within the budget.
+    pub fn insert(&self, reader: &dyn Reader, frame: usize, cached: &CachedFrame) -> Result<()> {
+        let Some(key) = entry_key(reader, frame) else {
+            return Ok(());
+        };
+        let entry = encode_entry(&key, cached);
+        if entry.len() as u64 > self.budget_bytes {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        // Written under a temporary name so a reader never sees half an entry.
+        let path = self.entry_path(&key);
+        let partial = path.with_extension("partial");
+        std::fs::File::create(&partial)?.write_all(&entry)?;
+        std::fs::rename(&partial, &path)?;
+        self.evict()
+    }
+
+    /// Delete every entry, returning the bytes removed. A cache that was
+    /// never written to has nothing to remove.
+    pub fn clear(&self) -> u64 {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return 0;
+        };
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                let extension = path.extension()?;
+                if extension != "frame" && extension != "partial" {
+                    return None;
+                }
+                let len = entry.metadata().ok()?.len();
+                std::fs::remove_file(&path).ok()?;
+                Some(len)
+            })
+            .sum()
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.frame", hasher.finish()))
+    }
+
+    /// Delete the entries modified longest ago until the rest fit the budget.
+    fn evict(&self) -> Result<()> {
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                if path.extension()? != "frame" {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), path))
+            })
+            .collect();
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        if total <= self.budget_bytes {
+            return Ok(());
+        }
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in entries {
+            if total <= self.budget_bytes {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                total -= len;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What an entry is a decoded copy of; `None` if the source file's
+/// modification time can't be read, in which case nothing is cached. An
+/// archive entry's is its archive's.
+fn entry_key(reader: &dyn Reader, frame: usize) -> Option<String> {
+    let (source, local) = reader.frame_source(frame).ok()?;
+    let file = split_member(&source).map_or(source.as_path(), |(archive, _)| archive);
+    let modified = std::fs::metadata(file).ok()?.modified().ok()?;
+    let nanos = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    let source = std::fs::canonicalize(&source).unwrap_or(source);
+    Some(format!(
+        "{}\n{nanos}\n{}\n{}\n{local}",
+        source.display(),
+        reader.dataset_path().unwrap_or(""),
+        reader.applied_transforms().join(",")
+    ))
+}
+
+/// `MAGIC`, then the key's length and bytes, the frame's width and height,
+/// and the pixels, all little-endian.
+fn encode_entry(key: &str, cached: &CachedFrame) -> Vec<u8> {
+    let mut out = Vec::with_capacity(16 + key.len() + cached.pixels.len() * 2);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    out.extend_from_slice(key.as_bytes());
+    out.extend_from_slice(&(cached.width as u32).to_le_bytes());
+    out.extend_from_slice(&(cached.height as u32).to_le_bytes());
+    out.extend(cached.pixels.iter().flat_map(|v| v.to_le_bytes()));
+    out
+}
+
+/// The frame in an entry written by [`encode_entry`] for `key`; `None` if
+/// `bytes` is another key's entry or not a well-formed one.
+fn decode_entry(bytes: &[u8], key: &str) -> Option<CachedFrame> {
+    let u32_at = |at: usize| -> Option<usize> {
+        let b = bytes.get(at..at + 4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+    if bytes.get(..4)? != MAGIC {
+        return None;
+    }
+    let key_len = u32_at(4)?;
+    if bytes.get(8..8 + key_len)? != key.as_bytes() {
+        return None;
+    }
+    let at = 8 + key_len;
+    let (width, height) = (u32_at(at)?, u32_at(at + 4)?);
+    let pixels = bytes.get(at + 8..)?;
+    if pixels.len() != width * height * 2 {
+        return None;
+    }
+    Some(CachedFrame {
+        pixels: std::sync::Arc::new(
+            pixels
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+        ),
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::{self, OpenOptions};
+
+    /// Write a raw dump of one 2x2 frame of `value`s.
+    fn write_frame(path: &std::path::Path, value: u16) {
+        std::fs::write(
+            path.with_extension("json"),
+            r#"{"width": 2, "height": 2, "dtype": "u16"}"#,
+        )
+        .unwrap();
+        std::fs::write(path, [value.to_le_bytes(); 4].concat()).unwrap();
+    }
+
+    fn decode(reader: &dyn Reader, frame: usize) -> CachedFrame {
+        let (pixels, width, height) = reader.read_frame(frame).unwrap();
+        CachedFrame {
+            pixels: std::sync::Arc::new(pixels),
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn keeps_the_entries_of_different_readers_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let [a, b, c] = ["a.raw", "b.raw", "c.raw"].map(|name| dir.path().join(name));
+        for (path, value) in [(&a, 1), (&b, 2), (&c, 3)] {
+            write_frame(path, value);
+        }
+        let cache = DiskCache::new(dir.path().join("cache"), 1 << 20);
+        let options = OpenOptions::default();
+
+        // Two series starting with the same file differ from frame 1 on.
+        let ab = readers::open_series(&[&a, &b], &options).unwrap();
+        let ac = readers::open_series(&[&a, &c], &options).unwrap();
+        cache
+            .insert(ab.as_ref(), 1, &decode(ab.as_ref(), 1))
+            .unwrap();
+        assert_eq!(*cache.get(ab.as_ref(), 1).unwrap().pixels, [2; 4]);
+        assert!(cache.get(ac.as_ref(), 1).is_none());
+        // The same file's frame is shared, as a series or alone.
+        cache
+            .insert(ac.as_ref(), 0, &decode(ac.as_ref(), 0))
+            .unwrap();
+        let alone = readers::open(&a, &options).unwrap();
+        assert!(cache.get(ab.as_ref(), 0).is_some());
+        assert!(cache.get(alone.as_ref(), 0).is_some());
+
+        // Reordered frames are kept apart from those as stored.
+        let options = OpenOptions {
+            planar_rows: Some(true),
+            ..Default::default()
+        };
+        let planar = readers::open(&a, &options).unwrap();
+        assert!(cache.get(planar.as_ref(), 0).is_none());
+    }
+
+    #[test]
+    fn clear_removes_every_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.raw");
+        write_frame(&path, 1);
+        let cache = DiskCache::new(dir.path().join("cache"), 1 << 20);
+        assert_eq!(cache.clear(), 0);
+
+        let reader = readers::open(&path, &OpenOptions::default()).unwrap();
+        cache
+            .insert(reader.as_ref(), 0, &decode(reader.as_ref(), 0))
+            .unwrap();
+        let stored = std::fs::read_dir(dir.path().join("cache"))
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum::<u64>();
+        assert!(stored > 0);
+        assert_eq!(cache.clear(), stored);
+        assert!(cache.get(reader.as_ref(), 0).is_none());
+    }
+}
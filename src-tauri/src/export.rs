@@ -0,0 +1,192 @@
+//! Writing a range of decoded frames to a new file.
+//!
+//! Frames are read one at a time through [`Reader::read_frame`], so memory use
+//! stays at one frame regardless of the range. Called from `spawn_blocking`.
+//!
+//! The export is written under a temporary name next to the destination and
+//! renamed over it only once complete, so a failed or cancelled export
+//! leaves an existing file there untouched.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Deserialize;
+use tiff::encoder::{TiffEncoder, colortype};
+use tiff::tags::Tag;
+
+use crate::readers::ImageMetadata;
+
+/// Output container for [`export_frames`].
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One 16-bit grayscale page per frame.
+    Tiff,
+    /// A `[frames, height, width]` u16 dataset at `entry/data/data`.
+    Hdf5,
+}
+
+/// What to export and where.
+pub struct ExportJob<'a> {
+    pub format: ExportFormat,
+    pub dest: &'a Path,
+    /// File the frames come from, recorded in the export.
+    pub source: &'a Path,
+    pub metadata: &'a ImageMetadata,
+    pub frames: Range<usize>,
+}
+
+/// Write `job.frames` to `job.dest`, getting each frame from `read_frame` and
+/// calling `progress(written)` after each one.
+///
+/// Geometry from the metadata goes into the export so it is self-describing:
+/// as a JSON `ImageDescription` tag on every TIFF page, or as attributes of
+/// the HDF5 dataset.
+pub fn export_frames(
+    job: &ExportJob,
+    read_frame: impl FnMut(usize) -> Result<(Vec<u16>, usize, usize)>,
+    progress: impl FnMut(usize),
+) -> Result<()> {
+    let mut partial = job.dest.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    match write_frames(job, &partial, read_frame, progress) {
+        Ok(()) => Ok(std::fs::rename(&partial, job.dest)?),
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Write the export described by `job` to `path`.
+fn write_frames(
+    job: &ExportJob,
+    path: &Path,
+    mut read_frame: impl FnMut(usize) -> Result<(Vec<u16>, usize, usize)>,
+    mut progress: impl FnMut(usize),
+) -> Result<()> {
+    let ExportJob {
+        format,
+        source,
+        metadata,
+        ref frames,
+        ..
+    } = *job;
+    let start = frames.start;
+    match format {
+        ExportFormat::Tiff => {
+            let description = serde_json::json!({
+                "source": source.display().to_string(),
+                "first_frame": start,
+                "metadata": metadata,
+            })
+            .to_string();
+            let mut writer = BufWriter::new(File::create(path)?);
+            let mut tiff = TiffEncoder::new(&mut writer)?;
+            for (i, frame) in frames.clone().enumerate() {
+                let (pixels, width, height) = read_frame(frame)?;
+                let mut page = tiff.new_image::<colortype::Gray16>(width as u32, height as u32)?;
+                page.encoder()
+                    .write_tag(Tag::ImageDescription, description.as_str())?;
+                page.write_data(&pixels)?;
+                progress(i + 1);
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Hdf5 => {
+            let [width, height] = metadata.panel_size_fast_slow.map(|v| v as usize);
+            let file = hdf5::File::create(path)?;
+            let dataset = file
+                .create_group("entry")?
+                .create_group("data")?
+                .new_dataset::<u16>()
+                .chunk((1, height, width))
+                .shape((frames.len(), height, width))
+                .create("data")?;
+
+            let write_f64 = |name: &str, value: f64| -> Result<()> {
+                dataset
+                    .new_attr::<f64>()
+                    .create(name)?
+                    .write_scalar(&value)?;
+                Ok(())
+            };
+            write_f64("panel_distance_mm", metadata.panel_distance_mm)?;
+            write_f64("beam_center_x", metadata.beam_center[0])?;
+            write_f64("beam_center_y", metadata.beam_center[1])?;
+            write_f64("pixel_size_mm", metadata.pixel_size)?;
+            write_f64("trusted_range_max", metadata.trusted_range_max)?;
+            if let Some(energy) = metadata.beam_energy_kev {
+                write_f64("beam_energy_kev", energy)?;
+            }
+            dataset
+                .new_attr::<u64>()
+                .create("first_frame")?
+                .write_scalar(&(start as u64))?;
+            let source: hdf5::types::VarLenUnicode = source.display().to_string().parse()?;
+            dataset
+                .new_attr::<hdf5::types::VarLenUnicode>()
+                .create("source")?
+                .write_scalar(&source)?;
+
+            for (i, frame) in frames.clone().enumerate() {
+                let (pixels, w, h) = read_frame(frame)?;
+                if (w, h) != (width, height) {
+                    anyhow::bail!("Frame {frame} is {w}x{h}, expected {width}x{height}");
+                }
+                let pixels = ndarray::Array2::from_shape_vec((height, width), pixels)?;
+                dataset.write_slice(&pixels, (i, .., ..))?;
+                progress(i + 1);
+            }
+            drop(dataset);
+            file.close()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job<'a>(dest: &'a Path, metadata: &'a ImageMetadata) -> ExportJob<'a> {
+        ExportJob {
+            format: ExportFormat::Tiff,
+            dest,
+            source: Path::new("source.h5"),
+            metadata,
+            frames: 0..2,
+        }
+    }
+
+    #[test]
+    fn failed_export_leaves_dest_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("out.tif");
+        std::fs::write(&dest, b"previous export").unwrap();
+        let metadata = ImageMetadata::default();
+        let read_frame = |frame| match frame {
+            0 => Ok((vec![1u16; 4], 2, 2)),
+            _ => anyhow::bail!("unreadable frame"),
+        };
+        assert!(export_frames(&job(&dest, &metadata), read_frame, |_| {}).is_err());
+        assert_eq!(std::fs::read(&dest).unwrap(), b"previous export");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn finished_export_replaces_dest() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("out.tif");
+        std::fs::write(&dest, b"previous export").unwrap();
+        let metadata = ImageMetadata::default();
+        let read_frame = |_| Ok((vec![1u16; 4], 2, 2));
+        export_frames(&job(&dest, &metadata), read_frame, |_| {}).unwrap();
+        assert_eq!(&std::fs::read(&dest).unwrap()[..2], b"II");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
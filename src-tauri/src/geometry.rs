@@ -0,0 +1,71 @@
+//! Detector geometry kernels that depend only on metadata, not on any frame.
+
+use crate::readers::ImageMetadata;
+
+/// `h·c` in keV·Å, for converting between beam energy and wavelength.
+pub(crate) const HC_KEV_ANGSTROM: f64 = 12.398_419_843;
+
+/// A per-pixel map sampled on a grid coarser than the detector.
+pub struct PixelMap {
+    pub values: Vec<f32>,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Resolution (d-spacing, Å) at the centre of each `downsample`×`downsample`
+/// block of the detector, for a flat panel normal to the beam.
+///
+/// The beam centre itself has no finite resolution and maps to `+inf`.
+/// Returns `None` if the beam energy or the distance is unknown; readers
+/// without a distance report 0.
+pub fn resolution_map(metadata: &ImageMetadata, downsample: usize) -> Option<PixelMap> {
+    let wavelength = HC_KEV_ANGSTROM / metadata.beam_energy_kev?;
+    if metadata.panel_distance_mm <= 0.0 {
+        return None;
+    }
+    let factor = downsample.max(1);
+    let [panel_w, panel_h] = metadata.panel_size_fast_slow;
+    let width = (panel_w as usize).div_ceil(factor);
+    let height = (panel_h as usize).div_ceil(factor);
+    let [beam_x, beam_y] = metadata.beam_center;
+    let distance = metadata.panel_distance_mm;
+    let pixel_size = metadata.pixel_size;
+
+    let mut values = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let dy = ((y as f64 + 0.5) * factor as f64 - beam_y) * pixel_size;
+        for x in 0..width {
+            let dx = ((x as f64 + 0.5) * factor as f64 - beam_x) * pixel_size;
+            let two_theta = dx.hypot(dy).atan2(distance);
+            let d = wavelength / (2.0 * (two_theta / 2.0).sin());
+            values.push(d as f32);
+        }
+    }
+
+    Some(PixelMap {
+        values,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn needs_a_distance_and_an_energy() {
+        let mut metadata = ImageMetadata {
+            panel_size_fast_slow: [8, 8],
+            pixel_size: 0.075,
+            ..Default::default()
+        };
+        metadata.beam_energy_kev = Some(12.4);
+        assert!(resolution_map(&metadata, 1).is_none());
+        metadata.panel_distance_mm = 100.0;
+        let map = resolution_map(&metadata, 4).unwrap();
+        assert_eq!((map.width, map.height), (2, 2));
+        metadata.beam_energy_kev = None;
+        assert!(resolution_map(&metadata, 1).is_none());
+    }
+}
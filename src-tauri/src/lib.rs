@@ -1,16 +1,151 @@
+mod cache;
 mod commands;
+mod disk_cache;
+mod export;
+mod geometry;
 mod readers;
+mod recent;
+mod render;
 mod server;
+mod stats;
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tauri::Manager;
 use tokio::sync::Mutex;
 
 pub type SharedReader = Arc<Mutex<Option<Box<dyn readers::Reader>>>>;
 
+/// Incremented every time the active reader is replaced, so anything cached
+/// for a file can tell it is stale. Only changed while holding the reader
+/// lock, so a value read under the same lock always matches the reader.
+pub type ReaderGeneration = Arc<AtomicU64>;
+
+/// Incremented by the `cancel_analysis` command. Long-running aggregate work
+/// takes a [`CancelToken`] when it starts and stops early once this changes.
+pub type AnalysisEpoch = Arc<AtomicU64>;
+
+/// Snapshot of the [`AnalysisEpoch`] taken when a task starts.
+#[derive(Clone)]
+pub struct CancelToken {
+    epoch: AnalysisEpoch,
+    start: u64,
+}
+
+impl CancelToken {
+    pub fn new(epoch: &AnalysisEpoch) -> Self {
+        Self {
+            epoch: epoch.clone(),
+            start: epoch.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Whether `cancel_analysis` has been called since the token was taken.
+    pub fn is_cancelled(&self) -> bool {
+        self.epoch.load(Ordering::SeqCst) != self.start
+    }
+}
+
+/// Environment variable giving the idle timeout in minutes. When set to a
+/// positive number, the active file is closed after that long without any
+/// HTTP request.
+pub const IDLE_TIMEOUT_ENV: &str = "DIFFRANT_IDLE_TIMEOUT_MINS";
+
+/// Environment variable giving the size in bytes above which `/image`
+/// response bodies are streamed rather than buffered; defaults to
+/// [`server::DEFAULT_STREAM_THRESHOLD`].
+pub const STREAM_THRESHOLD_ENV: &str = "DIFFRANT_STREAM_THRESHOLD_BYTES";
+
+/// Environment variable giving the memory budget in bytes of the server's
+/// decoded-frame cache; defaults to [`server::DEFAULT_FRAME_CACHE_BYTES`], and
+/// 0 disables the cache.
+pub const FRAME_CACHE_BYTES_ENV: &str = "DIFFRANT_FRAME_CACHE_BYTES";
+
+/// Environment variable giving how many frames on each side of a requested
+/// one the server prefetches into its frame cache; defaults to
+/// [`server::DEFAULT_PREFETCH_RADIUS`], and 0 disables prefetching.
+pub const PREFETCH_RADIUS_ENV: &str = "DIFFRANT_PREFETCH_RADIUS";
+
+/// Environment variable giving the size budget in bytes of the on-disk
+/// frame cache in the app cache directory, which keeps decoded frames across
+/// sessions. Unset or 0 disables it.
+pub const DISK_CACHE_BYTES_ENV: &str = "DIFFRANT_DISK_CACHE_BYTES";
+
+/// Environment variable giving the number of threads bulk analysis
+/// (`/project`, `/montage` and `/stats/stream`) decodes frames on; unset or
+/// 0 uses one per CPU.
+pub const ANALYSIS_THREADS_ENV: &str = "DIFFRANT_ANALYSIS_THREADS";
+
+/// Environment variable giving the niceness of those threads on Linux, from
+/// 0 (normal priority) to 19; defaults to
+/// [`server::DEFAULT_ANALYSIS_NICENESS`].
+pub const ANALYSIS_NICENESS_ENV: &str = "DIFFRANT_ANALYSIS_NICENESS";
+
+/// Environment variable giving the most frames an aggregate request
+/// (`/project`, `/stats/stream`, `/next_active` and the mean/variance
+/// images) reads before sampling; defaults to
+/// [`server::DEFAULT_MAX_SCAN_FRAMES`], and 0 disables the limit.
+pub const MAX_SCAN_FRAMES_ENV: &str = "DIFFRANT_MAX_SCAN_FRAMES";
+
+/// Environment variable listing, comma-separated, detector descriptions (or
+/// parts of them, ignoring case) of detectors that store even and odd rows
+/// apart, whose frames are reordered on reading.
+pub const PLANAR_DETECTORS_ENV: &str = "DIFFRANT_PLANAR_DETECTORS";
+
+/// When the embedded server was last used, for the idle timeout.
+pub struct Activity {
+    started: Instant,
+    last_ms: AtomicU64,
+    closed_idle: AtomicBool,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            last_ms: AtomicU64::new(0),
+            closed_idle: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Activity {
+    /// Record a use of the reader now.
+    pub fn touch(&self) {
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last_ms.store(now, Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+
+    /// Whether the active file was closed by the idle timeout and not reopened.
+    pub fn closed_idle(&self) -> bool {
+        self.closed_idle.load(Ordering::SeqCst)
+    }
+
+    pub fn set_closed_idle(&self, closed: bool) {
+        self.closed_idle.store(closed, Ordering::SeqCst);
+    }
+}
+
+pub type SharedActivity = Arc<Activity>;
+
 #[derive(Clone)]
 pub struct AppState {
     pub reader: SharedReader,
+    pub generation: ReaderGeneration,
+    pub analysis_epoch: AnalysisEpoch,
+    /// Path and open options of the active file, for saving sessions.
+    pub active_file: Arc<Mutex<Option<commands::ActiveFile>>>,
+    pub activity: SharedActivity,
+    /// Recently opened files, for the frontend's menu.
+    pub recent_files: Arc<Mutex<recent::RecentFiles>>,
+    /// The embedded server's state, for dropping its caches on close.
+    pub server: server::ServerState,
     pub server_port: u16,
 }
 
@@ -43,6 +178,49 @@ pub fn run() {
             }
 
             let reader: SharedReader = Arc::new(Mutex::new(None));
+            let generation: ReaderGeneration = Arc::new(AtomicU64::new(0));
+            let analysis_epoch: AnalysisEpoch = Arc::new(AtomicU64::new(0));
+            let activity: SharedActivity = Arc::new(Activity::default());
+            let idle_timeout = std::env::var(IDLE_TIMEOUT_ENV)
+                .ok()
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|&mins| mins > 0.0)
+                .map(|mins| Duration::from_secs_f64(mins * 60.0));
+            if let Some(timeout) = idle_timeout {
+                tracing::info!("Closing files after {}s idle", timeout.as_secs());
+            }
+            let env_usize = |name: &str| {
+                std::env::var(name)
+                    .ok()
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+            };
+            let defaults = server::ServerConfig::default();
+            let server_config = server::ServerConfig {
+                stream_threshold: env_usize(STREAM_THRESHOLD_ENV)
+                    .unwrap_or(defaults.stream_threshold),
+                frame_cache_bytes: env_usize(FRAME_CACHE_BYTES_ENV)
+                    .unwrap_or(defaults.frame_cache_bytes),
+                prefetch_radius: env_usize(PREFETCH_RADIUS_ENV).unwrap_or(defaults.prefetch_radius),
+                analysis_threads: env_usize(ANALYSIS_THREADS_ENV)
+                    .unwrap_or(defaults.analysis_threads),
+                analysis_niceness: std::env::var(ANALYSIS_NICENESS_ENV)
+                    .ok()
+                    .and_then(|v| v.trim().parse::<i32>().ok())
+                    .map(|niceness| niceness.clamp(0, 19))
+                    .unwrap_or(defaults.analysis_niceness),
+                max_scan_frames: env_usize(MAX_SCAN_FRAMES_ENV).unwrap_or(defaults.max_scan_frames),
+            };
+            let disk_cache = match (env_usize(DISK_CACHE_BYTES_ENV), app.path().app_cache_dir()) {
+                (Some(budget), Ok(cache_dir)) if budget > 0 => {
+                    let dir = cache_dir.join("frames");
+                    tracing::info!(
+                        "Caching up to {budget} bytes of frames in {}",
+                        dir.display()
+                    );
+                    Some(disk_cache::DiskCache::new(dir, budget as u64))
+                }
+                _ => None,
+            };
 
             // Bind on an OS-assigned port before starting the async server.
             // Must be set to non-blocking before handing to tokio.
@@ -51,8 +229,20 @@ pub fn run() {
             std_listener.set_nonblocking(true)?;
             tracing::info!("Starting embedded HTTP server on port {port}");
 
-            let router = server::create_router(reader.clone());
+            let server_state = server::ServerState::new(
+                reader.clone(),
+                generation.clone(),
+                analysis_epoch.clone(),
+                activity.clone(),
+                server_config,
+                disk_cache,
+            );
+            let router_state = server_state.clone();
             tauri::async_runtime::spawn(async move {
+                if let Some(timeout) = idle_timeout {
+                    tokio::spawn(server::close_when_idle(router_state.clone(), timeout));
+                }
+                let router = server::create_router(router_state);
                 let listener = tokio::net::TcpListener::from_std(std_listener)
                     .expect("failed to convert TcpListener");
                 axum::serve(listener, router)
@@ -60,8 +250,17 @@ pub fn run() {
                     .expect("embedded server error");
             });
 
+            let config_dir = app.path().app_config_dir().ok();
+            let recent_files = recent::RecentFiles::load(config_dir.as_deref());
+
             let state = AppState {
                 reader,
+                generation,
+                analysis_epoch,
+                active_file: Arc::new(Mutex::new(None)),
+                recent_files: Arc::new(Mutex::new(recent_files)),
+                activity,
+                server: server_state,
                 server_port: port,
             };
             app.manage(state);
@@ -70,6 +269,17 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::get_server_port,
             commands::open_file,
+            commands::open_file_series,
+            commands::close_file,
+            commands::get_recent_files,
+            commands::add_recent_file,
+            commands::inspect_file,
+            commands::cancel_analysis,
+            commands::save_session,
+            commands::load_session,
+            commands::export_frames,
+            commands::max_pixel,
+            commands::read_frame,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
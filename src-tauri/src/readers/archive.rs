@@ -0,0 +1,794 @@
+//! Frames shipped inside a `.zip` or `.tar` archive.
+//!
+//! Series of single-image frames are often sent as one archive of CBF or
+//! TIFF files. [`ArchiveReader::open`] takes either
+//! `archive.zip!inner/frame_0001.cbf`, for one entry, or the archive's own
+//! path, which opens every entry in a format [`super::open`] reads as one
+//! [`SeriesReader`], sorted by name.
+//! HDF5 entries and sibling masks (see [`super::mask`]) are left out of that
+//! series, since a NeXus file's frames usually live in the files it links
+//! to; they can still be opened by name.
+//!
+//! The readers read files by path, so entries are extracted to a temporary
+//! directory that is deleted when the reader is dropped. For one entry that
+//! is the entry and those next to it sharing its stem, such as a `.raw`
+//! file's JSON sidecar or an Eiger master file's `_data_000001.h5`; for a
+//! whole archive it is every file in it. Zip entries may be stored or
+//! deflated, and zip64 archives are read; tar archives may be ustar, GNU or
+//! pax, but not compressed.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::CrcReader;
+use flate2::read::DeflateDecoder;
+use tempfile::TempDir;
+use tracing::{debug, warn};
+
+use super::series::SeriesReader;
+use super::{FrameMetadata, GainMap, ImageMetadata, OpenOptions, RawChunk, Reader};
+
+/// Extensions of the archives read.
+pub const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "tar"];
+
+/// Extensions of entries left out when a whole archive is opened.
+const HDF5_EXTENSIONS: &[&str] = &["nxs", "h5", "hdf5", "nx5"];
+
+/// Longest GNU long-name or pax header read, well beyond any real path.
+const MAX_NAME_HEADER_BYTES: u64 = 1 << 20;
+
+const ZIP_LOCAL_HEADER: [u8; 4] = *b"PK\x03\x04";
+const ZIP_CENTRAL_HEADER: [u8; 4] = *b"PK\x01\x02";
+const ZIP_END: [u8; 4] = *b"PK\x05\x06";
+const ZIP64_END: [u8; 4] = *b"PK\x06\x06";
+const ZIP64_END_LOCATOR: [u8; 4] = *b"PK\x06\x07";
+
+/// A reader over entries extracted from an archive.
+pub struct ArchiveReader {
+    inner: Box<dyn Reader>,
+    /// What was opened, e.g. `data.zip!frame_0001.cbf`.
+    path: PathBuf,
+    /// Where the entries were extracted; dropped after `inner` has closed
+    /// them, deleting them.
+    _extracted: Option<TempDir>,
+}
+
+impl ArchiveReader {
+    /// Open the entry named after the `!` in `path`, or every frame in the
+    /// archive if `path` is the archive itself. Fails if the archive can't
+    /// be read, the entry doesn't exist, or the frames don't form a series.
+    pub fn open(path: &Path, options: &OpenOptions) -> Result<Self> {
+        let (archive, member) = match split_member(path) {
+            Some((archive, member)) => (archive, Some(member)),
+            None => (path, None),
+        };
+        let mut file = File::open(archive)?;
+        let entries = list_entries(&mut file, archive)
+            .with_context(|| format!("Cannot read archive {}", archive.display()))?;
+        let dir = tempfile::Builder::new()
+            .prefix("diffrant-archive-")
+            .tempdir()?;
+
+        let inner: Box<dyn Reader> = match member {
+            Some(member) => {
+                let entry = entries
+                    .iter()
+                    .find(|e| e.name == member)
+                    .with_context(|| format!("{} has no entry {member}", archive.display()))?;
+                let file_start = member.rfind('/').map_or(0, |i| i + 1);
+                let stem = match member[file_start..].rfind('.') {
+                    Some(i) => &member[..file_start + i],
+                    None => member,
+                };
+                let siblings = entries.iter().filter(|e| {
+                    e.name == member
+                        || (e.name.starts_with(stem)
+                            && matches!(e.name.as_bytes().get(stem.len()), Some(b'.' | b'_')))
+                });
+                extract_all(&mut file, siblings, dir.path())?;
+                let Some(extracted) = extract_path(dir.path(), &entry.name) else {
+                    anyhow::bail!(
+                        "Archive entry {member} would be extracted outside its directory"
+                    );
+                };
+                super::open_format(&extracted, options)?
+            }
+            None => {
+                extract_all(&mut file, entries.iter(), dir.path())?;
+                let mut frames: Vec<&Entry> =
+                    entries.iter().filter(|e| is_frame(&e.name)).collect();
+                if frames.is_empty() {
+                    anyhow::bail!("{} has no frames in a supported format", archive.display());
+                }
+                frames.sort_by(|a, b| a.name.cmp(&b.name));
+                let readers = frames
+                    .iter()
+                    .filter_map(|e| Some((e, extract_path(dir.path(), &e.name)?)))
+                    .map(|(entry, extracted)| {
+                        let reader = super::open_format(&extracted, options)?;
+                        Ok(Box::new(Self {
+                            inner: reader,
+                            path: member_path(archive, &entry.name),
+                            _extracted: None,
+                        }) as Box<dyn Reader>)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Box::new(SeriesReader::new(readers)?)
+            }
+        };
+        debug!(
+            entries = entries.len(),
+            "archive: opened {} via {}",
+            path.display(),
+            dir.path().display()
+        );
+        Ok(Self {
+            inner,
+            path: path.to_path_buf(),
+            _extracted: Some(dir),
+        })
+    }
+}
+
+impl Reader for ArchiveReader {
+    fn format_name(&self) -> &'static str {
+        self.inner.format_name()
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn dataset_path(&self) -> Option<&str> {
+        self.inner.dataset_path()
+    }
+
+    /// An entry is its own source, as `archive.zip!entry`; a whole archive's
+    /// frames are those of its entries.
+    fn frame_source(&self, frame: usize) -> Result<(PathBuf, usize)> {
+        if split_member(&self.path).is_some() {
+            Ok((self.path.clone(), frame))
+        } else {
+            self.inner.frame_source(frame)
+        }
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        self.inner.metadata()
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        self.inner.frame_count()
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        self.inner.read_frame(frame)
+    }
+
+    fn read_region(
+        &self,
+        frame: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> Result<Vec<u16>> {
+        self.inner.read_region(frame, x, y, w, h)
+    }
+
+    fn read_frame_into(&self, frame: usize, buf: &mut Vec<u16>) -> Result<(usize, usize)> {
+        self.inner.read_frame_into(frame, buf)
+    }
+
+    fn read_frame_f32(&self, frame: usize) -> Result<(Vec<f32>, usize, usize)> {
+        self.inner.read_frame_f32(frame)
+    }
+
+    fn frames_per_chunk(&self) -> usize {
+        self.inner.frames_per_chunk()
+    }
+
+    fn read_frames(&self, frames: Range<usize>) -> Result<(Vec<u16>, usize, usize)> {
+        self.inner.read_frames(frames)
+    }
+
+    fn read_raw_chunk(&self, frame: usize) -> Result<Option<RawChunk>> {
+        self.inner.read_raw_chunk(frame)
+    }
+
+    fn mask(&self) -> Result<Option<(Vec<u8>, usize, usize)>> {
+        self.inner.mask()
+    }
+
+    fn frame_angles(&self) -> Result<Option<Vec<f64>>> {
+        self.inner.frame_angles()
+    }
+
+    fn sources_available(&self) -> bool {
+        self.inner.sources_available()
+    }
+
+    fn frame_metadata(&self, frame: usize) -> Result<FrameMetadata> {
+        self.inner.frame_metadata(frame)
+    }
+
+    fn frame_times(&self) -> Result<Option<Vec<f64>>> {
+        self.inner.frame_times()
+    }
+
+    fn gain_map(&self) -> Result<Option<GainMap>> {
+        self.inner.gain_map()
+    }
+
+    fn applied_transforms(&self) -> Vec<&'static str> {
+        self.inner.applied_transforms()
+    }
+}
+
+/// `path` split into the archive and the entry named after its `!`, if it
+/// names an entry of a zip or tar file.
+pub fn split_member(path: &Path) -> Option<(&Path, &str)> {
+    let text = path.to_str()?;
+    text.match_indices('!').find_map(|(i, _)| {
+        let (archive, member) = (Path::new(&text[..i]), &text[i + 1..]);
+        (is_archive(archive) && !member.is_empty()).then_some((archive, member))
+    })
+}
+
+/// Whether `path` has the extension of an archive this module reads.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| {
+            ARCHIVE_EXTENSIONS
+                .iter()
+                .any(|a| ext.eq_ignore_ascii_case(a))
+        })
+}
+
+/// The file on disk holding `path`: its archive if it names an entry of
+/// one, otherwise `path` itself.
+pub fn file_on_disk(path: &Path) -> &Path {
+    split_member(path).map_or(path, |(archive, _)| archive)
+}
+
+fn member_path(archive: &Path, name: &str) -> PathBuf {
+    PathBuf::from(format!("{}!{name}", archive.display()))
+}
+
+/// Whether the entry `name` is opened as part of a whole-archive series.
+fn is_frame(name: &str) -> bool {
+    let ext = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    super::SUPPORTED_EXTENSIONS.contains(&ext.as_str())
+        && !HDF5_EXTENSIONS.contains(&ext.as_str())
+        && !ARCHIVE_EXTENSIONS.contains(&ext.as_str())
+        && !super::mask::SIBLING_SUFFIXES
+            .iter()
+            .any(|suffix| name.to_lowercase().ends_with(suffix))
+}
+
+/// A regular file in an archive, and where its bytes are.
+#[derive(Debug)]
+struct Entry {
+    /// Path within the archive, `/`-separated.
+    name: String,
+    /// Offset of the stored bytes from the start of the archive.
+    offset: u64,
+    /// Bytes stored, compressed or not.
+    stored_size: u64,
+    size: u64,
+    compression: Compression,
+    /// CRC-32 of the uncompressed bytes, for zip entries.
+    crc32: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Stored,
+    Deflate,
+    /// A zip compression method other than those, by number.
+    Other(u16),
+}
+
+/// The regular files in the archive `file`, read as a zip or tar by
+/// `path`'s extension.
+fn list_entries(file: &mut File, path: &Path) -> Result<Vec<Entry>> {
+    let is_zip = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if is_zip {
+        zip_entries(file)
+    } else {
+        tar_entries(file)
+    }
+}
+
+/// Where the entry `name` is extracted under `dir`; `None` for names that
+/// would land outside it, such as `../x` or `/etc/x`.
+fn extract_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    let relative = Path::new(name);
+    relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        .then(|| dir.join(relative))
+}
+
+/// Extract `entries` of the archive `file` under `dir`, skipping those
+/// whose names would land outside it.
+fn extract_all<'a>(
+    file: &mut File,
+    entries: impl Iterator<Item = &'a Entry>,
+    dir: &Path,
+) -> Result<()> {
+    for entry in entries {
+        let Some(dest) = extract_path(dir, &entry.name) else {
+            warn!("archive: skipping entry {} outside the archive", entry.name);
+            continue;
+        };
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        extract(file, entry, &dest).with_context(|| format!("Cannot extract {}", entry.name))?;
+    }
+    Ok(())
+}
+
+/// Decompress `entry` into a new file at `dest`, checking its size and,
+/// for zip entries, its CRC.
+fn extract(file: &mut File, entry: &Entry, dest: &Path) -> Result<()> {
+    file.seek(SeekFrom::Start(entry.offset))?;
+    let stored = BufReader::new(&mut *file).take(entry.stored_size);
+    let decoded: Box<dyn Read + '_> = match entry.compression {
+        Compression::Stored => Box::new(stored),
+        Compression::Deflate => Box::new(DeflateDecoder::new(stored)),
+        Compression::Other(method) => {
+            anyhow::bail!(
+                "zip compression method {method} is not supported, only stored and deflated entries"
+            )
+        }
+    };
+    // Stop one byte past the declared size, so an entry that inflates to
+    // more than it claims fails here rather than filling the disk.
+    let mut decoded = CrcReader::new(decoded.take(entry.size.saturating_add(1)));
+    let written = std::io::copy(&mut decoded, &mut File::create(dest)?)?;
+    if written != entry.size {
+        anyhow::bail!("expected {} bytes but got {written}", entry.size);
+    }
+    if let Some(crc32) = entry.crc32 {
+        if decoded.crc().sum() != crc32 {
+            anyhow::bail!("CRC mismatch; the archive is corrupt");
+        }
+    }
+    Ok(())
+}
+
+fn read_at(file: &mut File, offset: u64, buf: &mut [u8]) -> Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)?;
+    Ok(())
+}
+
+fn le16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// The files listed in a zip's central directory, found from the end of
+/// central directory record at the end of the file.
+fn zip_entries(file: &mut File) -> Result<Vec<Entry>> {
+    let len = file.metadata()?.len();
+    // The end record is 22 bytes, followed by a comment of up to 64 KiB.
+    let tail_len = len.min(22 + u64::from(u16::MAX));
+    let mut tail = vec![0; tail_len as usize];
+    read_at(file, len - tail_len, &mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| tail[i..i + 4] == ZIP_END)
+        .context("not a zip archive")?;
+    let record = &tail[end..end + 22];
+    let mut count = u64::from(le16(record, 10));
+    let mut directory_size = u64::from(le32(record, 12));
+    let mut directory_offset = u64::from(le32(record, 16));
+    if count == 0xFFFF || directory_size == 0xFFFF_FFFF || directory_offset == 0xFFFF_FFFF {
+        let locator = end
+            .checked_sub(20)
+            .map(|at| &tail[at..at + 20])
+            .filter(|locator| locator[..4] == ZIP64_END_LOCATOR)
+            .context("zip64 end of central directory locator missing")?;
+        let mut record = [0; 56];
+        read_at(file, le64(locator, 8), &mut record)?;
+        if record[..4] != ZIP64_END {
+            anyhow::bail!("zip64 end of central directory record missing");
+        }
+        count = le64(&record, 32);
+        directory_size = le64(&record, 40);
+        directory_offset = le64(&record, 48);
+    }
+    if directory_offset.saturating_add(directory_size) > len {
+        anyhow::bail!("central directory extends past the end of the file");
+    }
+    let mut directory = vec![0; directory_size as usize];
+    read_at(file, directory_offset, &mut directory)?;
+
+    let mut entries = Vec::new();
+    let mut at = 0;
+    for _ in 0..count {
+        let header = directory
+            .get(at..at + 46)
+            .filter(|h| h[..4] == ZIP_CENTRAL_HEADER)
+            .context("corrupt central directory")?;
+        let flags = le16(header, 8);
+        let method = le16(header, 10);
+        let crc32 = le32(header, 16);
+        let mut stored_size = u64::from(le32(header, 20));
+        let mut size = u64::from(le32(header, 24));
+        let name_len = usize::from(le16(header, 28));
+        let extra_len = usize::from(le16(header, 30));
+        let comment_len = usize::from(le16(header, 32));
+        let mut local_header = u64::from(le32(header, 42));
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .context("corrupt central directory")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        let extra = directory
+            .get(at + 46 + name_len..at + 46 + name_len + extra_len)
+            .context("corrupt central directory")?;
+        at += 46 + name_len + extra_len + comment_len;
+
+        // Zip64 sizes and offset replace, in order, those saturated above.
+        let mut zip64 = zip64_extra(extra).chunks_exact(8).map(|b| le64(b, 0));
+        for value in [&mut size, &mut stored_size, &mut local_header] {
+            if *value == 0xFFFF_FFFF {
+                *value = zip64.next().context("zip64 extra field missing")?;
+            }
+        }
+        if name.ends_with('/') {
+            continue;
+        }
+        if flags & 1 != 0 {
+            anyhow::bail!("entry {name} is encrypted");
+        }
+        let mut local = [0; 30];
+        read_at(file, local_header, &mut local)?;
+        if local[..4] != ZIP_LOCAL_HEADER {
+            anyhow::bail!("corrupt local header for entry {name}");
+        }
+        let offset = local_header + 30 + u64::from(le16(&local, 26)) + u64::from(le16(&local, 28));
+        entries.push(Entry {
+            name,
+            offset,
+            stored_size,
+            size,
+            compression: match method {
+                0 => Compression::Stored,
+                8 => Compression::Deflate,
+                method => Compression::Other(method),
+            },
+            crc32: Some(crc32),
+        });
+    }
+    Ok(entries)
+}
+
+/// The body of the zip64 extended information field in a central directory
+/// entry's extra data, or nothing if there isn't one.
+fn zip64_extra(mut extra: &[u8]) -> &[u8] {
+    while extra.len() >= 4 {
+        let (id, len) = (le16(extra, 0), usize::from(le16(extra, 2)));
+        let Some(body) = extra.get(4..4 + len) else {
+            break;
+        };
+        if id == 1 {
+            return body;
+        }
+        extra = &extra[4 + len..];
+    }
+    &[]
+}
+
+/// The regular files in a tar archive: a 512-byte header per entry, then
+/// its bytes padded to 512, until a zero block or the end of the file.
+fn tar_entries(file: &mut File) -> Result<Vec<Entry>> {
+    let len = file.metadata()?.len();
+    let mut entries = Vec::new();
+    // Name given by a preceding GNU long-name or pax header.
+    let mut long_name: Option<String> = None;
+    let mut header = [0; 512];
+    let mut offset = 0;
+    while offset + 512 <= len {
+        read_at(file, offset, &mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let checksum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    u64::from(b)
+                }
+            })
+            .sum();
+        if tar_number(&header[148..156]) != Some(checksum) {
+            anyhow::bail!("not a tar archive, or corrupt header at byte {offset}");
+        }
+        let size = tar_number(&header[124..136]).context("corrupt entry size")?;
+        let data = offset + 512;
+        match header[156] {
+            b'0' | b'\0' | b'7' => entries.push(Entry {
+                name: long_name.take().unwrap_or_else(|| ustar_name(&header)),
+                offset: data,
+                stored_size: size,
+                size,
+                compression: Compression::Stored,
+                crc32: None,
+            }),
+            flag @ (b'L' | b'x') => {
+                if size > MAX_NAME_HEADER_BYTES {
+                    anyhow::bail!("oversized name header at byte {offset}");
+                }
+                let mut body = vec![0; size as usize];
+                read_at(file, data, &mut body)?;
+                long_name = if flag == b'L' {
+                    Some(nul_terminated(&body))
+                } else {
+                    pax_path(&body)
+                };
+            }
+            // Directories, links, devices and global pax headers.
+            _ => long_name = None,
+        }
+        offset = data + size.div_ceil(512) * 512;
+    }
+    Ok(entries)
+}
+
+/// A tar header number: octal text, or big-endian base-256 if the high bit
+/// of its first byte is set.
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return Some(
+            field[1..]
+                .iter()
+                .fold(u64::from(field[0] & 0x7f), |n, &b| (n << 8) | u64::from(b)),
+        );
+    }
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+fn nul_terminated(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// A header's name, joined to its ustar prefix if it has one.
+fn ustar_name(header: &[u8; 512]) -> String {
+    let name = nul_terminated(&header[..100]);
+    let prefix = match &header[257..262] {
+        b"ustar" => nul_terminated(&header[345..500]),
+        _ => String::new(),
+    };
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// The `path` record of a pax extended header: records of the form
+/// `<length> <key>=<value>\n`, the length counting the whole record.
+fn pax_path(mut body: &[u8]) -> Option<String> {
+    while let Some(space) = body.iter().position(|&b| b == b' ') {
+        let len: usize = std::str::from_utf8(&body[..space]).ok()?.parse().ok()?;
+        let record = body.get(space + 1..len)?;
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return Some(String::from_utf8_lossy(path).into_owned());
+        }
+        body = &body[len..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DESCRIPTOR: &[u8] = br#"{"width": 3, "height": 2, "dtype": "u16"}"#;
+
+    fn frame_bytes(first: u16) -> Vec<u8> {
+        (first..first + 6).flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// A zip of `entries`, each deflated if its flag is set.
+    fn write_zip(path: &Path, entries: &[(&str, &[u8], bool)]) {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, data, deflate) in entries {
+            let stored = if deflate {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                data.to_vec()
+            };
+            let mut crc = flate2::Crc::new();
+            crc.update(data);
+            let method: u16 = if deflate { 8 } else { 0 };
+            let mut common = Vec::new();
+            common.extend_from_slice(&method.to_le_bytes());
+            common.extend_from_slice(&[0; 4]);
+            common.extend_from_slice(&crc.sum().to_le_bytes());
+            common.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            common.extend_from_slice(&[0; 2]);
+
+            directory.extend_from_slice(&ZIP_CENTRAL_HEADER);
+            directory.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            directory.extend_from_slice(&common);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&(out.len() as u32).to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+
+            out.extend_from_slice(&ZIP_LOCAL_HEADER);
+            out.extend_from_slice(&[20, 0, 0, 0]);
+            out.extend_from_slice(&common);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&stored);
+        }
+        let directory_offset = out.len() as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(&ZIP_END);
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&directory_offset.to_le_bytes());
+        out.extend_from_slice(&[0; 2]);
+        std::fs::write(path, out).unwrap();
+    }
+
+    /// A ustar archive of `entries`; names ending in `/` are directories.
+    fn write_tar(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut out = Vec::new();
+        for &(name, data) in entries {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(b"0000644");
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = if name.ends_with('/') { b'5' } else { b'0' };
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[263..265].copy_from_slice(b"00");
+            header[148..156].fill(b' ');
+            let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+            header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+            out.extend_from_slice(&header);
+            out.extend_from_slice(data);
+            out.resize(out.len().div_ceil(512) * 512, 0);
+        }
+        out.extend_from_slice(&[0; 1024]);
+        std::fs::write(path, out).unwrap();
+    }
+
+    #[test]
+    fn splits_archive_member_paths() {
+        let split = |p| split_member(Path::new(p));
+        assert_eq!(
+            split("/data/run!1.zip!frames/f_0001.cbf"),
+            Some((Path::new("/data/run!1.zip"), "frames/f_0001.cbf"))
+        );
+        assert_eq!(
+            split("/data/run.TAR!f.tif"),
+            Some((Path::new("/data/run.TAR"), "f.tif"))
+        );
+        assert_eq!(split("/data/run.zip!"), None);
+        assert_eq!(split("/data/wow!.cbf"), None);
+        assert_eq!(
+            file_on_disk(Path::new("/data/run.zip")),
+            Path::new("/data/run.zip")
+        );
+    }
+
+    #[test]
+    fn opens_a_zip_entry_with_its_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("frames.zip");
+        let frame = frame_bytes(10);
+        write_zip(
+            &archive,
+            &[
+                ("run/dump.raw", &frame, true),
+                ("run/dump.json", DESCRIPTOR, false),
+                ("run/other.json", b"not a descriptor", false),
+            ],
+        );
+        let path = PathBuf::from(format!("{}!run/dump.raw", archive.display()));
+        let reader = ArchiveReader::open(&path, &OpenOptions::default()).unwrap();
+        assert_eq!(reader.path(), path);
+        assert_eq!(reader.read_frame(0).unwrap(), ((10..16).collect(), 3, 2));
+
+        let extracted = reader.inner.path().to_path_buf();
+        assert!(extracted.exists());
+        assert!(!extracted.with_file_name("other.json").exists());
+        drop(reader);
+        assert!(!extracted.exists());
+    }
+
+    #[test]
+    fn opens_a_tar_as_a_series() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("frames.tar");
+        let (first, second) = (frame_bytes(0), frame_bytes(100));
+        write_tar(
+            &archive,
+            &[
+                ("run/", b""),
+                ("run/b.raw", &second),
+                ("run/b.json", DESCRIPTOR),
+                ("run/a.raw", &first),
+                ("run/a.json", DESCRIPTOR),
+            ],
+        );
+        let reader = ArchiveReader::open(&archive, &OpenOptions::default()).unwrap();
+        assert_eq!(reader.frame_count().unwrap(), 2);
+        assert_eq!(
+            reader.read_frame(0).unwrap().0,
+            (0..6).collect::<Vec<u16>>()
+        );
+        assert_eq!(
+            reader.read_frame(1).unwrap().0,
+            (100..106).collect::<Vec<u16>>()
+        );
+    }
+
+    #[test]
+    fn rejects_a_missing_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("frames.zip");
+        write_zip(&archive, &[("a.json", DESCRIPTOR, false)]);
+        let path = PathBuf::from(format!("{}!b.raw", archive.display()));
+        let e = ArchiveReader::open(&path, &OpenOptions::default())
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("has no entry b.raw"), "{e}");
+    }
+
+    #[test]
+    fn stops_extracting_past_the_declared_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("bomb.zip");
+        write_zip(&archive, &[("big.raw", &[0; 1 << 20], true)]);
+        let mut file = File::open(&archive).unwrap();
+        let mut entry = zip_entries(&mut file).unwrap().remove(0);
+        entry.size = 10;
+        let dest = dir.path().join("big.raw");
+        let e = extract(&mut file, &entry, &dest).unwrap_err();
+        assert_eq!(e.to_string(), "expected 10 bytes but got 11");
+        assert_eq!(std::fs::metadata(&dest).unwrap().len(), 11);
+    }
+}
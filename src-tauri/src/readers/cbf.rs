@@ -0,0 +1,376 @@
+//! Reader for single-image miniCBF files, as written by Pilatus and Eiger
+//! detectors.
+//!
+//! A miniCBF holds one frame: a text header whose `_array_data.header_contents`
+//! section carries the geometry as `# Key value` lines, then a MIME-style
+//! binary section holding the pixels with `x-CBF_BYTE_OFFSET` compression.
+//! `CbfReader` stores only the path and re-reads the file on each call.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use tracing::debug;
+
+use super::{FrameMetadata, ImageMetadata, Reader};
+use crate::geometry::HC_KEV_ANGSTROM;
+
+/// Marks the start of the binary data in a CBF file.
+const BINARY_START: [u8; 4] = [0x0c, 0x1a, 0x04, 0xd5];
+
+pub struct CbfReader {
+    path: PathBuf,
+}
+
+impl CbfReader {
+    /// Validate the file parses as a byte-offset miniCBF, then return a reader
+    /// for it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let reader = Self {
+            path: path.to_path_buf(),
+        };
+        // Parse the header now to surface errors early.
+        let bytes = reader.read_file()?;
+        let (header, _) = split_header(&bytes)?;
+        BinaryHeader::parse(header)?;
+        Ok(reader)
+    }
+
+    fn read_file(&self) -> Result<Vec<u8>> {
+        std::fs::read(&self.path).map_err(|e| anyhow!("Cannot open {}: {e}", self.path.display()))
+    }
+
+    /// The text header, before the binary section.
+    fn read_header(&self) -> Result<String> {
+        let bytes = self.read_file()?;
+        let (header, _) = split_header(&bytes)?;
+        Ok(String::from_utf8_lossy(header).into_owned())
+    }
+}
+
+impl Reader for CbfReader {
+    fn format_name(&self) -> &'static str {
+        "cbf"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        let bytes = self.read_file()?;
+        let (header, _) = split_header(&bytes)?;
+        let binary = BinaryHeader::parse(header)?;
+        Ok(read_cbf_metadata(&String::from_utf8_lossy(header), &binary))
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        Ok(1)
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        if frame != 0 {
+            anyhow::bail!("Frame index {frame} out of range (CBF files hold 1 frame)");
+        }
+        let t0 = std::time::Instant::now();
+        let bytes = self.read_file()?;
+        let (header, data) = split_header(&bytes)?;
+        let binary = BinaryHeader::parse(header)?;
+        let n = binary.width.checked_mul(binary.height).ok_or_else(|| {
+            anyhow!(
+                "Invalid CBF header: {}x{} pixels are too many",
+                binary.width,
+                binary.height
+            )
+        })?;
+        let data = binary
+            .size
+            .map_or(data, |size| &data[..size.min(data.len())]);
+        // Every pixel takes at least one byte, so don't allocate for a header
+        // that describes more pixels than the file can hold.
+        if n > data.len() {
+            anyhow::bail!(
+                "CBF binary data is truncated: {n} pixels in {} bytes",
+                data.len()
+            );
+        }
+        let pixels = decode_byte_offset(data, n)?;
+        debug!(
+            elapsed_ms = t0.elapsed().as_millis(),
+            width = binary.width,
+            height = binary.height,
+            "cbf: frame read + decode"
+        );
+        Ok((pixels, binary.width, binary.height))
+    }
+
+    fn frame_angles(&self) -> Result<Option<Vec<f64>>> {
+        let header = self.read_header()?;
+        Ok(header_number(&header, "Start_angle").map(|start| vec![start]))
+    }
+
+    fn frame_metadata(&self, frame: usize) -> Result<FrameMetadata> {
+        if frame != 0 {
+            anyhow::bail!("Frame index {frame} out of range (CBF files hold 1 frame)");
+        }
+        let header = self.read_header()?;
+        let mut metadata = FrameMetadata {
+            count_time_s: header_number(&header, "Exposure_time"),
+            ..Default::default()
+        };
+        if let Some(width) = header_number(&header, "Angle_increment") {
+            metadata
+                .signals
+                .insert("angle_increment".to_string(), width);
+        }
+        Ok(metadata)
+    }
+
+    /// miniCBF files embed no mask, so only a sibling mask file is used.
+    fn mask(&self) -> Result<Option<(Vec<u8>, usize, usize)>> {
+        let bytes = self.read_file()?;
+        let (header, _) = split_header(&bytes)?;
+        let binary = BinaryHeader::parse(header)?;
+        let mask = super::mask::load_sibling_mask(&self.path, binary.width, binary.height)?;
+        Ok(mask.map(|mask| (mask, binary.width, binary.height)))
+    }
+}
+
+// ── Private helpers ──────────────────────────────────────────────────────────
+
+/// The MIME-style header of the binary section.
+struct BinaryHeader {
+    width: usize,
+    height: usize,
+    /// Compressed size in bytes, if given.
+    size: Option<usize>,
+    /// `X-Binary-Element-Type`, e.g. `signed 32-bit integer`.
+    element_type: Option<String>,
+}
+
+impl BinaryHeader {
+    fn parse(header: &[u8]) -> Result<Self> {
+        let text = String::from_utf8_lossy(header);
+        let field = |name: &str| {
+            text.lines().find_map(|line| {
+                let (key, value) = line.trim().split_once(':')?;
+                key.trim()
+                    .eq_ignore_ascii_case(name)
+                    .then(|| value.trim().trim_matches(|c| c == '"' || c == ';').trim())
+            })
+        };
+        let dimension = |name: &str| -> Result<usize> {
+            field(name)
+                .with_context(|| format!("CBF header has no {name}"))?
+                .parse()
+                .with_context(|| format!("Invalid {name} in CBF header"))
+        };
+
+        // The conversions parameter may sit on the Content-Type line or the
+        // continuation line after it.
+        let conversions = text
+            .lines()
+            .find_map(|line| line.split_once("conversions=").map(|(_, v)| v))
+            .map(|v| {
+                v.trim()
+                    .trim_matches(|c| c == '"' || c == ';')
+                    .trim()
+                    .to_string()
+            });
+        match conversions.as_deref() {
+            Some(c) if c.eq_ignore_ascii_case("x-CBF_BYTE_OFFSET") => {}
+            Some(c) => anyhow::bail!(
+                "Unsupported CBF compression '{c}'; only x-CBF_BYTE_OFFSET is supported"
+            ),
+            None => anyhow::bail!(
+                "CBF header has no compression scheme; only x-CBF_BYTE_OFFSET is supported"
+            ),
+        }
+
+        Ok(Self {
+            width: dimension("X-Binary-Size-Fastest-Dimension")?,
+            height: dimension("X-Binary-Size-Second-Dimension")?,
+            size: field("X-Binary-Size").and_then(|v| v.parse().ok()),
+            element_type: field("X-Binary-Element-Type").map(str::to_string),
+        })
+    }
+}
+
+/// Short dtype name for a CBF element type, in the style of `source_dtype`
+/// for HDF5 files: `signed 32-bit integer` becomes `int32`.
+fn element_dtype(element_type: &str) -> String {
+    let bits = element_type
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|t| !t.is_empty())
+        .unwrap_or("");
+    if element_type.starts_with("unsigned") {
+        format!("uint{bits}")
+    } else if element_type.starts_with("signed") {
+        format!("int{bits}")
+    } else {
+        element_type.to_string()
+    }
+}
+
+/// Split a CBF file into the text before the binary marker and the data after.
+fn split_header(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    let start = bytes
+        .windows(BINARY_START.len())
+        .position(|w| w == BINARY_START)
+        .ok_or_else(|| anyhow!("No binary section found in CBF file"))?;
+    Ok((&bytes[..start], &bytes[start + BINARY_START.len()..]))
+}
+
+/// Decode `x-CBF_BYTE_OFFSET` data into `n` pixels.
+///
+/// Each value is a delta from the previous one, stored in 1 byte, or escaped
+/// to 2, 4 or 8 bytes (little-endian, signed). Negative values (gaps and bad
+/// pixels) are clamped to 0 and values above `u16::MAX` saturate.
+fn decode_byte_offset(data: &[u8], n: usize) -> Result<Vec<u16>> {
+    let truncated = || anyhow!("CBF binary data is truncated");
+    let mut pixels = Vec::with_capacity(n);
+    let mut pos = 0usize;
+    let mut value = 0i64;
+    while pixels.len() < n {
+        let delta8 = *data.get(pos).ok_or_else(truncated)? as i8;
+        pos += 1;
+        let delta = if delta8 != i8::MIN {
+            i64::from(delta8)
+        } else {
+            let bytes = data.get(pos..pos + 2).ok_or_else(truncated)?;
+            pos += 2;
+            let delta16 = i16::from_le_bytes([bytes[0], bytes[1]]);
+            if delta16 != i16::MIN {
+                i64::from(delta16)
+            } else {
+                let bytes = data.get(pos..pos + 4).ok_or_else(truncated)?;
+                pos += 4;
+                let delta32 = i32::from_le_bytes(bytes.try_into()?);
+                if delta32 != i32::MIN {
+                    i64::from(delta32)
+                } else {
+                    let bytes = data.get(pos..pos + 8).ok_or_else(truncated)?;
+                    pos += 8;
+                    i64::from_le_bytes(bytes.try_into()?)
+                }
+            }
+        };
+        value += delta;
+        pixels.push(value.clamp(0, i64::from(u16::MAX)) as u16);
+    }
+    Ok(pixels)
+}
+
+/// The value of a miniCBF `# Key value` header line.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    header.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix('#')?.trim();
+        let rest = rest.strip_prefix(key)?;
+        // Require a separator so `Exposure_time` doesn't match `Exposure_time_x`.
+        rest.starts_with([' ', '\t', ':', '='])
+            .then(|| rest.trim_start_matches([':', '=']).trim())
+    })
+}
+
+/// The first number in the value of header line `key`, ignoring units such
+/// as `deg.` or `m`.
+fn header_number(header: &str, key: &str) -> Option<f64> {
+    header_value(header, key).and_then(first_number)
+}
+
+fn first_number(s: &str) -> Option<f64> {
+    s.split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .find_map(|t| t.parse().ok())
+}
+
+/// Geometry from the miniCBF `# Key value` header lines. See the Pilatus
+/// header convention; lengths there are always in metres, angles in degrees.
+fn read_cbf_metadata(header: &str, binary: &BinaryHeader) -> ImageMetadata {
+    let value = |key: &str| header_value(header, key);
+    let numbers = |s: &str| -> Vec<f64> {
+        s.split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+            .filter_map(|t| t.parse().ok())
+            .collect()
+    };
+
+    let pixel_size = value("Pixel_size")
+        .and_then(first_number)
+        .map_or(0.172, |m| m * 1000.0);
+    let panel_distance_mm = value("Detector_distance")
+        .and_then(first_number)
+        .map_or(0.0, |m| m * 1000.0);
+    let beam_center = match value("Beam_xy").map(numbers).as_deref() {
+        Some(&[x, y, ..]) => [x, y],
+        _ => [binary.width as f64 / 2.0, binary.height as f64 / 2.0],
+    };
+    let beam_energy_kev = value("Wavelength")
+        .and_then(first_number)
+        .filter(|&a| a > 0.0)
+        .map(|angstrom| HC_KEV_ANGSTROM / angstrom);
+    let trusted_range_max = value("Count_cutoff")
+        .and_then(first_number)
+        .map_or((u16::MAX - 1) as f64, |cutoff| cutoff - 1.0);
+    let count_time_s = value("Exposure_time").and_then(first_number);
+    let frame_time_s = value("Exposure_period").and_then(first_number);
+    let duty_cycle = match (count_time_s, frame_time_s) {
+        (Some(count), Some(frame)) if frame > 0.0 => Some(count / frame),
+        _ => None,
+    };
+    // e.g. `X, CW`; the axis is named, but not as a lab-frame vector.
+    let scan_axis_name = value("Oscillation_axis")
+        .filter(|axis| !axis.is_empty())
+        .map(str::to_string);
+
+    ImageMetadata {
+        panel_distance_mm,
+        beam_center,
+        pixel_size,
+        panel_size_fast_slow: [binary.width as u64, binary.height as u64],
+        image_depth: 16,
+        trusted_range_max,
+        beam_energy_kev,
+        source_dtype: binary.element_type.as_deref().map(element_dtype),
+        count_time_s,
+        frame_time_s,
+        duty_cycle,
+        scan_axis_name,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_each_delta_width() {
+        let mut data = vec![5u8, 0xfb];
+        // A 2-byte delta of +1000.
+        data.push(0x80);
+        data.extend(1000i16.to_le_bytes());
+        // A 4-byte delta of +40000.
+        data.extend([0x80, 0x00, 0x80]);
+        data.extend(40000i32.to_le_bytes());
+        // An 8-byte delta of -41000.
+        data.extend([0x80, 0x00, 0x80, 0x00, 0x00, 0x00, 0x80]);
+        data.extend((-41000i64).to_le_bytes());
+        assert_eq!(
+            decode_byte_offset(&data, 5).unwrap(),
+            vec![5, 0, 1000, 41000, 0]
+        );
+    }
+
+    #[test]
+    fn clamps_negative_and_saturates_large_values() {
+        // -1 (a gap), then +70001 to 70000.
+        let mut data = vec![0xff, 0x80, 0x00, 0x80];
+        data.extend(70001i32.to_le_bytes());
+        assert_eq!(decode_byte_offset(&data, 2).unwrap(), vec![0, u16::MAX]);
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert!(decode_byte_offset(&[1, 2], 3).is_err());
+        // An escape with its 2-byte delta cut short.
+        assert!(decode_byte_offset(&[0x80, 0x01], 1).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! A user-supplied bad-pixel mask, merged into the detector's own.
+//!
+//! Users keep lists of bad pixels the detector firmware doesn't flag. The
+//! mask file is either a text list, one pixel per line as `x y` (or `x,y`)
+//! or as a row-major index, with `#` starting a comment; an HDF5 file with a
+//! 2D `pixel_mask`, read like the one an NXmx file embeds; or an image in any
+//! format [`super::open`] reads, whose first frame is nonzero at bad pixels.
+//! [`ExtraMaskReader`] unions it into [`Reader::mask`], so `/mask` and the
+//! statistics that skip masked pixels pick it up.
+//!
+//! Some setups instead ship the detector mask as a sibling of the data file,
+//! `data_mask.h5` or `data_mask.cbf` next to `data.h5`. Readers whose file
+//! has no embedded mask fall back to one with [`load_sibling_mask`].
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use tracing::info;
+
+use super::{FrameMetadata, GainMap, ImageMetadata, RawChunk, Reader};
+
+/// Extensions of mask files read as text lists rather than images.
+const TEXT_EXTENSIONS: &[&str] = &["txt", "lst", "csv"];
+
+/// Names of sibling mask files, appended to the data file's stem.
+pub(super) const SIBLING_SUFFIXES: &[&str] = &["_mask.h5", "_mask.cbf"];
+
+/// Wraps a reader, adding the pixels of a mask file to its mask.
+pub struct ExtraMaskReader {
+    inner: Box<dyn Reader>,
+    /// 0/1 per pixel, `width * height`.
+    extra: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+impl ExtraMaskReader {
+    /// Load the mask at `mask_path` for `inner`'s frames. Fails if it can't
+    /// be read, or doesn't match the frames' dimensions.
+    pub fn new(inner: Box<dyn Reader>, mask_path: &Path) -> Result<Self> {
+        let [width, height] = inner.metadata()?.panel_size_fast_slow;
+        let (width, height) = (width as usize, height as usize);
+        let extra = load_mask_file(mask_path, width, height)
+            .with_context(|| format!("Cannot load mask {}", mask_path.display()))?;
+        info!(
+            masked = extra.iter().filter(|&&m| m != 0).count(),
+            "mask: loaded extra mask {}",
+            mask_path.display()
+        );
+        Ok(Self {
+            inner,
+            extra,
+            width,
+            height,
+        })
+    }
+}
+
+impl Reader for ExtraMaskReader {
+    fn format_name(&self) -> &'static str {
+        self.inner.format_name()
+    }
+
+    fn path(&self) -> &Path {
+        self.inner.path()
+    }
+
+    fn dataset_path(&self) -> Option<&str> {
+        self.inner.dataset_path()
+    }
+
+    fn frame_source(&self, frame: usize) -> Result<(PathBuf, usize)> {
+        self.inner.frame_source(frame)
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        self.inner.metadata()
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        self.inner.frame_count()
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        self.inner.read_frame(frame)
+    }
+
+    fn read_region(
+        &self,
+        frame: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> Result<Vec<u16>> {
+        self.inner.read_region(frame, x, y, w, h)
+    }
+
+    fn read_frame_into(&self, frame: usize, buf: &mut Vec<u16>) -> Result<(usize, usize)> {
+        self.inner.read_frame_into(frame, buf)
+    }
+
+    fn read_frame_f32(&self, frame: usize) -> Result<(Vec<f32>, usize, usize)> {
+        self.inner.read_frame_f32(frame)
+    }
+
+    fn frames_per_chunk(&self) -> usize {
+        self.inner.frames_per_chunk()
+    }
+
+    fn read_frames(&self, frames: Range<usize>) -> Result<(Vec<u16>, usize, usize)> {
+        self.inner.read_frames(frames)
+    }
+
+    fn read_raw_chunk(&self, frame: usize) -> Result<Option<RawChunk>> {
+        self.inner.read_raw_chunk(frame)
+    }
+
+    fn mask(&self) -> Result<Option<(Vec<u8>, usize, usize)>> {
+        let mut mask = match self.inner.mask()? {
+            Some((mask, w, h)) if (w, h) == (self.width, self.height) => mask,
+            Some((_, w, h)) => anyhow::bail!(
+                "Detector mask is {w}x{h} but the extra mask is {}x{}",
+                self.width,
+                self.height
+            ),
+            None => vec![0; self.extra.len()],
+        };
+        for (m, &e) in mask.iter_mut().zip(&self.extra) {
+            *m |= e;
+        }
+        Ok(Some((mask, self.width, self.height)))
+    }
+
+    fn frame_angles(&self) -> Result<Option<Vec<f64>>> {
+        self.inner.frame_angles()
+    }
+
+    fn sources_available(&self) -> bool {
+        self.inner.sources_available()
+    }
+
+    fn frame_metadata(&self, frame: usize) -> Result<FrameMetadata> {
+        self.inner.frame_metadata(frame)
+    }
+
+    fn frame_times(&self) -> Result<Option<Vec<f64>>> {
+        self.inner.frame_times()
+    }
+
+    fn gain_map(&self) -> Result<Option<GainMap>> {
+        self.inner.gain_map()
+    }
+
+    fn applied_transforms(&self) -> Vec<&'static str> {
+        self.inner.applied_transforms()
+    }
+}
+
+/// Read a mask file as 0/1 per pixel of a `width` x `height` frame.
+pub fn load_mask_file(path: &Path, width: usize, height: usize) -> Result<Vec<u8>> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    if TEXT_EXTENSIONS.contains(&ext.as_str()) {
+        let text = std::fs::read_to_string(path)?;
+        return parse_pixel_list(&text, width, height);
+    }
+    if super::has_hdf5_signature(path) {
+        if let Some(mask) = super::nxs::read_mask_file(path, width, height)? {
+            return Ok(mask);
+        }
+    }
+    let reader = super::open(path, &Default::default())?;
+    let (pixels, w, h) = reader.read_frame(0)?;
+    if (w, h) != (width, height) {
+        anyhow::bail!("Mask image is {w}x{h} but frames are {width}x{height}");
+    }
+    Ok(pixels.iter().map(|&v| u8::from(v != 0)).collect())
+}
+
+/// The mask in a sibling of the data file at `path`, if there is one; see
+/// the module docs. An Eiger `data_master.h5` also finds `data_mask.h5`.
+/// Fails if the sibling exists but can't be read or doesn't match the
+/// `width` x `height` frames.
+pub fn load_sibling_mask(path: &Path, width: usize, height: usize) -> Result<Option<Vec<u8>>> {
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return Ok(None);
+    };
+    let stems = [Some(stem), stem.strip_suffix("_master")];
+    for stem in stems.into_iter().flatten() {
+        for suffix in SIBLING_SUFFIXES {
+            let candidate = path.with_file_name(format!("{stem}{suffix}"));
+            if !candidate.is_file() {
+                continue;
+            }
+            let mask = load_mask_file(&candidate, width, height)
+                .with_context(|| format!("Cannot load mask {}", candidate.display()))?;
+            info!("mask: using sibling mask {}", candidate.display());
+            return Ok(Some(mask));
+        }
+    }
+    Ok(None)
+}
+
+/// Parse a text list of bad pixels; see the module docs.
+fn parse_pixel_list(text: &str, width: usize, height: usize) -> Result<Vec<u8>> {
+    let mut mask = vec![0u8; width * height];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|f| !f.is_empty())
+            .map(str::parse::<usize>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("Line {}: {e}", n + 1))?;
+        let index = match fields[..] {
+            [index] if index < mask.len() => index,
+            [x, y] if x < width && y < height => y * width + x,
+            [_] | [_, _] => {
+                anyhow::bail!("Line {}: pixel outside the {width}x{height} frame", n + 1)
+            }
+            _ => anyhow::bail!("Line {}: expected `x y` or an index", n + 1),
+        };
+        mask[index] = 1;
+    }
+    Ok(mask)
+}
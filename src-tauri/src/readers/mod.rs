@@ -1,14 +1,26 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use anyhow::Result;
 use serde::Serialize;
 
+pub mod archive;
+pub mod cbf;
+pub mod mask;
+pub mod mrc;
 pub mod nxs;
+pub mod planar;
+pub mod raw;
+pub mod series;
+pub mod smv;
+pub mod tiff;
 
 /// Detector geometry and image properties returned by the metadata endpoint.
 /// Field names match the `ImageMetadata` interface expected by diffrant.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ImageMetadata {
-    /// Sample-to-detector distance in mm
+    /// Sample-to-detector distance in mm; frame 0's if it changes during
+    /// the scan (see [`FrameMetadata::distance_mm`])
     pub panel_distance_mm: f64,
     /// Beam centre in pixels [fast, slow] / [x, y]
     pub beam_center: [f64; 2],
@@ -20,9 +32,152 @@ pub struct ImageMetadata {
     pub image_depth: u32,
     /// Pixel value above which pixels are considered masked / untrusted
     pub trusted_range_max: f64,
-    /// Beam energy in keV (optional)
+    /// Pixel value below which pixels are considered masked / untrusted,
+    /// e.g. negative "no data" values
+    pub trusted_range_min: f64,
+    /// Beam energy in keV; frame 0's if it changes during the scan (see
+    /// [`FrameMetadata::incident_energy_kev`]) (optional)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub beam_energy_kev: Option<f64>,
+    /// On-disk pixel type before conversion to u16, e.g. `"uint32"` (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_dtype: Option<String>,
+    /// Free-text detector model, e.g. `"Dectris Eiger2 XE 16M"` (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detector_description: Option<String>,
+    /// Sensor material, e.g. `"Si"` or `"CdTe"` (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensor_material: Option<String>,
+    /// Sensor thickness in mm (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensor_thickness_mm: Option<f64>,
+    /// Frames per trigger, for multi-trigger / pump-probe data (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub images_per_trigger: Option<usize>,
+    /// Number of triggers; frames are grouped `images_per_trigger` at a time (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntriggers: Option<usize>,
+    /// Gain in counts per photon, for integrating detectors (optional;
+    /// photon-counting detectors record photons directly)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain: Option<f64>,
+    /// Exposure time per frame in seconds (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count_time_s: Option<f64>,
+    /// Frame period in seconds (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_time_s: Option<f64>,
+    /// `count_time_s / frame_time_s`; well below 1 means dead time between frames (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duty_cycle: Option<f64>,
+    /// Whether the stored data already has the flatfield applied (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flatfield_applied: Option<bool>,
+    /// Whether the stored data already has the pixel mask applied (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixel_mask_applied: Option<bool>,
+    /// Per-frame scan position `[x, y]` for raster / mapping scans (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_positions: Option<Vec<[f64; 2]>>,
+    /// Lab-frame geometry of each module of a tiled detector (optional; absent
+    /// for single-module detectors)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panels: Option<Vec<PanelGeometry>>,
+    /// Unit vector of the goniometer axis rotated during the scan, in the
+    /// NeXus lab frame and oriented so successive frames advance by a
+    /// right-handed rotation about it (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_axis: Option<[f64; 3]>,
+    /// Name of that axis, e.g. `"omega"` (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_axis_name: Option<String>,
+}
+
+/// Position and orientation of one detector module in the NeXus lab frame,
+/// resolved from its NXmx `depends_on` transformation chain.
+#[derive(Debug, Clone, Serialize)]
+pub struct PanelGeometry {
+    /// Name of the `NXdetector_module` group
+    pub name: String,
+    /// Position of the module's first pixel in mm
+    pub origin_mm: [f64; 3],
+    /// Unit vector along increasing fast (x) pixel index
+    pub fast_axis: [f64; 3],
+    /// Unit vector along increasing slow (y) pixel index
+    pub slow_axis: [f64; 3],
+    /// Pixel pitch in mm [fast, slow]
+    pub pixel_size_mm: [f64; 2],
+    /// Module's first pixel within the image [fast, slow] (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_origin_fast_slow: Option<[u64; 2]>,
+    /// Module size in pixels [fast, slow] (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_fast_slow: Option<[u64; 2]>,
+}
+
+/// Conditions recorded for one frame, for plotting alongside the image. All
+/// fields are optional; formats that record none return the default.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FrameMetadata {
+    /// Acquisition time of the frame in seconds, on the file's own clock
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_s: Option<f64>,
+    /// Exposure time of the frame in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count_time_s: Option<f64>,
+    /// Sample-to-detector distance of the frame in mm, for scans that move
+    /// the detector
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_mm: Option<f64>,
+    /// Beam energy of the frame in keV, for energy scans
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incident_energy_kev: Option<f64>,
+    /// Wavelength of the frame in Å, for energy scans
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wavelength_angstrom: Option<f64>,
+    /// Other per-frame values by name, e.g. ring current or I0
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub signals: BTreeMap<String, f64>,
+}
+
+/// Per-pixel, per-gain-stage calibration of a gain-switching detector such
+/// as Jungfrau, needed to turn raw ADUs into photons.
+#[derive(Debug, Clone)]
+pub struct GainMap {
+    /// Number of gain stages, e.g. 3 for Jungfrau's G0, G1 and G2.
+    pub stages: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Gain as stored, typically ADU per keV; one `width * height` row-major
+    /// image per stage.
+    pub gain: Vec<f32>,
+    /// Dark offset in ADU, laid out like `gain` (optional).
+    pub pedestal: Option<Vec<f32>>,
+}
+
+/// One stored chunk exactly as it is on disk, still compressed.
+#[derive(Debug, Clone, Serialize)]
+pub struct RawChunk {
+    /// Chunk dimensions in elements, slowest first, e.g. `[1, height, width]`.
+    pub chunk_shape: Vec<usize>,
+    /// Element type once decompressed, e.g. `"uint16"`.
+    pub dtype: String,
+    /// Filter pipeline in the order it was applied when writing; decode in
+    /// reverse.
+    pub filters: Vec<ChunkFilter>,
+    /// Bit `i` set means filter `i` was skipped for this chunk.
+    pub filter_mask: u32,
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+/// One HDF5 filter in a chunk's pipeline.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkFilter {
+    /// HDF5 filter ID, e.g. 1 = deflate, 32004 = LZ4, 32008 = bitshuffle.
+    pub id: i32,
+    /// Filter parameters (`cd_values`); only filled in for plugin filters.
+    pub params: Vec<u32>,
 }
 
 /// Abstraction over different file formats that can supply detector images.
@@ -30,6 +185,25 @@ pub struct ImageMetadata {
 /// All methods may perform blocking I/O and should be called from
 /// `tokio::task::spawn_blocking` or a similar blocking context.
 pub trait Reader: Send + Sync {
+    /// Short name of the file format, e.g. `"nxs"`.
+    fn format_name(&self) -> &'static str;
+
+    /// The file being read.
+    fn path(&self) -> &Path;
+
+    /// The dataset within the file holding the frames, for containers such
+    /// as HDF5; the first of them if the frames are split across several.
+    fn dataset_path(&self) -> Option<&str> {
+        None
+    }
+
+    /// The file `frame` is stored in, and its index there: readers giving
+    /// the same source for a frame read the same stored pixels, before any
+    /// [`Reader::applied_transforms`]. The default is `path()`.
+    fn frame_source(&self, frame: usize) -> Result<(PathBuf, usize)> {
+        Ok((self.path().to_path_buf(), frame))
+    }
+
     /// Detector metadata (same for all frames in a file).
     fn metadata(&self) -> Result<ImageMetadata>;
 
@@ -39,13 +213,258 @@ pub trait Reader: Send + Sync {
     /// Read one frame. Returns `(pixels, width, height)` where `pixels` is a
     /// row-major `Vec<u16>` of length `width * height`.
     fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)>;
+
+    /// Read the `w` x `h` pixels of `frame` whose top-left corner is
+    /// (`x`, `y`), row-major, failing if any of them lie outside the frame.
+    /// The default reads the whole frame and crops it.
+    fn read_region(
+        &self,
+        frame: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> Result<Vec<u16>> {
+        let (pixels, width, height) = self.read_frame(frame)?;
+        check_region(width, height, x, y, w, h)?;
+        Ok(crop(&pixels, width, x, y, w, h))
+    }
+
+    /// Read one frame into `buf`, replacing its contents, and return
+    /// `(width, height)`. For callers reading many frames in a row, so a
+    /// reader that can decode in place reuses `buf`'s allocation. The default
+    /// calls `read_frame`.
+    fn read_frame_into(&self, frame: usize, buf: &mut Vec<u16>) -> Result<(usize, usize)> {
+        let (pixels, width, height) = self.read_frame(frame)?;
+        *buf = pixels;
+        Ok((width, height))
+    }
+
+    /// Read one frame as f32, for float-valued (e.g. corrected) data that
+    /// `read_frame` would round and clip. The default widens `read_frame`.
+    fn read_frame_f32(&self, frame: usize) -> Result<(Vec<f32>, usize, usize)> {
+        let (pixels, width, height) = self.read_frame(frame)?;
+        Ok((pixels.into_iter().map(f32::from).collect(), width, height))
+    }
+
+    /// How many consecutive frames are stored together, e.g. in one chunk of
+    /// an HDF5 dataset, counted from frame 0. Reading such frames one at a
+    /// time decompresses their chunk again for each, so aggregate work reads
+    /// them with [`Reader::read_frames`] instead. 1 if frames are stored apart.
+    fn frames_per_chunk(&self) -> usize {
+        1
+    }
+
+    /// Read frames `frames` in one go, as `(pixels, width, height)` with the
+    /// frames' pixels back to back. The default reads each frame in turn.
+    fn read_frames(&self, frames: Range<usize>) -> Result<(Vec<u16>, usize, usize)> {
+        read_each_frame(self, frames)
+    }
+
+    /// The stored, still-compressed chunk holding exactly `frame`, for clients
+    /// that decompress themselves. `None` if the format or layout doesn't
+    /// store one frame per chunk.
+    fn read_raw_chunk(&self, _frame: usize) -> Result<Option<RawChunk>> {
+        Ok(None)
+    }
+
+    /// Per-pixel mask with its width and height: 1 where the pixel is a gap,
+    /// dead, hot or otherwise untrusted, 0 where it is good. `None` if the
+    /// file has no mask.
+    fn mask(&self) -> Result<Option<(Vec<u8>, usize, usize)>> {
+        Ok(None)
+    }
+
+    /// The goniometer rotation angle of each frame in degrees, for rotation
+    /// scans. `None` if the file records no rotation axis.
+    fn frame_angles(&self) -> Result<Option<Vec<f64>>> {
+        Ok(None)
+    }
+
+    /// Whether every file the frames are read from is present. `false` for
+    /// e.g. an HDF5 virtual dataset whose source files haven't been copied
+    /// yet: the frame count and metadata are known, but some frames can't
+    /// be read.
+    fn sources_available(&self) -> bool {
+        true
+    }
+
+    /// Timestamp, exposure and other values recorded for `frame`.
+    fn frame_metadata(&self, _frame: usize) -> Result<FrameMetadata> {
+        Ok(FrameMetadata::default())
+    }
+
+    /// Every frame's [`FrameMetadata::timestamp_s`], or `None` unless each
+    /// frame has one. The default asks `frame_metadata` frame by frame, so
+    /// readers that store the timestamps together should read them at once.
+    fn frame_times(&self) -> Result<Option<Vec<f64>>> {
+        // Files without timestamps are told apart by frame 0 alone.
+        match self.frame_metadata(0) {
+            Ok(first) if first.timestamp_s.is_some() => (0..self.frame_count()?)
+                .map(|frame| Ok(self.frame_metadata(frame)?.timestamp_s))
+                .collect(),
+            _ => Ok(None),
+        }
+    }
+
+    /// Gain-stage calibration, in the same orientation as the frames, for
+    /// detectors that store raw ADUs. `None` if the file has none.
+    fn gain_map(&self) -> Result<Option<GainMap>> {
+        Ok(None)
+    }
+
+    /// Reorderings applied to the stored pixels to produce frames in image
+    /// order, e.g. `"transpose"`, innermost first. Empty if frames are
+    /// served as stored.
+    fn applied_transforms(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+}
+
+/// Options for [`open`]. Options that don't apply to a format are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    /// HDF5 virtual file driver: `sec2`, `stdio` or `core`. `None` uses the
+    /// library default.
+    pub vfd: Option<String>,
+    /// HDF5 image dataset to read, overriding the search for one.
+    pub data_path: Option<String>,
+    /// A bad-pixel mask file to merge into the detector's mask; see
+    /// [`mask`] for the formats read.
+    pub extra_mask_path: Option<String>,
+    /// Whether the detector stores its rows planar and they need reordering;
+    /// see [`planar`]. `None` decides from `planar_detectors`.
+    pub planar_rows: Option<bool>,
+    /// Detector descriptions, or parts of them, of detectors that store rows
+    /// planar.
+    pub planar_detectors: Vec<String>,
+}
+
+/// Pixel size, in mm, used when a file doesn't record one: the 75 µm pixels
+/// of an Eiger.
+pub(crate) const DEFAULT_PIXEL_SIZE_MM: f64 = 0.075;
+
+/// File extensions [`open`] recognises.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "nxs", "h5", "hdf5", "nx5", "cbf", "img", "tif", "tiff", "raw", "mrc", "mrcs", "zip", "tar",
+];
+
+/// Error from [`open`] for a file no reader recognises, so callers can tell
+/// it apart from a supported file that fails to open.
+#[derive(Debug)]
+pub struct UnsupportedFormat {
+    pub ext: String,
+}
+
+impl std::fmt::Display for UnsupportedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Unsupported file extension '.{}'. Supported: {}",
+            self.ext,
+            SUPPORTED_EXTENSIONS.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedFormat {}
+
+/// Convert a raw pixel value of any numeric type to the u16 display scale.
+///
+/// - NaN, and values below `trusted_min` (e.g. the negative "masked"
+///   sentinels some detectors write into signed data), become `masked_value`.
+/// - Values above `trusted_max` (overloads, and all-ones sentinels such as
+///   `0xFFFFFFFF` in u32 data) also become `masked_value`.
+/// - Everything else is rounded and saturated into `0..=u16::MAX`, so large
+///   values never wrap around to small ones.
+pub fn to_display_u16(value: f64, trusted_min: f64, trusted_max: f64, masked_value: u16) -> u16 {
+    if value.is_nan() || value < trusted_min || value > trusted_max {
+        masked_value
+    } else {
+        value.round().clamp(0.0, f64::from(u16::MAX)) as u16
+    }
+}
+
+/// [`Reader::read_frames`] a frame at a time, failing if the frames' sizes
+/// differ.
+pub fn read_each_frame<R: Reader + ?Sized>(
+    reader: &R,
+    frames: Range<usize>,
+) -> Result<(Vec<u16>, usize, usize)> {
+    let mut pixels = Vec::new();
+    let mut size = None;
+    for frame in frames {
+        let (frame_pixels, width, height) = reader.read_frame(frame)?;
+        if size.is_some_and(|size| size != (width, height)) {
+            anyhow::bail!("Frame {frame} is {width}x{height}, unlike the frames before it");
+        }
+        size = Some((width, height));
+        pixels.extend_from_slice(&frame_pixels);
+    }
+    let Some((width, height)) = size else {
+        anyhow::bail!("No frames to read");
+    };
+    Ok((pixels, width, height))
+}
+
+/// Fail unless the `w` x `h` region at (`x`, `y`) is non-empty and lies
+/// within a `width` x `height` frame.
+pub fn check_region(
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+) -> Result<()> {
+    if w == 0 || h == 0 {
+        anyhow::bail!("Region {w}x{h} is empty");
+    }
+    if x.saturating_add(w) > width || y.saturating_add(h) > height {
+        anyhow::bail!("Region {w}x{h} at ({x}, {y}) extends outside the {width}x{height} frame");
+    }
+    Ok(())
+}
+
+/// Copy the `w` x `h` region at (`x`, `y`) out of a row-major frame `width`
+/// pixels wide. The region must lie within the frame; see [`check_region`].
+pub fn crop(pixels: &[u16], width: usize, x: usize, y: usize, w: usize, h: usize) -> Vec<u16> {
+    pixels
+        .chunks_exact(width)
+        .skip(y)
+        .take(h)
+        .flat_map(|row| &row[x..x + w])
+        .copied()
+        .collect()
 }
 
 /// Open a file by inspecting its extension and returning the appropriate reader.
+/// Files with an unrecognised extension are sniffed for a format signature.
+/// `archive.zip!inner/frame.cbf` opens an entry of a zip or tar archive, and
+/// an archive's own path its frames as a series; see [`archive`].
+pub fn open(path: &Path, options: &OpenOptions) -> Result<Box<dyn Reader>> {
+    with_extra_mask(with_planar(open_format(path, options)?, options)?, options)
+}
+
+/// Open several files as one series, their frames numbered end to end in
+/// the given order. See [`series::SeriesReader`] for what must match.
+pub fn open_series(paths: &[&Path], options: &OpenOptions) -> Result<Box<dyn Reader>> {
+    let readers = paths
+        .iter()
+        .map(|path| open_format(path, options))
+        .collect::<Result<Vec<_>>>()?;
+    let reader = with_planar(Box::new(series::SeriesReader::new(readers)?), options)?;
+    with_extra_mask(reader, options)
+}
+
+/// The reader for `path`'s format, without the wrapping [`open`] adds.
 ///
 /// Extend this function to support additional formats: add a new module under
 /// `readers/` and match on the extension here.
-pub fn open(path: &Path) -> Result<Box<dyn Reader>> {
+fn open_format(path: &Path, options: &OpenOptions) -> Result<Box<dyn Reader>> {
+    if archive::split_member(path).is_some() || archive::is_archive(path) {
+        return Ok(Box::new(archive::ArchiveReader::open(path, options)?));
+    }
     let ext = path
         .extension()
         .and_then(|e| e.to_str())
@@ -53,9 +472,138 @@ pub fn open(path: &Path) -> Result<Box<dyn Reader>> {
         .to_lowercase();
 
     match ext.as_str() {
-        "nxs" | "h5" | "hdf5" | "nx5" => Ok(Box::new(nxs::NxsReader::open(path)?)),
-        _ => anyhow::bail!(
-            "Unsupported file extension '.{ext}'. Supported: nxs, h5, hdf5, nx5"
-        ),
+        "nxs" | "h5" | "hdf5" | "nx5" => open_nxs(path, options),
+        "cbf" => Ok(Box::new(cbf::CbfReader::open(path)?)),
+        "img" => Ok(Box::new(smv::SmvReader::open(path)?)),
+        "tif" | "tiff" => Ok(Box::new(tiff::TiffReader::open(path)?)),
+        "raw" => Ok(Box::new(raw::RawReader::open(path)?)),
+        "mrc" | "mrcs" => Ok(Box::new(mrc::MrcReader::open(path)?)),
+        _ if has_hdf5_signature(path) => open_nxs(path, options),
+        _ => Err(UnsupportedFormat { ext }.into()),
+    }
+}
+
+/// Wrap `reader` to reorder its rows if its detector stores them planar.
+/// Comes before [`with_extra_mask`], as mask files are in image order.
+fn with_planar(reader: Box<dyn Reader>, options: &OpenOptions) -> Result<Box<dyn Reader>> {
+    if options.planar_rows.is_none() && options.planar_detectors.is_empty() {
+        return Ok(reader);
+    }
+    let description = match options.planar_rows {
+        Some(_) => None,
+        None => reader.metadata()?.detector_description,
+    };
+    if planar::is_planar(
+        options.planar_rows,
+        description.as_deref(),
+        &options.planar_detectors,
+    ) {
+        Ok(Box::new(planar::PlanarReader::new(reader)))
+    } else {
+        Ok(reader)
+    }
+}
+
+/// Wrap `reader` to merge in the mask at `options.extra_mask_path`, if any.
+fn with_extra_mask(reader: Box<dyn Reader>, options: &OpenOptions) -> Result<Box<dyn Reader>> {
+    match &options.extra_mask_path {
+        Some(mask_path) => Ok(Box::new(mask::ExtraMaskReader::new(
+            reader,
+            Path::new(mask_path),
+        )?)),
+        None => Ok(reader),
+    }
+}
+
+fn open_nxs(path: &Path, options: &OpenOptions) -> Result<Box<dyn Reader>> {
+    let vfd = match options.vfd.as_deref() {
+        Some(name) => name.parse()?,
+        None => nxs::Vfd::default(),
+    };
+    Ok(Box::new(nxs::NxsReader::open(
+        path,
+        vfd,
+        options.data_path.as_deref(),
+    )?))
+}
+
+/// Whether the file contains the HDF5 superblock signature at one of the
+/// offsets where it may appear: 0, then 512, 1024, 2048, ... for files with
+/// a user block in front.
+fn has_hdf5_signature(path: &Path) -> bool {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const SIGNATURE: [u8; 8] = *b"\x89HDF\r\n\x1a\n";
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let len = file.metadata().map_or(0, |m| m.len());
+    let mut offset = 0u64;
+    while offset + SIGNATURE.len() as u64 <= len {
+        let mut buf = [0u8; 8];
+        if file.seek(SeekFrom::Start(offset)).is_err() || file.read_exact(&mut buf).is_err() {
+            return false;
+        }
+        if buf == SIGNATURE {
+            return true;
+        }
+        offset = if offset == 0 { 512 } else { offset * 2 };
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saturates_values_within_the_trusted_range() {
+        let display = |v| to_display_u16(v, 0.0, 100_000.0, 7);
+        assert_eq!(display(0.0), 0);
+        assert_eq!(display(100.0), 100);
+        assert_eq!(display(70000.0), u16::MAX);
+    }
+
+    #[test]
+    fn rounds_fractional_values() {
+        assert_eq!(to_display_u16(2.4, 0.0, 10.0, 7), 2);
+        assert_eq!(to_display_u16(2.6, 0.0, 10.0, 7), 3);
+    }
+
+    #[test]
+    fn masks_untrusted_values() {
+        let display = |v| to_display_u16(v, 0.0, 200.0, u16::MAX);
+        assert_eq!(display(201.0), u16::MAX);
+        assert_eq!(display(f64::from(u32::MAX)), u16::MAX);
+        assert_eq!(display(-1.0), u16::MAX);
+        assert_eq!(display(f64::NAN), u16::MAX);
+    }
+
+    #[test]
+    fn opens_hdf5_files_with_a_user_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let file = hdf5::File::with_options()
+            .with_fcpl(|p| p.userblock(512))
+            .create(&path)
+            .unwrap();
+        file.create_group("entry/instrument/detector").unwrap();
+        let data = ndarray::Array3::from_shape_vec((1, 2, 3), vec![1u16, 2, 3, 4, 5, 6]).unwrap();
+        file.new_dataset_builder()
+            .with_data(&data)
+            .create("entry/data/data")
+            .unwrap();
+        drop(file);
+        let mut start = [0u8; 8];
+        std::io::Read::read_exact(&mut std::fs::File::open(&path).unwrap(), &mut start).unwrap();
+        assert_ne!(&start, b"\x89HDF\r\n\x1a\n");
+
+        assert!(has_hdf5_signature(&path));
+        let reader = open(&path, &OpenOptions::default()).unwrap();
+        assert_eq!(reader.format_name(), "nxs");
+        assert_eq!(
+            reader.read_frame(0).unwrap(),
+            (vec![1, 2, 3, 4, 5, 6], 3, 2)
+        );
     }
 }
@@ -0,0 +1,317 @@
+//! Reader for MRC / CCP4-style image stacks (`.mrc`, `.mrcs`).
+//!
+//! An MRC file has a 1024-byte header of 32-bit words, an optional extended
+//! header of `NSYMBT` bytes, then `NZ` images of `NX` x `NY` pixels stored
+//! back to back; each image is one frame. Modes 0 (int8), 1 (int16),
+//! 2 (float32) and 6 (uint16) are supported, though float32 frames are
+//! only served through [`Reader::read_frame_f32`]. Byte order comes from the
+//! machine stamp, defaulting to little-endian. The header is parsed once on
+//! open; frames are read from the file on each call.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use tracing::debug;
+
+use super::{DEFAULT_PIXEL_SIZE_MM, ImageMetadata, Reader};
+
+/// Size of the fixed header.
+const HEADER_BYTES: usize = 1024;
+
+pub struct MrcReader {
+    path: PathBuf,
+    header: MrcHeader,
+}
+
+impl MrcReader {
+    /// Parse and validate the header, and check the file is long enough to
+    /// hold the images it describes, then return a reader for the file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file =
+            File::open(path).map_err(|e| anyhow!("Cannot open {}: {e}", path.display()))?;
+        let mut buf = [0u8; HEADER_BYTES];
+        file.read_exact(&mut buf)
+            .context("File is too short to hold an MRC header")?;
+        let header = MrcHeader::parse(&buf)?;
+        let data_end = u64::try_from(header.nz)
+            .ok()
+            .and_then(|nz| nz.checked_mul(header.frame_bytes as u64))
+            .and_then(|data| data.checked_add(header.data_offset))
+            .ok_or_else(|| anyhow!("Invalid MRC header: {} images are too large", header.nz))?;
+        let len = file.metadata()?.len();
+        if len < data_end {
+            anyhow::bail!(
+                "{} is truncated: its header describes {data_end} bytes but the file holds {len}",
+                path.display()
+            );
+        }
+        debug!(
+            frames = header.nz,
+            width = header.nx,
+            height = header.ny,
+            mode = header.mode.name(),
+            "mrc: opened {}",
+            path.display()
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            header,
+        })
+    }
+
+    fn open_file(&self) -> Result<File> {
+        File::open(&self.path).map_err(|e| anyhow!("Cannot open {}: {e}", self.path.display()))
+    }
+
+    /// The stored bytes of frame `frame`.
+    fn read_frame_bytes(&self, frame: usize) -> Result<Vec<u8>> {
+        let h = &self.header;
+        if frame >= h.nz {
+            anyhow::bail!(
+                "Frame index {frame} out of range (file has {} frames)",
+                h.nz
+            );
+        }
+        // Can't overflow: `open` checked the whole stack fits in the file.
+        let start = h.data_offset + frame as u64 * h.frame_bytes as u64;
+        let mut file = self.open_file()?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![0u8; h.frame_bytes];
+        file.read_exact(&mut bytes)
+            .with_context(|| format!("Frame {frame} of {} is truncated", self.path.display()))?;
+        Ok(bytes)
+    }
+}
+
+impl Reader for MrcReader {
+    fn format_name(&self) -> &'static str {
+        "mrc"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        let h = &self.header;
+        Ok(ImageMetadata {
+            panel_distance_mm: 0.0,
+            beam_center: [h.nx as f64 / 2.0, h.ny as f64 / 2.0],
+            pixel_size: h.pixel_size_mm.unwrap_or(DEFAULT_PIXEL_SIZE_MM),
+            panel_size_fast_slow: [h.nx as u64, h.ny as u64],
+            image_depth: 16,
+            trusted_range_max: (u16::MAX - 1) as f64,
+            source_dtype: Some(h.mode.name().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        Ok(self.header.nz)
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        let t0 = std::time::Instant::now();
+        let bytes = self.read_frame_bytes(frame)?;
+        let h = &self.header;
+        let convert = |v: f64| super::to_display_u16(v, 0.0, (u16::MAX - 1) as f64, u16::MAX);
+        let pixels = match h.mode {
+            MrcMode::Uint16 => h.decode(&bytes, u16::from_le_bytes, u16::from_be_bytes),
+            MrcMode::Int8 => bytes.iter().map(|&b| convert(f64::from(b as i8))).collect(),
+            MrcMode::Int16 => h
+                .decode(&bytes, i16::from_le_bytes, i16::from_be_bytes)
+                .into_iter()
+                .map(|v| convert(f64::from(v)))
+                .collect(),
+            MrcMode::Float32 => anyhow::bail!(
+                "Floating-point MRC images (mode 2) are not supported; \
+                 frames are sent as 16-bit integers"
+            ),
+        };
+        debug!(
+            elapsed_ms = t0.elapsed().as_millis(),
+            frame,
+            width = h.nx,
+            height = h.ny,
+            "mrc: frame read + convert"
+        );
+        Ok((pixels, h.nx, h.ny))
+    }
+
+    fn read_frame_f32(&self, frame: usize) -> Result<(Vec<f32>, usize, usize)> {
+        let h = &self.header;
+        if h.mode != MrcMode::Float32 {
+            let (pixels, width, height) = self.read_frame(frame)?;
+            return Ok((pixels.into_iter().map(f32::from).collect(), width, height));
+        }
+        let bytes = self.read_frame_bytes(frame)?;
+        let pixels = h.decode(&bytes, f32::from_le_bytes, f32::from_be_bytes);
+        Ok((pixels, h.nx, h.ny))
+    }
+}
+
+// ── Private helpers ──────────────────────────────────────────────────────────
+
+/// MRC data modes this reader handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MrcMode {
+    Int8,
+    Int16,
+    Float32,
+    Uint16,
+}
+
+impl MrcMode {
+    fn from_code(mode: i32) -> Result<Self> {
+        match mode {
+            0 => Ok(MrcMode::Int8),
+            1 => Ok(MrcMode::Int16),
+            2 => Ok(MrcMode::Float32),
+            6 => Ok(MrcMode::Uint16),
+            _ => anyhow::bail!(
+                "Unsupported MRC mode {mode}; only modes 0 (int8), 1 (int16), \
+                 2 (float32) and 6 (uint16) are supported"
+            ),
+        }
+    }
+
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            MrcMode::Int8 => 1,
+            MrcMode::Int16 | MrcMode::Uint16 => 2,
+            MrcMode::Float32 => 4,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MrcMode::Int8 => "int8",
+            MrcMode::Int16 => "int16",
+            MrcMode::Float32 => "float32",
+            MrcMode::Uint16 => "uint16",
+        }
+    }
+}
+
+/// The header fields this reader uses.
+struct MrcHeader {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    mode: MrcMode,
+    big_endian: bool,
+    /// Bytes per image.
+    frame_bytes: usize,
+    /// Byte offset of the first image, past any extended header.
+    data_offset: u64,
+    /// `cella.x / mx`, converted from Å to mm.
+    pixel_size_mm: Option<f64>,
+}
+
+impl MrcHeader {
+    fn parse(buf: &[u8; HEADER_BYTES]) -> Result<Self> {
+        // MACHST (word 54): 0x44 0x44 for little-endian, 0x11 0x11 for
+        // big-endian. Files from old writers leave it zero.
+        let big_endian = buf[212] == 0x11;
+        let word = |i: usize| {
+            let b = [buf[4 * i], buf[4 * i + 1], buf[4 * i + 2], buf[4 * i + 3]];
+            if big_endian {
+                i32::from_be_bytes(b)
+            } else {
+                i32::from_le_bytes(b)
+            }
+        };
+        let real = |i: usize| f32::from_bits(word(i) as u32);
+        let dimension = |i: usize, name: &str| -> Result<usize> {
+            usize::try_from(word(i))
+                .ok()
+                .filter(|&v| v > 0)
+                .ok_or_else(|| anyhow!("Invalid MRC header: {name} is {}", word(i)))
+        };
+        let nx = dimension(0, "NX")?;
+        let ny = dimension(1, "NY")?;
+        let nz = dimension(2, "NZ")?;
+        let mode = MrcMode::from_code(word(3))?;
+        let frame_bytes = nx
+            .checked_mul(ny)
+            .and_then(|n| n.checked_mul(mode.bytes_per_pixel()))
+            .ok_or_else(|| anyhow!("Invalid MRC header: {nx}x{ny} images are too large"))?;
+        let nsymbt = u64::try_from(word(23))
+            .map_err(|_| anyhow!("Invalid MRC header: NSYMBT is {}", word(23)))?;
+        let mx = word(7);
+        let cella_x = f64::from(real(10));
+        let pixel_size_mm = (mx > 0 && cella_x > 0.0).then(|| cella_x / f64::from(mx) * 1e-7);
+        Ok(Self {
+            nx,
+            ny,
+            nz,
+            mode,
+            big_endian,
+            frame_bytes,
+            data_offset: HEADER_BYTES as u64 + nsymbt,
+            pixel_size_mm,
+        })
+    }
+
+    /// Decode `bytes` as `N`-byte values in the file's byte order.
+    fn decode<T, const N: usize>(
+        &self,
+        bytes: &[u8],
+        from_le: fn([u8; N]) -> T,
+        from_be: fn([u8; N]) -> T,
+    ) -> Vec<T> {
+        let from_bytes = if self.big_endian { from_be } else { from_le };
+        bytes
+            .chunks_exact(N)
+            .map(|b| from_bytes(std::array::from_fn(|i| b[i])))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian MRC file of `nz` 3x2 images in `mode`, followed by
+    /// `data`.
+    fn write_mrc(path: &Path, mode: i32, nz: i32, data: &[u8]) {
+        let mut header = [0u8; HEADER_BYTES];
+        for (i, word) in [3, 2, nz, mode].into_iter().enumerate() {
+            header[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        header[212] = 0x44;
+        header[213] = 0x44;
+        std::fs::write(path, [&header[..], data].concat()).unwrap();
+    }
+
+    #[test]
+    fn float_frames_are_only_read_as_f32() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stack.mrc");
+        let values = [0.5f32, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        write_mrc(&path, 2, 1, &data);
+        let reader = MrcReader::open(&path).unwrap();
+
+        let err = reader.read_frame(0).unwrap_err().to_string();
+        assert!(err.contains("16-bit integers"), "{err}");
+        let (pixels, width, height) = reader.read_frame_f32(0).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(pixels, values);
+    }
+
+    #[test]
+    fn open_rejects_a_truncated_stack() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stack.mrc");
+        // Two 3x2 uint16 images need 24 bytes.
+        write_mrc(&path, 6, 2, &[0; 23]);
+        let err = MrcReader::open(&path).err().unwrap().to_string();
+        assert!(err.contains("truncated"), "{err}");
+
+        write_mrc(&path, 6, 2, &[0; 24]);
+        assert_eq!(MrcReader::open(&path).unwrap().frame_count().unwrap(), 2);
+    }
+}
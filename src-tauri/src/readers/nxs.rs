@@ -5,94 +5,758 @@
 //!
 //! - No axum/HTTP coupling — returns plain Rust types.
 //! - Metadata field `panel_distance_mm` matches diffrant's `ImageMetadata`.
-//! - `NxsReader` stores only the path; the HDF5 file is opened per call so the
-//!   struct is `Send + Sync` without a mutex around the file handle.
+//! - `NxsReader` opens the HDF5 file once and keeps the handle for later
+//!   calls, so scrubbing through frames doesn't re-read the superblock each
+//!   time. The handle is closed when the reader is dropped.
+//! - Eiger master files, whose frames are split across `data_NNNNNN`
+//!   external links to separate data files, read as one run of frames.
 
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use anyhow::{Result, anyhow};
-use tracing::debug;
+use tracing::{debug, info, warn};
 
-use super::{ImageMetadata, Reader};
+use super::{
+    ChunkFilter, DEFAULT_PIXEL_SIZE_MM, FrameMetadata, GainMap, ImageMetadata, PanelGeometry,
+    RawChunk, Reader,
+};
+use crate::geometry::HC_KEV_ANGSTROM;
+
+/// Environment variable holding a comma-separated, prioritised list of
+/// dataset paths to try before [`DEFAULT_DATA_PATHS`], for site-specific
+/// layouts, e.g. `entry/data/data,entry_0000/measurement/data`.
+pub const DATA_PATHS_ENV: &str = "DIFFRANT_DATA_PATHS";
+
+/// Image dataset paths tried in order: standard NXmx first, then layouts
+/// seen from other facilities.
+const DEFAULT_DATA_PATHS: &[&str] = &[
+    "entry/data/data",
+    "entry/instrument/detector/data",
+    "entry1/instrument/detector/data",
+    "entry1/data/data",
+    "data",
+];
+
+/// Linked image datasets of an Eiger master file live in this group.
+const EIGER_DATA_GROUP: &str = "entry/data";
 
 pub struct NxsReader {
     path: PathBuf,
+    vfd: Vfd,
+    /// The image datasets in frame order, resolved on open. A standard file
+    /// has one; an Eiger master file links one per data file.
+    blocks: Vec<DataBlock>,
+    /// Frames are stored `[frame, fast, slow]` instead of the standard
+    /// `[frame, slow, fast]`, or come from a detector mounted portrait, so
+    /// each one must be transposed on read.
+    transposed: bool,
+    /// Source files of the image datasets, if they are HDF5 virtual
+    /// datasets, so a frame in a missing one is reported by name.
+    vds_sources: Vec<VdsSource>,
+    /// Limits of the trusted pixel values, read on open.
+    trusted: TrustedRange,
+    /// The open file, reused by every call. Only held long enough to clone
+    /// the handle, so concurrent reads don't wait on each other here; the
+    /// HDF5 library serialises the reads themselves.
+    handle: std::sync::Mutex<Option<hdf5::File>>,
+}
+
+/// One image dataset and the run of frames it holds.
+struct DataBlock {
+    path: String,
+    /// Index of the dataset's first frame in the whole file.
+    first_frame: usize,
+}
+
+/// The part of one frame to read: rows `rows` and columns `cols` of frame
+/// `frame`, in stored order (so swapped for fast-major datasets).
+struct FrameSlice {
+    frames: std::ops::Range<usize>,
+    rows: std::ops::Range<usize>,
+    cols: std::ops::Range<usize>,
+}
+
+impl FrameSlice {
+    /// All of frame `frame` of a 3D `dataset`.
+    fn whole(dataset: &hdf5::Dataset, frame: usize) -> Self {
+        Self::frames(dataset, frame..frame + 1)
+    }
+
+    /// All of frames `frames` of a 3D `dataset`.
+    fn frames(dataset: &hdf5::Dataset, frames: std::ops::Range<usize>) -> Self {
+        let shape = dataset.shape();
+        Self {
+            frames,
+            rows: 0..shape[1],
+            cols: 0..shape[2],
+        }
+    }
+}
+
+/// A source file of a virtual image dataset and the frames it backs.
+struct VdsSource {
+    file: PathBuf,
+    frames: std::ops::Range<usize>,
+}
+
+/// Error reading a dataset whose filter pipeline includes a filter the HDF5
+/// library can't load, e.g. bitshuffle when no plugin directory is set up.
+#[derive(Debug)]
+pub struct MissingFilter {
+    pub id: i32,
+    pub name: String,
+    pub dataset: String,
+    /// Where HDF5 looked for plugins, as reported by [`plugin_path`].
+    pub plugin_path: String,
+}
+
+impl std::fmt::Display for MissingFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Dataset {} uses {} (filter {}), which is not installed; set HDF5_PLUGIN_PATH \
+             to a directory containing the plugin (currently searching: {})",
+            self.dataset, self.name, self.id, self.plugin_path
+        )
+    }
+}
+
+impl std::error::Error for MissingFilter {}
+
+/// HDF5 virtual file driver used to open the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Vfd {
+    /// Whatever the HDF5 library defaults to (normally sec2).
+    #[default]
+    Default,
+    /// POSIX unbuffered I/O.
+    Sec2,
+    /// C stdio buffered I/O; can behave better on some network mounts.
+    Stdio,
+    /// Read the whole file into memory on open.
+    Core,
+}
+
+impl std::str::FromStr for Vfd {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(Vfd::Default),
+            "sec2" => Ok(Vfd::Sec2),
+            "stdio" => Ok(Vfd::Stdio),
+            "core" => Ok(Vfd::Core),
+            "ros3" => anyhow::bail!("The ros3 (S3) driver is not supported by this build"),
+            _ => anyhow::bail!("Unknown HDF5 driver '{s}'. Supported: sec2, stdio, core"),
+        }
+    }
 }
 
 impl NxsReader {
-    /// Validate the file is readable, then return a reader for it.
-    pub fn open(path: &Path) -> Result<Self> {
-        // Quick open-and-close to surface errors early.
-        let _file = hdf5::File::open(path)
-            .map_err(|e| anyhow!("Cannot open {}: {e}", path.display()))?;
-        Ok(Self {
+    /// Validate the file is readable and locate its image dataset, then
+    /// return a reader for it. `data_path` names the dataset explicitly
+    /// instead of searching for it.
+    pub fn open(path: &Path, vfd: Vfd, data_path: Option<&str>) -> Result<Self> {
+        let mut reader = Self {
             path: path.to_path_buf(),
-        })
+            vfd,
+            blocks: Vec::new(),
+            transposed: false,
+            vds_sources: Vec::new(),
+            trusted: TrustedRange::default(),
+            handle: std::sync::Mutex::new(None),
+        };
+        // Open now to surface errors early; the handle is kept for later calls.
+        let file = reader.file()?;
+        info!("nxs: HDF5 plugin path: {}", plugin_path());
+        reader.blocks = match data_path {
+            Some(data_path) => vec![DataBlock {
+                path: open_data_path(&file, data_path)?,
+                first_frame: 0,
+            }],
+            None => locate_data_blocks(&file)?,
+        };
+        let fast_major = is_fast_major(&file, &file.dataset(reader.data_path())?.shape());
+        if fast_major {
+            info!(
+                "nxs: {} is stored fast-major; transposing frames",
+                reader.data_path()
+            );
+        }
+        let portrait = is_portrait_mount(&file);
+        if portrait {
+            info!("nxs: detector is mounted portrait; transposing frames");
+        }
+        // Both are transposes, so together they cancel.
+        reader.transposed = fast_major != portrait;
+        reader.trusted = TrustedRange::read(&file);
+        reader.vds_sources = read_vds_sources(&file, &reader.blocks);
+        let missing = reader.vds_sources.iter().filter(|s| !s.file.exists());
+        for source in missing {
+            warn!(
+                "nxs: virtual dataset source {} is missing; its frames can't be read",
+                source.file.display()
+            );
+        }
+        Ok(reader)
+    }
+
+    /// Path of the first image dataset, which stands for all of them where
+    /// only the frame shape matters.
+    fn data_path(&self) -> &str {
+        &self.blocks[0].path
+    }
+
+    /// The dataset holding `frame`, and the frame's index within it.
+    fn locate_frame(&self, frame: usize) -> Result<(&str, usize)> {
+        if self.blocks.len() > 1 {
+            let frame_count = self.frame_count()?;
+            if frame >= frame_count {
+                anyhow::bail!("Frame index {frame} out of range (file has {frame_count} frames)");
+            }
+        }
+        let i = self
+            .blocks
+            .partition_point(|block| block.first_frame <= frame)
+            .saturating_sub(1);
+        let block = &self.blocks[i];
+        let missing = self
+            .vds_sources
+            .iter()
+            .find(|source| source.frames.contains(&frame) && !source.file.exists());
+        if let Some(source) = missing {
+            anyhow::bail!(
+                "Frame {frame} is stored in {}, which is missing (not yet copied?)",
+                source.file.display()
+            );
+        }
+        Ok((&block.path, frame - block.first_frame))
+    }
+
+    /// The open HDF5 file, opening it on first use.
+    fn file(&self) -> Result<hdf5::File> {
+        let mut handle = self
+            .handle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(file) = handle.as_ref() {
+            return Ok(file.clone());
+        }
+        let file = self.open_file()?;
+        *handle = Some(file.clone());
+        Ok(file)
+    }
+
+    /// Run `op` on the open file. If it fails because the handle has gone
+    /// stale, as on NFS after the file is replaced (see [`is_stale_handle`]),
+    /// the handle is dropped and `op` retried once on a freshly opened file.
+    fn with_file<T>(&self, mut op: impl FnMut(&hdf5::File) -> Result<T>) -> Result<T> {
+        let file = self.file()?;
+        match op(&file) {
+            Err(e) if is_stale_handle(&e) => {
+                warn!(
+                    "nxs: reading {} failed ({e}); reopening it and retrying",
+                    self.path.display()
+                );
+                drop(file);
+                self.handle
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .take();
+                op(&self.file()?)
+            }
+            result => result,
+        }
+    }
+
+    /// Open the HDF5 file with the configured driver.
+    fn open_file(&self) -> Result<hdf5::File> {
+        let mut builder = hdf5::File::with_options();
+        match self.vfd {
+            Vfd::Default => {}
+            Vfd::Sec2 => {
+                builder.with_fapl(|p| p.sec2());
+            }
+            Vfd::Stdio => {
+                builder.with_fapl(|p| p.stdio());
+            }
+            Vfd::Core => {
+                builder.with_fapl(|p| p.core());
+            }
+        }
+        builder
+            .open(&self.path)
+            .map_err(|e| anyhow!("Cannot open {}: {e}", self.path.display()))
     }
 }
 
 impl Reader for NxsReader {
+    fn format_name(&self) -> &'static str {
+        "nxs"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn dataset_path(&self) -> Option<&str> {
+        Some(self.data_path())
+    }
+
     fn frame_count(&self) -> Result<usize> {
-        let file = hdf5::File::open(&self.path)?;
-        let dataset = file.dataset("entry/data/data")?;
-        let shape = dataset.shape();
-        if shape.len() != 3 {
-            anyhow::bail!("Expected 3D dataset, got {}D", shape.len());
-        }
-        Ok(shape[0])
+        // Only the last dataset is re-read, so a file still being written
+        // to is followed as it grows.
+        let last = &self.blocks[self.blocks.len() - 1];
+        self.with_file(|file| {
+            let dataset = file.dataset(&last.path)?;
+            let shape = dataset.shape();
+            if shape.len() != 3 {
+                anyhow::bail!("Expected 3D dataset, got {}D", shape.len());
+            }
+            Ok(last.first_frame + shape[0])
+        })
     }
 
     fn metadata(&self) -> Result<ImageMetadata> {
-        read_nxs_metadata(&self.path)
+        let nframes = self.frame_count()?;
+        self.with_file(|file| read_nxs_metadata(file, self.data_path(), nframes, self.transposed))
     }
 
     fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
-        read_nxs_frame(&self.path, frame)
+        let (data_path, local) = self.locate_frame(frame)?;
+        self.with_file(|file| read_nxs_frame(file, data_path, self.transposed, self.trusted, local))
+    }
+
+    /// The depth of the image datasets' chunks, if every dataset is chunked
+    /// alike and starts on a chunk boundary of the whole file.
+    fn frames_per_chunk(&self) -> usize {
+        let depth = |block: &DataBlock| {
+            self.with_file(|file| Ok(file.dataset(&block.path)?.chunk()))
+                .ok()
+                .flatten()
+                .and_then(|chunk| chunk.first().copied())
+                .unwrap_or(1)
+                .max(1)
+        };
+        let first = depth(&self.blocks[0]);
+        let aligned = self.blocks[1..]
+            .iter()
+            .all(|block| block.first_frame % first == 0 && depth(block) == first);
+        if aligned { first } else { 1 }
+    }
+
+    fn read_frames(&self, frames: Range<usize>) -> Result<(Vec<u16>, usize, usize)> {
+        if frames.is_empty() {
+            return super::read_each_frame(self, frames);
+        }
+        let (data_path, local) = self.locate_frame(frames.start)?;
+        let (last_path, last) = self.locate_frame(frames.end - 1)?;
+        if last_path != data_path {
+            return super::read_each_frame(self, frames);
+        }
+        // Frames of a virtual dataset may still come from different sources,
+        // any of which may be missing.
+        for frame in frames.clone() {
+            self.locate_frame(frame)?;
+        }
+        let (transposed, trusted) = (self.transposed, self.trusted);
+        self.with_file(|file| {
+            read_nxs_frames(file, data_path, transposed, trusted, local..last + 1)
+        })
+    }
+
+    fn read_frame_into(&self, frame: usize, buf: &mut Vec<u16>) -> Result<(usize, usize)> {
+        let (data_path, local) = self.locate_frame(frame)?;
+        self.with_file(|file| {
+            let (dataset, width, height) =
+                open_frame_dataset(file, data_path, self.transposed, local)?;
+            let dtype = dataset.dtype()?;
+            if self.transposed || !dtype.is::<u16>() {
+                // Needs converting or reordering anyway.
+                *buf = read_nxs_frame(file, data_path, self.transposed, self.trusted, local)?.0;
+            } else {
+                if !read_frame_mapped(&dataset, &dtype, local, buf)? {
+                    read_u16_frame_into(&dataset, local, width, height, buf)?;
+                }
+                self.trusted.apply_u16(buf);
+            }
+            Ok((width, height))
+        })
+    }
+
+    fn read_region(
+        &self,
+        frame: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> Result<Vec<u16>> {
+        let (data_path, local) = self.locate_frame(frame)?;
+        self.with_file(|file| {
+            let (dataset, width, height) =
+                open_frame_dataset(file, data_path, self.transposed, local)?;
+            super::check_region(width, height, x, y, w, h)?;
+            // Only the chunks overlapping the hyperslab are read and decompressed.
+            let slice = if self.transposed {
+                FrameSlice {
+                    frames: local..local + 1,
+                    rows: x..x + w,
+                    cols: y..y + h,
+                }
+            } else {
+                FrameSlice {
+                    frames: local..local + 1,
+                    rows: y..y + h,
+                    cols: x..x + w,
+                }
+            };
+            let pixels = read_nxs_pixels(&dataset, &dataset.dtype()?, self.trusted, &slice)?;
+            Ok(if self.transposed {
+                transpose_frame(pixels, w, h)
+            } else {
+                pixels
+            })
+        })
+    }
+
+    fn read_frame_f32(&self, frame: usize) -> Result<(Vec<f32>, usize, usize)> {
+        let (data_path, local) = self.locate_frame(frame)?;
+        self.with_file(|file| read_nxs_frame_f32(file, data_path, self.transposed, local))
+    }
+
+    fn read_raw_chunk(&self, frame: usize) -> Result<Option<RawChunk>> {
+        if self.transposed {
+            // Clients would have to know to transpose; don't hand them that.
+            return Ok(None);
+        }
+        let (data_path, local) = self.locate_frame(frame)?;
+        self.with_file(|file| read_nxs_raw_chunk(file, data_path, local))
+    }
+
+    fn mask(&self) -> Result<Option<(Vec<u8>, usize, usize)>> {
+        let mask = self.with_file(|file| read_nxs_mask(file, self.data_path(), self.transposed))?;
+        if let Some(mask) = mask {
+            return Ok(Some(mask));
+        }
+        let [width, height] = self.metadata()?.panel_size_fast_slow;
+        let (width, height) = (width as usize, height as usize);
+        let mask = super::mask::load_sibling_mask(&self.path, width, height)?;
+        Ok(mask.map(|mask| (mask, width, height)))
+    }
+
+    fn frame_angles(&self) -> Result<Option<Vec<f64>>> {
+        let frame_count = self.frame_count()?;
+        self.with_file(|file| Ok(read_nxs_frame_angles(file, frame_count)))
+    }
+
+    fn sources_available(&self) -> bool {
+        self.vds_sources.iter().all(|source| source.file.exists())
+    }
+
+    fn frame_metadata(&self, frame: usize) -> Result<FrameMetadata> {
+        let frame_count = self.frame_count()?;
+        if frame >= frame_count {
+            anyhow::bail!("Frame index {frame} out of range (file has {frame_count} frames)");
+        }
+        self.with_file(|file| Ok(read_nxs_frame_metadata(file, self.data_path(), frame)))
+    }
+
+    fn frame_times(&self) -> Result<Option<Vec<f64>>> {
+        let frame_count = self.frame_count()?;
+        self.with_file(|file| Ok(read_nxs_frame_times(file, self.data_path(), frame_count)))
+    }
+
+    fn gain_map(&self) -> Result<Option<GainMap>> {
+        self.with_file(|file| read_nxs_gain_map(file, self.data_path(), self.transposed))
+    }
+
+    fn applied_transforms(&self) -> Vec<&'static str> {
+        if self.transposed {
+            vec!["transpose"]
+        } else {
+            Vec::new()
+        }
     }
 }
 
 // ── Private helpers ──────────────────────────────────────────────────────────
 
-fn read_nxs_frame(path: &Path, frame_idx: usize) -> Result<(Vec<u16>, usize, usize)> {
-    use std::time::Instant;
-    let t_total = Instant::now();
+/// Where the sample-to-detector distance may be stored, in the order tried.
+/// Besides the NXmx fields, some beamlines write it next to the detector
+/// group, in `detectorSpecific`, or only as the detector's `det_z`
+/// translation.
+const DISTANCE_PATHS: &[&str] = &[
+    "entry/instrument/detector/distance",
+    "entry/instrument/detector/detector_distance",
+    "entry/instrument/detector_distance",
+    "entry/instrument/detector/detectorSpecific/detector_distance",
+    "entry/instrument/detector/transformations/det_z",
+    "entry/instrument/transformations/det_z",
+];
 
-    let file = hdf5::File::open(path)
-        .map_err(|e| anyhow!("Failed to open {}: {e}", path.display()))?;
+/// Groups checked for an attribute holding an object reference to the image
+/// dataset, when no candidate path has one.
+const REFERENCE_GROUPS: &[&str] = &["entry/data", "entry"];
 
+/// Check that an explicitly requested image dataset exists and is 3D.
+fn open_data_path(file: &hdf5::File, data_path: &str) -> Result<String> {
     let dataset = file
-        .dataset("entry/data/data")
-        .map_err(|e| anyhow!("Failed to open dataset entry/data/data: {e}"))?;
+        .dataset(data_path)
+        .map_err(|e| anyhow!("Cannot open requested dataset {data_path}: {e}"))?;
+    if dataset.ndim() != 3 {
+        anyhow::bail!(
+            "Requested dataset {data_path} is {}D; expected a 3D image stack",
+            dataset.ndim()
+        );
+    }
+    info!("nxs: using requested image dataset {data_path}");
+    Ok(data_path.to_owned())
+}
 
-    let shape = dataset.shape();
-    if shape.len() != 3 {
-        anyhow::bail!("Expected 3D dataset, got {}D", shape.len());
+/// Find the image datasets: the paths in [`DATA_PATHS_ENV`] in order, then
+/// [`DEFAULT_DATA_PATHS`]. The first that exists as a 3D dataset wins. Failing
+/// those, the `data_NNNNNN` links of an Eiger master file are used, and then
+/// an object reference attribute on one of [`REFERENCE_GROUPS`] that points
+/// at a 3D dataset is followed.
+fn locate_data_blocks(file: &hdf5::File) -> Result<Vec<DataBlock>> {
+    let single = |path: String| {
+        vec![DataBlock {
+            path,
+            first_frame: 0,
+        }]
+    };
+    let from_env = std::env::var(DATA_PATHS_ENV).unwrap_or_default();
+    let candidates: Vec<&str> = from_env
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .chain(DEFAULT_DATA_PATHS.iter().copied())
+        .collect();
+
+    for &candidate in &candidates {
+        match file.dataset(candidate) {
+            Ok(ds) if ds.ndim() == 3 => {
+                info!("nxs: using image dataset {candidate}");
+                return Ok(single(candidate.to_string()));
+            }
+            Ok(ds) => debug!("nxs: skipping {candidate}: {}D dataset", ds.ndim()),
+            Err(_) => debug!("nxs: skipping {candidate}: not found"),
+        }
     }
-    if frame_idx >= shape[0] {
-        anyhow::bail!(
-            "Frame index {frame_idx} out of range (dataset has {} frames)",
-            shape[0]
+    if let Some(blocks) = find_linked_blocks(file)? {
+        return Ok(blocks);
+    }
+    if let Some(path) = find_referenced_data_path(file) {
+        info!("nxs: using referenced image dataset {path}");
+        return Ok(single(path));
+    }
+    anyhow::bail!(
+        "No 3D image dataset found (tried {}, data_NNNNNN links in {EIGER_DATA_GROUP}, \
+         and references on {})",
+        candidates.join(", "),
+        REFERENCE_GROUPS.join(", ")
+    )
+}
+
+/// The `data_000001`, `data_000002`, ... datasets an Eiger master file links
+/// from [`EIGER_DATA_GROUP`] to its data files, in order, each with the index
+/// of its first frame. `None` if there are none; an error naming the data
+/// file if one can't be opened.
+fn find_linked_blocks(file: &hdf5::File) -> Result<Option<Vec<DataBlock>>> {
+    let Ok(group) = file.group(EIGER_DATA_GROUP) else {
+        return Ok(None);
+    };
+    let mut names: Vec<String> = group
+        .member_names()?
+        .into_iter()
+        .filter(|name| {
+            name.strip_prefix("data_")
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .collect();
+    if names.is_empty() {
+        return Ok(None);
+    }
+    // The numbers are zero-padded, so this is also numeric order.
+    names.sort();
+
+    let mut blocks = Vec::with_capacity(names.len());
+    let mut first_frame = 0;
+    for name in names {
+        let path = format!("{EIGER_DATA_GROUP}/{name}");
+        let missing = |e: hdf5::Error| match external_link_file(&group, &name) {
+            Some(target) => {
+                anyhow!("Cannot open {path}: data file {target} is missing or unreadable ({e})")
+            }
+            None => anyhow!("Cannot open {path}: {e}"),
+        };
+        let dataset = group.dataset(&name).map_err(missing)?;
+        let shape = dataset.shape();
+        if shape.len() != 3 {
+            anyhow::bail!("Expected 3D dataset at {path}, got {}D", shape.len());
+        }
+        blocks.push(DataBlock { path, first_frame });
+        first_frame += shape[0];
+    }
+    info!(
+        "nxs: using {} linked image datasets in {EIGER_DATA_GROUP}, {first_frame} frames",
+        blocks.len()
+    );
+    Ok(Some(blocks))
+}
+
+/// The file an external link in `group` points to, or `None` if `name` is
+/// not an external link.
+fn external_link_file(group: &hdf5::Group, name: &str) -> Option<String> {
+    use hdf5_sys::h5l::{H5Lget_val, H5Lunpack_elink_val};
+    use hdf5_sys::h5p::H5P_DEFAULT;
+
+    let c_name = std::ffi::CString::new(name).ok()?;
+    // Zero-filled and one byte longer than HDF5 may write, so the strings
+    // unpacked from it are always terminated.
+    let mut value = vec![0u8; 4097];
+    let id = group.id();
+    // SAFETY: `c_name` is a valid C string and `value` holds the buffer
+    // length passed; the unpacked pointers point into `value`.
+    hdf5::sync::sync(|| unsafe {
+        let status = H5Lget_val(
+            id,
+            c_name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len() - 1,
+            H5P_DEFAULT,
         );
+        if status < 0 {
+            return None;
+        }
+        let mut flags = 0;
+        let mut filename = std::ptr::null();
+        let mut object = std::ptr::null();
+        let status = H5Lunpack_elink_val(
+            value.as_ptr().cast(),
+            value.len() - 1,
+            &mut flags,
+            &mut filename,
+            &mut object,
+        );
+        if status < 0 || filename.is_null() {
+            return None;
+        }
+        let filename = std::ffi::CStr::from_ptr(filename);
+        Some(filename.to_string_lossy().into_owned())
+    })
+}
+
+/// Path of the first 3D dataset pointed to by an object reference attribute
+/// on one of [`REFERENCE_GROUPS`]. Only (legacy) object references are
+/// understood, not region references.
+fn find_referenced_data_path(file: &hdf5::File) -> Option<String> {
+    use hdf5::{ObjectReference1, ReferencedObject};
+
+    REFERENCE_GROUPS.iter().find_map(|&group_path| {
+        let group = file.group(group_path).ok()?;
+        group.attr_names().ok()?.into_iter().find_map(|name| {
+            let reference = group
+                .attr(&name)
+                .ok()?
+                .read_scalar::<ObjectReference1>()
+                .ok()?;
+            match file.dereference(&reference) {
+                Ok(ReferencedObject::Dataset(ds)) if ds.ndim() == 3 => Some(ds.name()),
+                Ok(_) => {
+                    debug!("nxs: skipping reference {group_path}@{name}: not a 3D dataset");
+                    None
+                }
+                Err(e) => {
+                    debug!("nxs: cannot follow reference {group_path}@{name}: {e}");
+                    None
+                }
+            }
+        })
+    })
+}
+
+/// Whether an `[n, a, b]` image dataset is stored fast-major, i.e. `a` is
+/// the fast (width) axis. Decided from the `data_size` (`[slow, fast]`) of
+/// the detector's `NXdetector_module`; square or unlabelled data is assumed
+/// standard.
+fn is_fast_major(file: &hdf5::File, shape: &[usize]) -> bool {
+    let [_, a, b] = *shape else {
+        return false;
+    };
+    if a == b {
+        return false;
+    }
+    let Ok(detector) = file.group("entry/instrument/detector") else {
+        return false;
+    };
+    let module = detector
+        .member_names()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| detector.group(&name).ok())
+        .find(|g| {
+            read_attr_strings(g, "NX_class")
+                .is_some_and(|classes| classes.iter().any(|c| c == "NXdetector_module"))
+        });
+    let data_size = module
+        .and_then(|m| m.dataset("data_size").ok())
+        .and_then(|ds| ds.read_raw::<u64>().ok());
+    match data_size.as_deref() {
+        Some(&[slow, fast]) => (a, b) == (fast as usize, slow as usize),
+        _ => false,
+    }
+}
+
+/// Whether the detector's `detector_orientation` records it as mounted
+/// `portrait`, a quarter turn from the `landscape` its rows are stored in,
+/// so frames must be transposed to show it as mounted. Other values are
+/// ignored.
+fn is_portrait_mount(file: &hdf5::File) -> bool {
+    let Some(orientation) = file
+        .group("entry/instrument/detector")
+        .ok()
+        .and_then(|detector| read_scalar_string(&detector, "detector_orientation"))
+    else {
+        return false;
+    };
+    match orientation.trim().to_ascii_lowercase().as_str() {
+        "portrait" => true,
+        "landscape" => false,
+        other => {
+            warn!("nxs: ignoring unknown detector_orientation {other:?}");
+            false
+        }
     }
+}
+
+fn read_nxs_frame(
+    file: &hdf5::File,
+    data_path: &str,
+    transposed: bool,
+    trusted: TrustedRange,
+    frame_idx: usize,
+) -> Result<(Vec<u16>, usize, usize)> {
+    use std::time::Instant;
+    let t_total = Instant::now();
 
-    let height = shape[1];
-    let width = shape[2];
+    let (dataset, width, height) = open_frame_dataset(file, data_path, transposed, frame_idx)?;
     let dtype_desc = format!("{:?}", dataset.dtype()?.to_descriptor()?);
 
     let t0 = Instant::now();
-    let pixels: Vec<u16> = if dataset.dtype()?.is::<u16>() {
-        let frame = dataset.read_slice_2d::<u16, _>((frame_idx, .., ..))?;
-        frame.into_raw_vec_and_offset().0
-    } else if dataset.dtype()?.is::<i32>() {
-        let frame = dataset.read_slice_2d::<i32, _>((frame_idx, .., ..))?;
-        frame.iter().map(|&v| v as i16 as u16).collect()
-    } else if dataset.dtype()?.is::<u32>() {
-        let frame = dataset.read_slice_2d::<u32, _>((frame_idx, .., ..))?;
-        frame.iter().map(|&v| v as i32 as i16 as u16).collect()
-    } else if dataset.dtype()?.is::<i16>() {
-        let frame = dataset.read_slice_2d::<i16, _>((frame_idx, .., ..))?;
-        frame.iter().map(|&v| v as u16).collect()
+    let dtype = dataset.dtype()?;
+    let mut pixels = Vec::new();
+    if read_frame_mapped(&dataset, &dtype, frame_idx, &mut pixels)? {
+        trusted.apply_u16(&mut pixels);
+    } else {
+        let slice = FrameSlice::whole(&dataset, frame_idx);
+        pixels = read_nxs_pixels(&dataset, &dtype, trusted, &slice)?;
+    }
+    let pixels = if transposed {
+        transpose_frame(pixels, width, height)
     } else {
-        anyhow::bail!("Unsupported pixel dtype: {dtype_desc}");
+        pixels
     };
     debug!(
         elapsed_ms = t0.elapsed().as_millis(),
@@ -109,96 +773,463 @@ fn read_nxs_frame(path: &Path, frame_idx: usize) -> Result<(Vec<u16>, usize, usi
     Ok((pixels, width, height))
 }
 
-fn read_nxs_metadata(path: &Path) -> Result<ImageMetadata> {
+/// Read frames `frames` of one image dataset as [`read_nxs_frame`] does,
+/// back to back, in a single read so each chunk is decompressed only once.
+fn read_nxs_frames(
+    file: &hdf5::File,
+    data_path: &str,
+    transposed: bool,
+    trusted: TrustedRange,
+    frames: Range<usize>,
+) -> Result<(Vec<u16>, usize, usize)> {
+    let (dataset, width, height) = open_frame_dataset(file, data_path, transposed, frames.end - 1)?;
+    let slice = FrameSlice::frames(&dataset, frames);
+    let pixels = read_nxs_pixels(&dataset, &dataset.dtype()?, trusted, &slice)?;
+    let pixels = if transposed {
+        pixels
+            .chunks_exact(width * height)
+            .flat_map(|frame| transpose_frame(frame.to_vec(), width, height))
+            .collect()
+    } else {
+        pixels
+    };
+    Ok((pixels, width, height))
+}
+
+/// Read `slice` of `dataset`, whose type is `dtype`, converted
+/// to the u16 display scale by [`TrustedRange::display`].
+fn read_nxs_pixels(
+    dataset: &hdf5::Dataset,
+    dtype: &hdf5::Datatype,
+    trusted: TrustedRange,
+    slice: &FrameSlice,
+) -> Result<Vec<u16>> {
+    Ok(if dtype.is::<u16>() {
+        let mut pixels = read_frame_slice::<u16>(dataset, slice)?;
+        trusted.apply_u16(&mut pixels);
+        pixels
+    } else {
+        let convert = |v: f64| trusted.display(v);
+        if dtype.is::<u8>() {
+            read_frame_as::<u8, _>(dataset, slice, |v| convert(f64::from(v)))?
+        } else if dtype.is::<i8>() {
+            read_frame_as::<i8, _>(dataset, slice, |v| convert(f64::from(v)))?
+        } else if dtype.is::<i16>() {
+            read_frame_as::<i16, _>(dataset, slice, |v| convert(f64::from(v)))?
+        } else if dtype.is::<i32>() {
+            read_frame_as::<i32, _>(dataset, slice, |v| convert(f64::from(v)))?
+        } else if dtype.is::<u32>() {
+            read_frame_as::<u32, _>(dataset, slice, |v| convert(f64::from(v)))?
+        } else if dtype.is::<i64>() {
+            read_frame_as::<i64, _>(dataset, slice, |v| convert(v as f64))?
+        } else if dtype.is::<u64>() {
+            read_frame_as::<u64, _>(dataset, slice, |v| convert(v as f64))?
+        } else if dtype.is::<f32>() {
+            read_frame_as::<f32, _>(dataset, slice, |v| convert(f64::from(v)))?
+        } else if dtype.is::<f64>() {
+            read_frame_as::<f64, _>(dataset, slice, convert)?
+        } else {
+            anyhow::bail!("Unsupported pixel dtype: {:?}", dtype.to_descriptor()?);
+        }
+    })
+}
+
+/// Read one frame as f32 without the display conversion of
+/// [`read_nxs_frame`]: float data keeps its fractional and negative values,
+/// and integers are converted as they are, so counts above `u16::MAX`
+/// survive. 64-bit values lose precision beyond f32's 24-bit mantissa.
+fn read_nxs_frame_f32(
+    file: &hdf5::File,
+    data_path: &str,
+    transposed: bool,
+    frame_idx: usize,
+) -> Result<(Vec<f32>, usize, usize)> {
+    let t0 = std::time::Instant::now();
+    let (dataset, width, height) = open_frame_dataset(file, data_path, transposed, frame_idx)?;
+    let dtype = dataset.dtype()?;
+    let slice = FrameSlice::whole(&dataset, frame_idx);
+    let pixels: Vec<f32> = if dtype.is::<f32>() {
+        read_frame_slice::<f32>(&dataset, &slice)?
+    } else if dtype.is::<f64>() {
+        read_frame_as::<f64, _>(&dataset, &slice, |v| v as f32)?
+    } else if dtype.is::<u8>() {
+        read_frame_as::<u8, _>(&dataset, &slice, f32::from)?
+    } else if dtype.is::<i8>() {
+        read_frame_as::<i8, _>(&dataset, &slice, f32::from)?
+    } else if dtype.is::<u16>() {
+        read_frame_as::<u16, _>(&dataset, &slice, f32::from)?
+    } else if dtype.is::<i16>() {
+        read_frame_as::<i16, _>(&dataset, &slice, f32::from)?
+    } else if dtype.is::<u32>() {
+        read_frame_as::<u32, _>(&dataset, &slice, |v| v as f32)?
+    } else if dtype.is::<i32>() {
+        read_frame_as::<i32, _>(&dataset, &slice, |v| v as f32)?
+    } else if dtype.is::<u64>() {
+        read_frame_as::<u64, _>(&dataset, &slice, |v| v as f32)?
+    } else if dtype.is::<i64>() {
+        read_frame_as::<i64, _>(&dataset, &slice, |v| v as f32)?
+    } else {
+        anyhow::bail!("Unsupported pixel dtype: {:?}", dtype.to_descriptor()?);
+    };
+    let pixels = if transposed {
+        transpose_frame(pixels, width, height)
+    } else {
+        pixels
+    };
+    debug!(
+        elapsed_ms = t0.elapsed().as_millis(),
+        width, height, "nxs: f32 frame read + convert"
+    );
+    Ok((pixels, width, height))
+}
+
+/// Open the image dataset and check `frame_idx` can be read from it as an
+/// image. Returns the dataset and the frame's `(width, height)`.
+fn open_frame_dataset(
+    file: &hdf5::File,
+    data_path: &str,
+    transposed: bool,
+    frame_idx: usize,
+) -> Result<(hdf5::Dataset, usize, usize)> {
+    let dataset = file
+        .dataset(data_path)
+        .map_err(|e| anyhow!("Failed to open dataset {data_path}: {e}"))?;
+
+    let shape = dataset.shape();
+    if shape.len() != 3 {
+        anyhow::bail!("Expected 3D dataset, got {}D", shape.len());
+    }
+    if frame_idx >= shape[0] {
+        anyhow::bail!(
+            "Frame index {frame_idx} out of range (dataset has {} frames)",
+            shape[0]
+        );
+    }
+
+    // NeXus `interpretation`: anything other than a plain image (e.g.
+    // `rgba-image`, `vertex`) would be misread as grayscale counts.
+    if let Some(interpretation) = read_dataset_attr_string(&dataset, "interpretation") {
+        if !interpretation.eq_ignore_ascii_case("image") {
+            anyhow::bail!(
+                "Dataset {data_path} has interpretation '{interpretation}'; \
+                 only 'image' is supported"
+            );
+        }
+    }
+
+    let (height, width) = if transposed {
+        (shape[2], shape[1])
+    } else {
+        (shape[1], shape[2])
+    };
+    Ok((dataset, width, height))
+}
+
+/// Copy frame `frame_idx` of a u16 dataset straight out of the mapped file
+/// into `buf`, bypassing the HDF5 read pipeline. Only uncompressed,
+/// contiguous datasets have their frames at a fixed byte offset; returns
+/// `false`, leaving `buf` alone, for anything else, which is read the usual
+/// way.
+#[cfg(feature = "mmap")]
+fn read_frame_mapped(
+    dataset: &hdf5::Dataset,
+    dtype: &hdf5::Datatype,
+    frame_idx: usize,
+    buf: &mut Vec<u16>,
+) -> Result<bool> {
+    use hdf5::dataset::Layout;
+    use hdf5::datatype::ByteOrder;
+
+    if !dtype.is::<u16>() || dataset.layout() != Layout::Contiguous || !dataset.filters().is_empty()
+    {
+        return Ok(false);
+    }
+    let from_bytes = match dtype.byte_order() {
+        ByteOrder::LittleEndian => u16::from_le_bytes,
+        ByteOrder::BigEndian => u16::from_be_bytes,
+        _ => return Ok(false),
+    };
+    // Not yet written, or stored in external files.
+    let Some(offset) = dataset.offset() else {
+        return Ok(false);
+    };
+    let shape = dataset.shape();
+    let frame_bytes = shape[1] * shape[2] * 2;
+    let start = offset as usize + frame_idx * frame_bytes;
+
+    let path = dataset.filename();
+    let file = std::fs::File::open(&path).map_err(|e| anyhow!("Cannot open {path}: {e}"))?;
+    // SAFETY: the mapping is only read, and only while `file` is open. If
+    // another process truncates the file meanwhile the read may fault, as it
+    // would for any mmap reader; HDF5 files aren't truncated in place.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    let Some(bytes) = map.get(start..start + frame_bytes) else {
+        anyhow::bail!("Frame {frame_idx} lies beyond the end of {path}");
+    };
+    buf.clear();
+    buf.extend(bytes.chunks_exact(2).map(|b| from_bytes([b[0], b[1]])));
+    Ok(true)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn read_frame_mapped(
+    _dataset: &hdf5::Dataset,
+    _dtype: &hdf5::Datatype,
+    _frame_idx: usize,
+    _buf: &mut Vec<u16>,
+) -> Result<bool> {
+    Ok(false)
+}
+
+/// Transpose a frame stored row-major as `width` rows of `height` into
+/// `height` rows of `width`.
+fn transpose_frame<T: Copy + Default>(pixels: Vec<T>, width: usize, height: usize) -> Vec<T> {
+    let mut out = vec![T::default(); pixels.len()];
+    for (i, row) in pixels.chunks_exact(height).enumerate() {
+        for (j, &v) in row.iter().enumerate() {
+            out[j * width + i] = v;
+        }
+    }
+    out
+}
+
+/// Read the on-disk bytes of the chunk holding `frame_idx`, if chunks are
+/// frame-aligned (`[1, height, width]`).
+fn read_nxs_raw_chunk(
+    file: &hdf5::File,
+    data_path: &str,
+    frame_idx: usize,
+) -> Result<Option<RawChunk>> {
+    use hdf5::filters::Filter;
+    use hdf5_sys::h5d::{H5Dget_chunk_info_by_coord, H5Dread_chunk1};
+    use hdf5_sys::h5p::H5P_DEFAULT;
+
+    let dataset = file.dataset(data_path)?;
+    let shape = dataset.shape();
+    if shape.len() != 3 {
+        anyhow::bail!("Expected 3D dataset, got {}D", shape.len());
+    }
+    if frame_idx >= shape[0] {
+        anyhow::bail!(
+            "Frame index {frame_idx} out of range (dataset has {} frames)",
+            shape[0]
+        );
+    }
+    let Some(chunk_shape) = dataset.chunk() else {
+        return Ok(None);
+    };
+    if chunk_shape != [1, shape[1], shape[2]] {
+        return Ok(None);
+    }
+
+    let offset = [frame_idx as u64, 0, 0];
+    let id = dataset.id();
+    let mut filter_mask = 0u32;
+    let mut addr = 0u64;
+    let mut size = 0u64;
+    // SAFETY: `offset` has one entry per dataset dimension and the out
+    // pointers are valid for the duration of the call.
+    let status = hdf5::sync::sync(|| unsafe {
+        H5Dget_chunk_info_by_coord(id, offset.as_ptr(), &mut filter_mask, &mut addr, &mut size)
+    });
+    if status < 0 {
+        anyhow::bail!("Failed to locate chunk for frame {frame_idx}");
+    }
+    if size == 0 {
+        anyhow::bail!("Chunk for frame {frame_idx} has not been written");
+    }
+
+    let mut data = vec![0u8; size as usize];
+    // SAFETY: `data` is exactly the stored chunk size reported above.
+    let status = hdf5::sync::sync(|| unsafe {
+        H5Dread_chunk1(
+            id,
+            H5P_DEFAULT,
+            offset.as_ptr(),
+            &mut filter_mask,
+            data.as_mut_ptr().cast(),
+        )
+    });
+    if status < 0 {
+        anyhow::bail!("Failed to read chunk for frame {frame_idx}");
+    }
+
+    let filters = dataset
+        .dcpl()?
+        .filters()
+        .into_iter()
+        .map(|f| ChunkFilter {
+            id: f.id(),
+            params: match f {
+                Filter::User(_, params) => params,
+                _ => Vec::new(),
+            },
+        })
+        .collect();
+    let dtype = dataset.dtype()?.to_descriptor()?.to_string();
+
+    Ok(Some(RawChunk {
+        chunk_shape,
+        dtype,
+        filters,
+        filter_mask,
+        data,
+    }))
+}
+
+/// Metadata for a file with `nframes` frames shaped like those in
+/// `data_path`.
+fn read_nxs_metadata(
+    file: &hdf5::File,
+    data_path: &str,
+    nframes: usize,
+    transposed: bool,
+) -> Result<ImageMetadata> {
     use std::time::Instant;
     let t_total = Instant::now();
 
-    let file = hdf5::File::open(path)
-        .map_err(|e| anyhow!("Failed to open {}: {e}", path.display()))?;
-
-    let dataset = file.dataset("entry/data/data")?;
+    let dataset = file.dataset(data_path)?;
     let shape = dataset.shape();
-    let (width, height) = if shape.len() == 3 {
+    let (width, height) = if shape.len() == 3 && transposed {
+        (shape[1] as u64, shape[2] as u64)
+    } else if shape.len() == 3 {
         (shape[2] as u64, shape[1] as u64)
     } else {
         anyhow::bail!("Expected 3D dataset, got {}D", shape.len());
     };
+    // Only the type is read here, not any pixels.
+    let source_dtype = dataset
+        .dtype()
+        .and_then(|t| t.to_descriptor())
+        .ok()
+        .map(|d| d.to_string());
 
     let detector = file.group("entry/instrument/detector")?;
 
-    // Distance: try "distance" then "detector_distance"; read value + units, convert to mm
-    let panel_distance = ["distance", "detector_distance"]
+    // Distance: first of DISTANCE_PATHS present; read value + units, convert to mm.
+    // A per-frame distance gives frame 0's here; see read_nxs_frame_metadata.
+    let panel_distance = DISTANCE_PATHS
         .iter()
-        .find_map(|name| {
-            let ds = detector.dataset(name).ok()?;
+        .find_map(|path| {
+            let ds = file.dataset(path).ok()?;
             // Try scalar first, then 1-element array (shape {1})
             let raw = ds
                 .read_scalar::<f64>()
                 .or_else(|_| ds.read_scalar::<f32>().map(|v| v as f64))
-                .or_else(|_| ds.read_1d::<f64>().map(|a| a[0]))
-                .or_else(|_| ds.read_1d::<f32>().map(|a| a[0] as f64))
-                .ok()?;
+                .ok()
+                .or_else(|| ds.read_1d::<f64>().ok().and_then(|a| a.first().copied()))
+                .or_else(|| {
+                    ds.read_1d::<f32>()
+                        .ok()
+                        .and_then(|a| a.first().map(|&v| v as f64))
+                })?;
             let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "m".to_owned());
-            let mm = match units.to_lowercase().as_str() {
-                "mm" => raw,
-                "cm" => raw * 10.0,
-                "m" | _ => raw * 1000.0,
-            };
-            Some(mm)
+            // A zero distance is a placeholder; keep looking for a real one.
+            Some(length_to_mm(raw, &units).unwrap_or(raw * 1000.0)).filter(|&mm| mm > 0.0)
         })
         .unwrap_or(0.0);
 
-    // Pixel size: read value + units attribute, convert to mm
-    let pixel_size = detector
-        .dataset("x_pixel_size")
-        .ok()
-        .and_then(|ds| {
-            let raw = ds
-                .read_scalar::<f64>()
-                .or_else(|_| ds.read_scalar::<f32>().map(|v| v as f64))
-                .ok()?;
-            let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "m".to_owned());
-            let mm = match units.to_lowercase().as_str() {
-                "mm" => raw,
-                "cm" => raw * 10.0,
-                "m" | _ => raw * 1000.0,
-            };
-            Some(mm)
-        })
-        .unwrap_or(0.075);
-
-    // Beam centre in pixels
-    let beam_cx = read_scalar_f64(&detector, "beam_center_x").unwrap_or(width as f64 / 2.0);
-    let beam_cy = read_scalar_f64(&detector, "beam_center_y").unwrap_or(height as f64 / 2.0);
-
-    // Beam energy: prefer direct eV, fall back to wavelength
-    let beam_energy_kev = file.group("entry/instrument/beam").ok().and_then(|beam| {
-        if let Some(ev) = read_scalar_f64(&beam, "incident_energy") {
-            return Some(ev / 1000.0);
-        }
-        let ds = beam.dataset("incident_wavelength").ok()?;
+    // Lengths such as pixel size: read value + units attribute, convert to mm
+    let read_length_mm = |name: &str| {
+        let ds = detector.dataset(name).ok()?;
         let raw = ds
             .read_scalar::<f64>()
             .or_else(|_| ds.read_scalar::<f32>().map(|v| v as f64))
             .ok()?;
-        let units =
-            read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "angstrom".to_owned());
-        let angstrom = match units.to_lowercase().as_str() {
-            "angstrom" | "angstroms" | "a" | "\u{00c5}" => raw,
-            "nm" => raw * 10.0,
-            "m" => raw * 1e10,
-            _ => raw,
-        };
-        Some(wavelength_to_energy_kev(angstrom))
-    });
+        let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "m".to_owned());
+        Some(length_to_mm(raw, &units).unwrap_or(raw * 1000.0))
+    };
+    // A portrait-mounted detector records these along its stored axes,
+    // which its transposed frames swap.
+    let (x, y) = if is_portrait_mount(file) {
+        ("y", "x")
+    } else {
+        ("x", "y")
+    };
+    let pixel_size = read_length_mm(&format!("{x}_pixel_size")).unwrap_or(DEFAULT_PIXEL_SIZE_MM);
+    let pixel_size_y = read_length_mm(&format!("{y}_pixel_size")).unwrap_or(pixel_size);
 
-    // Trusted range max
-    let trusted_range_max = detector
-        .group("detectorSpecific")
+    // Beam centre in pixels
+    let beam_cx = read_beam_center(&detector, &format!("beam_center_{x}"), pixel_size)
+        .unwrap_or(width as f64 / 2.0);
+    let beam_cy = read_beam_center(&detector, &format!("beam_center_{y}"), pixel_size_y)
+        .unwrap_or(height as f64 / 2.0);
+
+    // Beam energy; frame 0's for energy scans, see read_nxs_frame_metadata.
+    let beam_energy_kev = file
+        .group("entry/instrument/beam")
         .ok()
-        .and_then(|ds| read_scalar_f64(&ds, "countrate_correction_count_cutoff"))
-        .or_else(|| read_scalar_f64(&detector, "saturation_value"))
-        .unwrap_or((u16::MAX - 1) as f64);
+        .and_then(|beam| read_beam_energy_kev(&beam, 0));
+
+    let trusted_range_max = read_trusted_range_max(file);
+    let trusted_range_min = read_trusted_range_min(file);
+
+    // Exposure vs frame period; a low duty cycle means dead time between frames
+    let read_time = |name: &str| {
+        let ds = detector.dataset(name).ok()?;
+        let raw = ds
+            .read_scalar::<f64>()
+            .or_else(|_| ds.read_scalar::<f32>().map(|v| v as f64))
+            .ok()
+            .or_else(|| ds.read_1d::<f64>().ok().and_then(|a| a.first().copied()))?;
+        let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "s".to_owned());
+        time_to_s(raw, &units)
+    };
+    let gain = read_scalar_f64(&detector, "gain").filter(|&g| g > 0.0);
+    let count_time_s = read_time("count_time");
+    let frame_time_s = read_time("frame_time");
+    let duty_cycle = match (count_time_s, frame_time_s) {
+        (Some(count), Some(frame)) if frame > 0.0 => Some(count / frame),
+        _ => None,
+    };
+
+    // What produced the data, e.g. "Dectris Eiger2 XE 16M", "Si", 0.45 mm
+    let read_text = |name: &str| {
+        read_scalar_string(&detector, name)
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+    };
+    let detector_description = read_text("description");
+    let sensor_material = read_text("sensor_material");
+    let sensor_thickness_mm = read_length_mm("sensor_thickness").filter(|&mm| mm > 0.0);
+
+    let detector_specific = detector.group("detectorSpecific").ok();
+
+    // Corrections already baked into the stored data
+    let read_applied = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            read_scalar_bool(&detector, name).or_else(|| {
+                detector_specific
+                    .as_ref()
+                    .and_then(|ds| read_scalar_bool(ds, name))
+            })
+        })
+    };
+    let flatfield_applied = read_applied(&["flatfield_applied", "flatfield_correction_applied"]);
+    let pixel_mask_applied = read_applied(&["pixel_mask_applied"]);
+
+    // Trigger structure: frames come in `ntrigger` groups of `nimages`
+    let images_per_trigger = detector_specific
+        .as_ref()
+        .and_then(|ds| read_scalar_usize(ds, "nimages"));
+    let ntriggers = detector_specific
+        .as_ref()
+        .and_then(|ds| read_scalar_usize(ds, "ntrigger"));
+
+    // Scan axes live alongside the image dataset in its NXdata group.
+    let data_group = data_path.rsplit_once('/').map_or("/", |(group, _)| group);
+    let scan_positions = file
+        .group(data_group)
+        .ok()
+        .and_then(|data| read_scan_positions(&data, nframes));
+
+    let panels = read_panels(&detector);
+
+    // Goniometer axis rotated during the scan, for the rotation overlay
+    let (scan_axis_name, rotation_axis) = match read_rotation_axis(file, nframes) {
+        Some((name, vector)) => (Some(name), vector),
+        None => (None, None),
+    };
 
     debug!(
         total_ms = t_total.elapsed().as_millis(),
@@ -212,10 +1243,1141 @@ fn read_nxs_metadata(path: &Path) -> Result<ImageMetadata> {
         panel_size_fast_slow: [width, height],
         image_depth: 16,
         trusted_range_max,
+        trusted_range_min,
         beam_energy_kev,
+        source_dtype,
+        detector_description,
+        sensor_material,
+        sensor_thickness_mm,
+        images_per_trigger,
+        ntriggers,
+        gain,
+        count_time_s,
+        frame_time_s,
+        duty_cycle,
+        flatfield_applied,
+        pixel_mask_applied,
+        scan_positions,
+        panels,
+        rotation_axis,
+        scan_axis_name,
+    })
+}
+
+/// Read the NXmx `pixel_mask` (an integer bitmask; any set bit means the
+/// pixel is bad) as 0/1 per pixel, in the same orientation as the frames.
+fn read_nxs_mask(
+    file: &hdf5::File,
+    data_path: &str,
+    transposed: bool,
+) -> Result<Option<(Vec<u8>, usize, usize)>> {
+    let Ok(mask) = file.dataset("entry/instrument/detector/pixel_mask") else {
+        return Ok(None);
+    };
+    let shape = file.dataset(data_path)?.shape();
+    let (height, width) = if transposed {
+        (shape[2], shape[1])
+    } else {
+        (shape[1], shape[2])
+    };
+    let flags = read_mask_dataset(&mask, width, height, transposed)?;
+    Ok(Some((flags, width, height)))
+}
+
+/// Where an HDF5 mask file may hold its mask, in the order tried: where an
+/// NXmx file keeps it, or at the root.
+const MASK_FILE_PATHS: &[&str] = &["entry/instrument/detector/pixel_mask", "pixel_mask"];
+
+/// The 2D pixel mask in the HDF5 mask file at `path`, as 0/1 per pixel of
+/// `width` x `height` frames, read as [`read_nxs_mask`] reads an embedded
+/// one; `None` if there is none at [`MASK_FILE_PATHS`].
+pub(super) fn read_mask_file(path: &Path, width: usize, height: usize) -> Result<Option<Vec<u8>>> {
+    let file =
+        hdf5::File::open(path).map_err(|e| anyhow!("Cannot open {}: {e}", path.display()))?;
+    let Some(mask) = MASK_FILE_PATHS.iter().find_map(|p| file.dataset(p).ok()) else {
+        return Ok(None);
+    };
+    read_mask_dataset(&mask, width, height, false).map(Some)
+}
+
+/// A 2D integer mask dataset as 0/1 per pixel of `width` x `height` frames.
+/// A mask stored fast-major, like data that is `transposed`, is transposed
+/// to match the frames. A square mask fits either way, so `transposed`
+/// decides.
+fn read_mask_dataset(
+    mask: &hdf5::Dataset,
+    width: usize,
+    height: usize,
+    transposed: bool,
+) -> Result<Vec<u8>> {
+    let fast_major = match mask.shape()[..] {
+        [h, w] if transposed && (h, w) == (width, height) => true,
+        [h, w] if (h, w) == (height, width) => false,
+        [h, w] if (h, w) == (width, height) => true,
+        ref other => anyhow::bail!("pixel_mask has shape {other:?}, frames are {width}x{height}"),
+    };
+    let bits = match mask.read_raw::<i64>() {
+        Ok(bits) => bits,
+        Err(e) => return Err(missing_filter(mask).map_or_else(|| e.into(), Into::into)),
+    };
+    let flags: Vec<u8> = bits.iter().map(|&v| u8::from(v != 0)).collect();
+    if !fast_major {
+        return Ok(flags);
+    }
+    // Each stored row is one column of the frame.
+    let mut out = vec![0u8; flags.len()];
+    for (i, row) in flags.chunks_exact(height).enumerate() {
+        for (j, &v) in row.iter().enumerate() {
+            out[j * width + i] = v;
+        }
+    }
+    Ok(out)
+}
+
+/// The source files of whichever image datasets are HDF5 virtual datasets,
+/// with the frames each backs. Mappings onto the file itself (`.`) are left
+/// out, as are selections that don't map to whole frames.
+fn read_vds_sources(file: &hdf5::File, blocks: &[DataBlock]) -> Vec<VdsSource> {
+    use hdf5::dataset::Layout;
+
+    let mut sources = Vec::new();
+    for block in blocks {
+        let Ok(ds) = file.dataset(&block.path) else {
+            continue;
+        };
+        let Ok(dcpl) = ds.dcpl() else {
+            continue;
+        };
+        if dcpl.layout() != Layout::Virtual {
+            continue;
+        }
+        // Relative source names are relative to the file holding the VDS.
+        let vds_file = ds.filename();
+        let base = Path::new(&vds_file).parent().unwrap_or(Path::new(""));
+        for mapping in dcpl.virtual_map() {
+            if mapping.src_filename == "." {
+                continue;
+            }
+            let Some(frames) = selection_frames(&mapping.vds_selection) else {
+                continue;
+            };
+            let start = block.first_frame + frames.start;
+            sources.push(VdsSource {
+                file: resolve_vds_source(base, &mapping.src_filename),
+                frames: start..block.first_frame.saturating_add(frames.end),
+            });
+        }
+    }
+    sources
+}
+
+/// Where HDF5 looks for a VDS source file named `name`: as given if
+/// absolute, else under `HDF5_VDS_PREFIX` if it is found there, else next
+/// to the file holding the VDS.
+fn resolve_vds_source(base: &Path, name: &str) -> PathBuf {
+    let name = Path::new(name);
+    if name.is_absolute() {
+        return name.to_path_buf();
+    }
+    if let Some(prefix) = std::env::var_os("HDF5_VDS_PREFIX") {
+        let candidate = Path::new(&prefix).join(name);
+        if candidate.exists() {
+            return candidate;
+        }
+    }
+    base.join(name)
+}
+
+/// The frames (first dimension) a VDS mapping selects, unbounded for
+/// `All` and unlimited selections.
+fn selection_frames(selection: &hdf5::Selection) -> Option<std::ops::Range<usize>> {
+    use hdf5::{Selection, SliceOrIndex};
+
+    match selection {
+        Selection::All => Some(0..usize::MAX),
+        Selection::Points(points) => {
+            let frames = points.column(0);
+            let first = *frames.iter().min()?;
+            let last = *frames.iter().max()?;
+            Some(first..last + 1)
+        }
+        Selection::Hyperslab(hyperslab) => match *hyperslab.first()? {
+            SliceOrIndex::Index(i) => Some(i..i + 1),
+            SliceOrIndex::SliceTo { start, end, .. } => Some(start..end),
+            SliceOrIndex::SliceCount { count: 0, .. } => None,
+            SliceOrIndex::SliceCount {
+                start,
+                step,
+                count,
+                block,
+            } => Some(start..start + (count - 1) * step + block),
+            SliceOrIndex::Unlimited { start, .. } => Some(start..usize::MAX),
+        },
+    }
+}
+
+/// Read what the file records about one frame: the `timestamp` in its NXdata
+/// group (or `entry/data`), the detector `count_time`, and the NXdata
+/// group's `auxiliary_signals`. Each may be a scalar or one value per frame;
+/// values that can't be read are left out. The distance is only given when
+/// the first of [`DISTANCE_PATHS`] holding one has a value per frame, and
+/// the beam energy and wavelength when the beam's does.
+fn read_nxs_frame_metadata(file: &hdf5::File, data_path: &str, frame: usize) -> FrameMetadata {
+    let data_group = data_path.rsplit_once('/').map_or("/", |(group, _)| group);
+    let data = file.group(data_group).ok();
+    let read_time = |ds: hdf5::Dataset| {
+        let raw = read_frame_value(&ds, frame)?;
+        let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "s".to_owned());
+        Some(time_to_s(raw, &units).unwrap_or(raw))
+    };
+
+    let timestamp_s = timestamp_dataset(file, data_path).and_then(read_time);
+    let count_time_s = file
+        .dataset("entry/instrument/detector/count_time")
+        .ok()
+        .and_then(read_time);
+    let distance_mm = DISTANCE_PATHS
+        .iter()
+        .find_map(|path| file.dataset(path).ok())
+        .filter(|ds| matches!(ds.shape()[..], [n] if n > 1))
+        .and_then(|ds| {
+            let raw = read_frame_value(&ds, frame)?;
+            let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "m".to_owned());
+            Some(length_to_mm(raw, &units).unwrap_or(raw * 1000.0))
+        });
+    let incident_energy_kev = file
+        .group("entry/instrument/beam")
+        .ok()
+        .filter(|beam| {
+            BEAM_ENERGY_NAMES
+                .iter()
+                .find_map(|name| beam.dataset(name).ok())
+                .is_some_and(|ds| matches!(ds.shape()[..], [n] if n > 1))
+        })
+        .and_then(|beam| read_beam_energy_kev(&beam, frame));
+    let signals = data
+        .as_ref()
+        .and_then(|data| {
+            let names = read_attr_strings(data, "auxiliary_signals")?;
+            Some(
+                names
+                    .into_iter()
+                    .filter_map(|name| {
+                        let value = read_frame_value(&data.dataset(&name).ok()?, frame)?;
+                        Some((name, value))
+                    })
+                    .collect(),
+            )
+        })
+        .unwrap_or_default();
+
+    FrameMetadata {
+        timestamp_s,
+        count_time_s,
+        distance_mm,
+        incident_energy_kev,
+        wavelength_angstrom: incident_energy_kev.map(energy_kev_to_wavelength_angstrom),
+        signals,
+    }
+}
+
+/// Names in the beam group of the beam energy or wavelength, in the order
+/// tried.
+const BEAM_ENERGY_NAMES: &[&str] = &["incident_energy", "incident_wavelength"];
+
+/// The beam energy in keV for `frame`, from the beam group's
+/// `incident_energy` (in eV unless its units say keV) or else its
+/// `incident_wavelength`. Either may be a scalar or one value per frame.
+fn read_beam_energy_kev(beam: &hdf5::Group, frame: usize) -> Option<f64> {
+    if let Ok(ds) = beam.dataset("incident_energy") {
+        if let Some(raw) = read_frame_value(&ds, frame) {
+            let units = read_dataset_attr_string(&ds, "units").unwrap_or_default();
+            return Some(match units.trim().to_lowercase().as_str() {
+                "kev" => raw,
+                _ => raw / 1000.0,
+            });
+        }
+    }
+    let ds = beam.dataset("incident_wavelength").ok()?;
+    let raw = read_frame_value(&ds, frame)?;
+    let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "angstrom".to_owned());
+    let angstrom = match units.to_lowercase().as_str() {
+        "angstrom" | "angstroms" | "a" | "\u{00c5}" => raw,
+        "nm" => raw * 10.0,
+        "m" => raw * 1e10,
+        _ => raw,
+    };
+    Some(wavelength_to_energy_kev(angstrom))
+}
+
+/// The `timestamp` dataset in the image data's group, or else in
+/// `entry/data`.
+fn timestamp_dataset(file: &hdf5::File, data_path: &str) -> Option<hdf5::Dataset> {
+    let data_group = data_path.rsplit_once('/').map_or("/", |(group, _)| group);
+    [data_group, EIGER_DATA_GROUP]
+        .iter()
+        .filter_map(|group| file.group(group).ok())
+        .find_map(|group| group.dataset("timestamp").ok())
+}
+
+/// Every frame's timestamp in seconds, as [`read_nxs_frame_metadata`] gives
+/// them but read in one go; `None` unless there is one for each of the
+/// `nframes` frames. A scalar timestamp applies to every frame.
+fn read_nxs_frame_times(file: &hdf5::File, data_path: &str, nframes: usize) -> Option<Vec<f64>> {
+    let ds = timestamp_dataset(file, data_path)?;
+    let mut times = match ds.shape()[..] {
+        [] | [1] => vec![*read_1d_f64(&ds)?.first()?; nframes],
+        [n] if n >= nframes => {
+            let mut times = read_1d_f64(&ds)?;
+            times.truncate(nframes);
+            times
+        }
+        _ => return None,
+    };
+    let units = read_dataset_attr_string(&ds, "units").unwrap_or_else(|| "s".to_owned());
+    for time in &mut times {
+        *time = time_to_s(*time, &units).unwrap_or(*time);
+    }
+    Some(times)
+}
+
+/// The value of a per-frame dataset for `frame`: element `frame` of a 1D
+/// dataset, or the value of a scalar one that applies to every frame.
+fn read_frame_value(ds: &hdf5::Dataset, frame: usize) -> Option<f64> {
+    match ds.shape()[..] {
+        [] | [1] => ds
+            .read_raw::<f64>()
+            .or_else(|_| {
+                ds.read_raw::<f32>()
+                    .map(|v| v.into_iter().map(f64::from).collect())
+            })
+            .ok()?
+            .first()
+            .copied(),
+        [n] if frame < n => ds
+            .read_slice_1d::<f64, _>(frame..frame + 1)
+            .or_else(|_| {
+                ds.read_slice_1d::<f32, _>(frame..frame + 1)
+                    .map(|v| v.mapv(f64::from))
+            })
+            .ok()?
+            .first()
+            .copied(),
+        _ => None,
+    }
+}
+
+/// Names under `detectorSpecific` of the per-pixel gain calibration, in the
+/// order tried.
+const GAIN_MAP_NAMES: &[&str] = &["gain_map", "gainmap", "gain"];
+
+/// Names under `detectorSpecific` of the matching dark offsets.
+const PEDESTAL_NAMES: &[&str] = &["pedestal", "pedestal_map"];
+
+/// Read a Jungfrau-style gain calibration from `detectorSpecific`: a
+/// `[stages, height, width]` (or single-stage `[height, width]`) gain array,
+/// and a pedestal array of the same shape if there is one.
+fn read_nxs_gain_map(
+    file: &hdf5::File,
+    data_path: &str,
+    transposed: bool,
+) -> Result<Option<GainMap>> {
+    let Ok(specific) = file.group("entry/instrument/detector/detectorSpecific") else {
+        return Ok(None);
+    };
+    let Some(gain_ds) = GAIN_MAP_NAMES
+        .iter()
+        .find_map(|name| specific.dataset(name).ok().filter(|ds| ds.ndim() >= 2))
+    else {
+        return Ok(None);
+    };
+    let shape = file.dataset(data_path)?.shape();
+    let (height, width) = if transposed {
+        (shape[2], shape[1])
+    } else {
+        (shape[1], shape[2])
+    };
+
+    let read_stages = |ds: &hdf5::Dataset| -> Result<(Vec<f32>, usize)> {
+        let (stages, h, w) = match ds.shape()[..] {
+            [h, w] => (1, h, w),
+            [stages, h, w] => (stages, h, w),
+            ref other => anyhow::bail!("{} has shape {other:?}", ds.name()),
+        };
+        let values = ds.read_raw::<f32>()?;
+        let values = if (h, w) == (height, width) {
+            values
+        } else if (h, w) == (width, height) {
+            // Stored fast-major like the data; transpose to match the frames.
+            values
+                .chunks_exact(h * w)
+                .flat_map(|stage| transpose_frame(stage.to_vec(), width, height))
+                .collect()
+        } else {
+            anyhow::bail!("{} is {w}x{h}, frames are {width}x{height}", ds.name());
+        };
+        Ok((values, stages))
+    };
+
+    let (gain, stages) = read_stages(&gain_ds)?;
+    let pedestal_ds = PEDESTAL_NAMES
+        .iter()
+        .find_map(|name| specific.dataset(name).ok());
+    let pedestal = match pedestal_ds {
+        Some(ds) => {
+            let (pedestal, pedestal_stages) = read_stages(&ds)?;
+            if pedestal_stages != stages {
+                anyhow::bail!("Gain map has {stages} stages but pedestal has {pedestal_stages}");
+            }
+            Some(pedestal)
+        }
+        None => None,
+    };
+    debug!(stages, "nxs: read gain map from {}", gain_ds.name());
+    Ok(Some(GainMap {
+        stages,
+        width,
+        height,
+        gain,
+        pedestal,
+    }))
+}
+
+/// Read the per-frame scan positions of a mapping scan from the NXdata group.
+///
+/// Candidate axes are the names in the group's `axes` attribute plus any
+/// `<name>_indices` attribute pointing at dimension 0 (the frame axis). Of
+/// those, the 1D datasets with one value per frame are used; an axis whose
+/// name ends in `x` is taken as x and one ending in `y` as y, otherwise the
+/// first two in order. Returns `None` unless two such axes are found.
+fn read_scan_positions(data: &hdf5::Group, nframes: usize) -> Option<Vec<[f64; 2]>> {
+    let mut names: Vec<String> = read_attr_strings(data, "axes")
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| name != ".")
+        .collect();
+    for attr in data.attr_names().unwrap_or_default() {
+        let Some(axis) = attr.strip_suffix("_indices") else {
+            continue;
+        };
+        let on_frame_axis = data
+            .attr(&attr)
+            .ok()
+            .and_then(|a| a.read_raw::<i64>().ok())
+            .is_some_and(|idx| idx.first() == Some(&0));
+        if on_frame_axis && !names.iter().any(|n| n == axis) {
+            names.push(axis.to_owned());
+        }
+    }
+
+    let mut axes: Vec<(String, Vec<f64>)> = names
+        .into_iter()
+        .filter_map(|name| {
+            let ds = data.dataset(&name).ok()?;
+            if ds.shape() != [nframes] {
+                return None;
+            }
+            let values = read_1d_f64(&ds)?;
+            Some((name, values))
+        })
+        .collect();
+    if axes.len() < 2 {
+        return None;
+    }
+
+    let position_ending = |axes: &[(String, Vec<f64>)], c: char| {
+        axes.iter()
+            .position(|(name, _)| name.to_lowercase().ends_with(c))
+            .unwrap_or(0)
+    };
+    let xs = axes.remove(position_ending(&axes, 'x')).1;
+    let ys = axes.remove(position_ending(&axes, 'y')).1;
+
+    Some(xs.into_iter().zip(ys).map(|(x, y)| [x, y]).collect())
+}
+
+/// Geometry of every `NXdetector_module` in a tiled detector. Modules whose
+/// transformation chain can't be resolved are skipped; `None` if none can,
+/// or if there is only one module, which the flat panel fields describe.
+fn read_panels(detector: &hdf5::Group) -> Option<Vec<PanelGeometry>> {
+    let modules: Vec<_> = detector
+        .member_names()
+        .ok()?
+        .into_iter()
+        .filter_map(|name| {
+            let module = detector.group(&name).ok()?;
+            read_attr_strings(&module, "NX_class")
+                .is_some_and(|classes| classes.iter().any(|c| c == "NXdetector_module"))
+                .then_some((name, module))
+        })
+        .collect();
+    if modules.len() < 2 {
+        return None;
+    }
+    let panels: Vec<_> = modules
+        .iter()
+        .filter_map(|(name, module)| {
+            read_panel(module, name)
+                .inspect_err(|e| debug!("nxs: cannot resolve geometry of module {name}: {e}"))
+                .ok()
+        })
+        .collect();
+    (!panels.is_empty()).then_some(panels)
+}
+
+/// Resolve one module's `fast_pixel_direction` and `slow_pixel_direction`
+/// through their `depends_on` chains into lab-frame axes and the position of
+/// the first pixel.
+fn read_panel(module: &hdf5::Group, name: &str) -> Result<PanelGeometry> {
+    let file = module.file()?;
+    let fast = module.dataset("fast_pixel_direction")?;
+    let slow = module.dataset("slow_pixel_direction")?;
+    let fast_step = Transformation::read(&fast)?;
+    let slow_step = Transformation::read(&slow)?;
+    if !fast_step.is_translation || !slow_step.is_translation {
+        anyhow::bail!("Pixel directions must be translations");
+    }
+    let fast_to_lab = resolve_depends_on(&file, &fast)?;
+    let slow_to_lab = resolve_depends_on(&file, &slow)?;
+
+    // The pixel directions lead off from pixel [0, 0], so with a value of 0
+    // the fast direction's own offset places the first pixel.
+    let origin_mm = fast_to_lab.apply_point(fast_step.offset_mm);
+    let fast_axis = normalize(fast_to_lab.apply_vector(fast_step.vector));
+    let slow_axis = normalize(slow_to_lab.apply_vector(slow_step.vector));
+
+    // NXmx orders these [slow, fast].
+    let read_slow_fast = |field: &str| {
+        let values = module.dataset(field).ok()?.read_raw::<i64>().ok()?;
+        match values[..] {
+            [slow, fast] => Some([fast.max(0) as u64, slow.max(0) as u64]),
+            _ => None,
+        }
+    };
+
+    Ok(PanelGeometry {
+        name: name.to_owned(),
+        origin_mm,
+        fast_axis,
+        slow_axis,
+        pixel_size_mm: [fast_step.value.abs(), slow_step.value.abs()],
+        data_origin_fast_slow: read_slow_fast("data_origin"),
+        size_fast_slow: read_slow_fast("data_size"),
+    })
+}
+
+/// Longest `depends_on` chain followed before assuming it loops.
+const MAX_DEPENDS_ON_DEPTH: usize = 64;
+
+/// Compose the transformations `ds` depends on, up to the `"."` that ends the
+/// chain, into the map from `ds`'s frame to the lab frame. `ds` itself is not
+/// included. Relative `depends_on` paths are resolved against the group
+/// holding the dataset that names them.
+fn resolve_depends_on(file: &hdf5::File, ds: &hdf5::Dataset) -> Result<Affine> {
+    let mut to_lab = Affine::IDENTITY;
+    let mut current = ds.clone();
+    for _ in 0..MAX_DEPENDS_ON_DEPTH {
+        let Some(next) = next_depends_on(file, &current)? else {
+            return Ok(to_lab);
+        };
+        current = next;
+        to_lab = to_lab.then(&Transformation::read(&current)?.to_affine());
+    }
+    anyhow::bail!("depends_on chain longer than {MAX_DEPENDS_ON_DEPTH} steps; is it circular?")
+}
+
+/// The dataset named by `ds`'s `depends_on` attribute; `None` if it has none.
+fn next_depends_on(file: &hdf5::File, ds: &hdf5::Dataset) -> Result<Option<hdf5::Dataset>> {
+    let Some(depends_on) = read_dataset_attr_string(ds, "depends_on") else {
+        return Ok(None);
+    };
+    let name = ds.name();
+    let parent = name.rsplit_once('/').map_or("", |(parent, _)| parent);
+    open_depends_on(file, parent, &depends_on)
+}
+
+/// Open the dataset a `depends_on` value names, resolving relative paths
+/// against the group `parent`. `None` for the `"."` that ends a chain.
+fn open_depends_on(
+    file: &hdf5::File,
+    parent: &str,
+    depends_on: &str,
+) -> Result<Option<hdf5::Dataset>> {
+    let depends_on = depends_on.trim();
+    if depends_on == "." || depends_on.is_empty() {
+        return Ok(None);
+    }
+    let path = if depends_on.starts_with('/') {
+        depends_on.to_owned()
+    } else {
+        format!("{parent}/{depends_on}")
+    };
+    file.dataset(&path)
+        .map(Some)
+        .map_err(|e| anyhow!("Cannot follow depends_on {path}: {e}"))
+}
+
+/// Rotation axes tried, after the sample's `depends_on` chain, for
+/// [`read_nxs_frame_angles`].
+const ROTATION_AXIS_PATHS: &[&str] = &[
+    "entry/sample/transformations/omega",
+    "entry/sample/transformations/phi",
+];
+
+/// Where the per-frame step of a rotation axis stored only as its start
+/// angle may be: an attribute of the axis, or a sibling dataset named
+/// `<axis>_<name>` (as NXmx's `omega_increment_set`).
+const ANGLE_INCREMENT_NAMES: &[&str] = &["increment_set", "oscillation_width", "increment"];
+
+/// How far, as a fraction of the increment, a per-frame angle may stray
+/// from `start + i * increment` before the two are reported as disagreeing.
+const ANGLE_INCREMENT_TOLERANCE: f64 = 0.1;
+
+/// The rotation angle of each frame in degrees; see [`read_scan_axis`].
+fn read_nxs_frame_angles(file: &hdf5::File, nframes: usize) -> Option<Vec<f64>> {
+    read_scan_axis(file, nframes).map(|(_, angles)| angles)
+}
+
+/// The goniometer axis rotated during the scan, with the angle of each
+/// frame in degrees.
+///
+/// The rotations in the sample's `depends_on` chain are tried first, then
+/// [`ROTATION_AXIS_PATHS`]. The first axis with a value per frame is used;
+/// failing that, the first axis holding one start angle with a nonzero
+/// increment gives `start + i * increment`. A per-frame axis that also has
+/// an increment is checked against it, with a warning if they disagree.
+fn read_scan_axis(file: &hdf5::File, nframes: usize) -> Option<(hdf5::Dataset, Vec<f64>)> {
+    let mut axes = Vec::new();
+    if let Some(depends_on) = file
+        .group("entry/sample")
+        .ok()
+        .and_then(|sample| read_scalar_string(&sample, "depends_on"))
+    {
+        let mut next = open_depends_on(file, "/entry/sample", &depends_on)
+            .ok()
+            .flatten();
+        while let Some(ds) = next {
+            if axes.len() >= MAX_DEPENDS_ON_DEPTH {
+                break;
+            }
+            next = next_depends_on(file, &ds).ok().flatten();
+            axes.push(ds);
+        }
+    }
+    axes.extend(
+        ROTATION_AXIS_PATHS
+            .iter()
+            .filter_map(|path| file.dataset(path).ok()),
+    );
+
+    let rotations: Vec<(hdf5::Dataset, Vec<f64>)> = axes
+        .into_iter()
+        .filter(|ds| {
+            read_dataset_attr_string(ds, "transformation_type")
+                .is_none_or(|kind| kind.trim().eq_ignore_ascii_case("rotation"))
+        })
+        .filter_map(|ds| {
+            let units = read_dataset_attr_string(&ds, "units");
+            let degrees = read_1d_f64(&ds)?
+                .into_iter()
+                .map(|v| angle_to_degrees(v, units.as_deref()))
+                .collect();
+            Some((ds, degrees))
+        })
+        .collect();
+
+    if let Some((ds, angles)) = rotations
+        .iter()
+        .find(|(_, angles)| angles.len() > 1 && angles.len() >= nframes)
+    {
+        debug!("nxs: frame angles from {}", ds.name());
+        let angles = &angles[..nframes];
+        let increment = read_angle_increment(file, ds).filter(|&step| step != 0.0);
+        if let Some((frame, expected)) =
+            increment.and_then(|step| first_off_increment(angles, step))
+        {
+            warn!(
+                "nxs: {} puts frame {frame} at {}°, but its increment puts it at {expected}°; \
+                 using the per-frame angles",
+                ds.name(),
+                angles[frame]
+            );
+        }
+        return Some((ds.clone(), angles.to_vec()));
+    }
+    rotations.into_iter().find_map(|(ds, angles)| {
+        let &[start] = &angles[..] else {
+            return None;
+        };
+        let increment = read_angle_increment(file, &ds).filter(|&step| step != 0.0)?;
+        debug!("nxs: frame angles from {} start and increment", ds.name());
+        let angles = (0..nframes).map(|i| start + i as f64 * increment).collect();
+        Some((ds, angles))
+    })
+}
+
+/// The first frame whose angle is more than [`ANGLE_INCREMENT_TOLERANCE`]
+/// of a step from `angles[0] + i * increment`, with the angle expected there.
+fn first_off_increment(angles: &[f64], increment: f64) -> Option<(usize, f64)> {
+    let start = *angles.first()?;
+    let tolerance = ANGLE_INCREMENT_TOLERANCE * increment.abs();
+    angles.iter().enumerate().find_map(|(i, &angle)| {
+        let expected = start + i as f64 * increment;
+        ((angle - expected).abs() > tolerance).then_some((i, expected))
+    })
+}
+
+/// The scan axis's name and unit `vector`, flipped if the angle decreases
+/// over the scan so that the frames always advance by a right-handed
+/// rotation about it.
+fn read_rotation_axis(file: &hdf5::File, nframes: usize) -> Option<(String, Option<[f64; 3]>)> {
+    let (ds, angles) = read_scan_axis(file, nframes)?;
+    let path = ds.name();
+    let name = path.rsplit('/').next().unwrap_or_default().to_owned();
+    let decreasing = matches!(&angles[..], [first, .., last] if last < first);
+    let vector = read_attr_vec3(&ds, "vector")
+        .map(normalize)
+        .map(|v| if decreasing { v.map(|c| -c) } else { v });
+    Some((name, vector))
+}
+
+/// The per-frame step of a rotation axis in degrees; see
+/// [`ANGLE_INCREMENT_NAMES`].
+fn read_angle_increment(file: &hdf5::File, ds: &hdf5::Dataset) -> Option<f64> {
+    let units = read_dataset_attr_string(ds, "units");
+    let name = ds.name();
+    ANGLE_INCREMENT_NAMES.iter().find_map(|suffix| {
+        let step = ds
+            .attr(suffix)
+            .ok()
+            .and_then(|attr| attr.read_raw::<f64>().ok())
+            .and_then(|v| v.first().copied())
+            .or_else(|| {
+                let sibling = file.dataset(&format!("{name}_{suffix}")).ok()?;
+                read_1d_f64(&sibling)?.first().copied()
+            })?;
+        Some(angle_to_degrees(step, units.as_deref()))
     })
 }
 
+/// Convert an angle in NeXus `units` to degrees; values without units are
+/// taken as degrees.
+fn angle_to_degrees(value: f64, units: Option<&str>) -> f64 {
+    match units.map(|u| u.trim().to_lowercase()).as_deref() {
+        Some("rad" | "radian" | "radians") => value.to_degrees(),
+        _ => value,
+    }
+}
+
+/// One NeXus `NXtransformations` entry: a translation along, or rotation
+/// about, `vector` by `value`, after which `offset` is added.
+struct Transformation {
+    is_translation: bool,
+    /// Unit vector.
+    vector: [f64; 3],
+    /// mm for translations, radians for rotations. For a scanned axis this is
+    /// the position at the first frame.
+    value: f64,
+    offset_mm: [f64; 3],
+}
+
+impl Transformation {
+    fn read(ds: &hdf5::Dataset) -> Result<Self> {
+        let name = ds.name();
+        let kind = read_dataset_attr_string(ds, "transformation_type")
+            .ok_or_else(|| anyhow!("{name} has no transformation_type"))?;
+        let is_translation = match kind.trim().to_lowercase().as_str() {
+            "translation" => true,
+            "rotation" => false,
+            other => anyhow::bail!("{name} has unknown transformation_type '{other}'"),
+        };
+        let vector = read_attr_vec3(ds, "vector")
+            .map(normalize)
+            .ok_or_else(|| anyhow!("{name} has no vector"))?;
+        let raw = read_1d_f64(ds)
+            .and_then(|v| v.first().copied())
+            .unwrap_or(0.0);
+        let units = read_dataset_attr_string(ds, "units");
+        let value = if is_translation {
+            let units = units.as_deref().unwrap_or("m");
+            length_to_mm(raw, units).unwrap_or(raw * 1000.0)
+        } else {
+            match units.as_deref().map(|u| u.trim().to_lowercase()).as_deref() {
+                Some("rad" | "radian" | "radians") => raw,
+                _ => raw.to_radians(),
+            }
+        };
+        let offset_units = read_dataset_attr_string(ds, "offset_units")
+            .or_else(|| units.filter(|_| is_translation))
+            .unwrap_or_else(|| "m".to_owned());
+        let offset_mm = read_attr_vec3(ds, "offset")
+            .unwrap_or_default()
+            .map(|v| length_to_mm(v, &offset_units).unwrap_or(v * 1000.0));
+        Ok(Self {
+            is_translation,
+            vector,
+            value,
+            offset_mm,
+        })
+    }
+
+    fn to_affine(&self) -> Affine {
+        let [x, y, z] = self.vector;
+        if self.is_translation {
+            Affine {
+                rotation: Affine::IDENTITY.rotation,
+                translation: std::array::from_fn(|i| {
+                    self.offset_mm[i] + self.value * self.vector[i]
+                }),
+            }
+        } else {
+            // Rodrigues' rotation formula.
+            let (sin, cos) = self.value.sin_cos();
+            let c = 1.0 - cos;
+            Affine {
+                rotation: [
+                    [cos + x * x * c, x * y * c - z * sin, x * z * c + y * sin],
+                    [y * x * c + z * sin, cos + y * y * c, y * z * c - x * sin],
+                    [z * x * c - y * sin, z * y * c + x * sin, cos + z * z * c],
+                ],
+                translation: self.offset_mm,
+            }
+        }
+    }
+}
+
+/// Rotation followed by translation; `p -> rotation · p + translation`.
+#[derive(Clone, Copy)]
+struct Affine {
+    rotation: [[f64; 3]; 3],
+    translation: [f64; 3],
+}
+
+impl Affine {
+    const IDENTITY: Self = Self {
+        rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        translation: [0.0; 3],
+    };
+
+    fn apply_vector(&self, v: [f64; 3]) -> [f64; 3] {
+        self.rotation
+            .map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+    }
+
+    fn apply_point(&self, p: [f64; 3]) -> [f64; 3] {
+        let r = self.apply_vector(p);
+        std::array::from_fn(|i| r[i] + self.translation[i])
+    }
+
+    /// `self` followed by `outer`.
+    fn then(&self, outer: &Affine) -> Affine {
+        let product = |i: usize, j: usize| {
+            (0..3)
+                .map(|k| outer.rotation[i][k] * self.rotation[k][j])
+                .sum()
+        };
+        Affine {
+            rotation: std::array::from_fn(|i| std::array::from_fn(|j| product(i, j))),
+            translation: outer.apply_point(self.translation),
+        }
+    }
+}
+
+fn normalize(v: [f64; 3]) -> [f64; 3] {
+    let len = v.iter().map(|c| c * c).sum::<f64>().sqrt();
+    if len > 0.0 { v.map(|c| c / len) } else { v }
+}
+
+fn read_attr_vec3(ds: &hdf5::Dataset, attr_name: &str) -> Option<[f64; 3]> {
+    let attr = ds.attr(attr_name).ok()?;
+    let values = attr
+        .read_raw::<f64>()
+        .or_else(|_| {
+            attr.read_raw::<f32>()
+                .map(|v| v.into_iter().map(f64::from).collect())
+        })
+        .ok()?;
+    values.try_into().ok()
+}
+
+/// Read a beam-centre coordinate in pixels.
+///
+/// NXmx stores the beam centre in pixels, but some SAXS files store it as a
+/// physical position from the corner at the data origin (pixel `[0, 0]`),
+/// with length `units`. Those are converted using the pixel pitch along the
+/// same axis. Values with no units, or non-length units such as `pixel`,
+/// are taken as pixels.
+fn read_beam_center(detector: &hdf5::Group, name: &str, pixel_size_mm: f64) -> Option<f64> {
+    let ds = detector.dataset(name).ok()?;
+    let raw = ds
+        .read_scalar::<f64>()
+        .or_else(|_| ds.read_scalar::<f32>().map(|v| v as f64))
+        .ok()?;
+    let mm = read_dataset_attr_string(&ds, "units").and_then(|units| length_to_mm(raw, &units));
+    match mm {
+        Some(mm) if pixel_size_mm > 0.0 => Some(mm / pixel_size_mm),
+        _ => Some(raw),
+    }
+}
+
+/// Convert a length in NeXus `units` to mm. Returns `None` if `units` is not
+/// a recognised length unit.
+fn length_to_mm(value: f64, units: &str) -> Option<f64> {
+    match units.trim().to_lowercase().as_str() {
+        "mm" | "millimetre" | "millimetres" | "millimeter" | "millimeters" => Some(value),
+        "cm" => Some(value * 10.0),
+        "m" | "metre" | "metres" | "meter" | "meters" => Some(value * 1000.0),
+        "um" | "\u{00b5}m" | "micron" | "microns" => Some(value / 1000.0),
+        _ => None,
+    }
+}
+
+/// Convert a time to seconds; `None` for unrecognised units.
+fn time_to_s(value: f64, units: &str) -> Option<f64> {
+    match units.trim().to_lowercase().as_str() {
+        "s" | "sec" | "second" | "seconds" => Some(value),
+        "ms" => Some(value / 1e3),
+        "us" | "\u{00b5}s" => Some(value / 1e6),
+        "ns" => Some(value / 1e9),
+        _ => None,
+    }
+}
+
+/// The range of pixel values the detector can be trusted to have measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TrustedRange {
+    min: f64,
+    max: f64,
+}
+
+impl Default for TrustedRange {
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: (u16::MAX - 1) as f64,
+        }
+    }
+}
+
+impl TrustedRange {
+    fn read(file: &hdf5::File) -> Self {
+        Self {
+            min: read_trusted_range_min(file),
+            max: read_trusted_range_max(file),
+        }
+    }
+
+    /// `value` on the u16 display scale, with untrusted values masked; see
+    /// [`super::to_display_u16`].
+    fn display(self, value: f64) -> u16 {
+        super::to_display_u16(value, self.min, self.max, u16::MAX)
+    }
+
+    /// Convert u16 data in place, as [`Self::display`] does other types.
+    /// A range covering every u16 value leaves the data as it is.
+    fn apply_u16(self, pixels: &mut [u16]) {
+        if self.min <= 0.0 && self.max >= f64::from(u16::MAX - 1) {
+            return;
+        }
+        for v in pixels {
+            *v = self.display(f64::from(*v));
+        }
+    }
+}
+
+/// Pixel value above which pixels are untrusted: the count-rate cutoff,
+/// else the saturation value, else `u16::MAX - 1`.
+fn read_trusted_range_max(file: &hdf5::File) -> f64 {
+    file.group("entry/instrument/detector")
+        .ok()
+        .and_then(|detector| {
+            detector
+                .group("detectorSpecific")
+                .ok()
+                .and_then(|ds| read_scalar_f64(&ds, "countrate_correction_count_cutoff"))
+                .or_else(|| read_scalar_f64(&detector, "saturation_value"))
+        })
+        .unwrap_or((u16::MAX - 1) as f64)
+}
+
+/// Pixel value below which pixels are untrusted: the NXmx `underload_value`
+/// of the detector (or its `detectorSpecific` group), else 0.
+fn read_trusted_range_min(file: &hdf5::File) -> f64 {
+    file.group("entry/instrument/detector")
+        .ok()
+        .and_then(|detector| {
+            read_scalar_f64(&detector, "underload_value").or_else(|| {
+                detector
+                    .group("detectorSpecific")
+                    .ok()
+                    .and_then(|ds| read_scalar_f64(&ds, "underload_value"))
+            })
+        })
+        .unwrap_or(0.0)
+}
+
+/// Read `slice` of a frame of a dataset of `T`, converting each pixel with
+/// `convert`.
+fn read_frame_as<T: hdf5::H5Type + Copy, U>(
+    dataset: &hdf5::Dataset,
+    slice: &FrameSlice,
+    convert: impl Fn(T) -> U,
+) -> Result<Vec<U>> {
+    let frame = read_frame_slice::<T>(dataset, slice)?;
+    Ok(frame.into_iter().map(convert).collect())
+}
+
+/// Read `slice` of a dataset of `T` as stored. A read that fails
+/// because a filter plugin is missing is reported as [`MissingFilter`].
+fn read_frame_slice<T: hdf5::H5Type>(
+    dataset: &hdf5::Dataset,
+    slice: &FrameSlice,
+) -> Result<Vec<T>> {
+    let selection = (slice.frames.clone(), slice.rows.clone(), slice.cols.clone());
+    match dataset.read_slice::<T, _, ndarray::Ix3>(selection) {
+        Ok(frames) => Ok(frames.into_raw_vec_and_offset().0),
+        Err(e) => Err(missing_filter(dataset).map_or_else(|| e.into(), Into::into)),
+    }
+}
+
+/// Read one `width` x `height` frame of a u16 dataset straight into `buf`,
+/// resized to fit, rather than into a new array.
+fn read_u16_frame_into(
+    dataset: &hdf5::Dataset,
+    frame_idx: usize,
+    width: usize,
+    height: usize,
+    buf: &mut Vec<u16>,
+) -> Result<()> {
+    use hdf5_sys::h5d::H5Dread;
+    use hdf5_sys::h5p::H5P_DEFAULT;
+
+    let file_space = dataset.space()?.select((frame_idx, .., ..))?;
+    let mem_space = hdf5::Dataspace::try_new([height, width])?;
+    let mem_type = hdf5::Datatype::from_type::<u16>()?;
+    buf.resize(width * height, 0);
+    let (id, mem_type, mem_space, file_space) =
+        (dataset.id(), mem_type.id(), mem_space.id(), file_space.id());
+    // SAFETY: `buf` holds exactly the `height * width` u16 values selected by
+    // both dataspaces, in the memory type passed. The error stack is read
+    // under the same lock, so it is this call's: it carries the file driver's
+    // message, with the `errno` that `is_stale_handle` looks for.
+    let read = hdf5::sync::sync(|| unsafe {
+        hdf5::h5check(H5Dread(
+            id,
+            mem_type,
+            mem_space,
+            file_space,
+            H5P_DEFAULT,
+            buf.as_mut_ptr().cast(),
+        ))
+    });
+    if let Err(e) = read {
+        return Err(match missing_filter(dataset) {
+            Some(missing) => missing.into(),
+            None => {
+                let context = format!("Failed to read frame {frame_idx} of {}", dataset.name());
+                anyhow::Error::new(e).context(context)
+            }
+        });
+    }
+    Ok(())
+}
+
+/// `errno` values of a read through a file handle that is no longer valid.
+#[cfg(unix)]
+const STALE_HANDLE_ERRNOS: [i32; 2] = [libc::ESTALE, libc::EBADF];
+#[cfg(not(unix))]
+const STALE_HANDLE_ERRNOS: [i32; 0] = [];
+
+/// Whether `e` is a read through a stale file handle, which reopening the
+/// file fixes: an OS error, or one HDF5's file driver reports as
+/// `errno = N`, of `ESTALE` or `EBADF`. Other I/O errors, and problems with
+/// the file's contents, would only fail again.
+fn is_stale_handle(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        let errno = match cause.downcast_ref::<std::io::Error>() {
+            Some(io) => io.raw_os_error(),
+            None => hdf5_errno(&cause.to_string()),
+        };
+        errno.is_some_and(|errno| STALE_HANDLE_ERRNOS.contains(&errno))
+    })
+}
+
+/// The `errno = N` in an HDF5 file driver's error message.
+fn hdf5_errno(message: &str) -> Option<i32> {
+    let (_, rest) = message.split_once("errno = ")?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// The first filter in `dataset`'s pipeline that HDF5 can't load, if any.
+fn missing_filter(dataset: &hdf5::Dataset) -> Option<MissingFilter> {
+    let filter = dataset.filters().into_iter().find(|f| !f.is_available())?;
+    let id = filter.id();
+    Some(MissingFilter {
+        id,
+        name: filter_name(id).map_or_else(|| format!("filter {id}"), str::to_string),
+        dataset: dataset.name(),
+        plugin_path: plugin_path(),
+    })
+}
+
+/// Common name of an HDF5 filter, built in or registered plugin, by id.
+fn filter_name(id: i32) -> Option<&'static str> {
+    Some(match id {
+        1 => "deflate",
+        2 => "shuffle",
+        3 => "fletcher32",
+        4 => "szip",
+        5 => "nbit",
+        6 => "scaleoffset",
+        307 => "bzip2",
+        32000 => "lzf",
+        32001 => "blosc",
+        32004 => "lz4",
+        32008 => "bitshuffle",
+        32013 => "zfp",
+        32015 => "zstd",
+        32026 => "blosc2",
+        _ => return None,
+    })
+}
+
+/// The directories HDF5 searches for filter plugins, `:`-separated: the
+/// `HDF5_PLUGIN_PATH` setting if there is one, else the library default.
+pub fn plugin_path() -> String {
+    use hdf5_sys::h5pl::{H5PLget, H5PLsize};
+
+    // SAFETY: each buffer is allocated with the length HDF5 reported for
+    // that entry plus its terminator, and only that length is passed.
+    let paths = hdf5::sync::sync(|| unsafe {
+        let mut count = 0;
+        if H5PLsize(&mut count) < 0 {
+            return Vec::new();
+        }
+        (0..count)
+            .filter_map(|i| {
+                let len = H5PLget(i, std::ptr::null_mut(), 0);
+                let len = usize::try_from(len).ok()?;
+                let mut buf = vec![0u8; len + 1];
+                if H5PLget(i, buf.as_mut_ptr().cast(), buf.len()) < 0 {
+                    return None;
+                }
+                buf.truncate(len);
+                String::from_utf8(buf).ok()
+            })
+            .collect()
+    });
+    if paths.is_empty() {
+        "(none)".to_string()
+    } else {
+        paths.join(":")
+    }
+}
+
 fn read_scalar_f64(group: &hdf5::Group, name: &str) -> Option<f64> {
     let ds = group.dataset(name).ok()?;
     ds.read_scalar::<f64>()
@@ -223,6 +2385,71 @@ fn read_scalar_f64(group: &hdf5::Group, name: &str) -> Option<f64> {
         .ok()
 }
 
+fn read_scalar_string(group: &hdf5::Group, name: &str) -> Option<String> {
+    use hdf5::types::{FixedAscii, VarLenAscii, VarLenUnicode};
+    let ds = group.dataset(name).ok()?;
+    ds.read_scalar::<VarLenUnicode>()
+        .map(|s| s.as_str().to_owned())
+        .or_else(|_| {
+            ds.read_scalar::<VarLenAscii>()
+                .map(|s| s.as_str().to_owned())
+        })
+        .or_else(|_| {
+            ds.read_scalar::<FixedAscii<256>>()
+                .map(|s| s.as_str().to_owned())
+        })
+        .ok()
+}
+
+/// Read a flag stored as an integer or a `"true"`/`"false"` string.
+fn read_scalar_bool(group: &hdf5::Group, name: &str) -> Option<bool> {
+    use hdf5::types::VarLenUnicode;
+    let ds = group.dataset(name).ok()?;
+    if let Ok(v) = ds.read_scalar::<i64>() {
+        return Some(v != 0);
+    }
+    let s = ds.read_scalar::<VarLenUnicode>().ok()?;
+    match s.as_str().trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" => Some(true),
+        "false" | "0" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn read_scalar_usize(group: &hdf5::Group, name: &str) -> Option<usize> {
+    let ds = group.dataset(name).ok()?;
+    ds.read_scalar::<u64>()
+        .or_else(|_| ds.read_scalar::<i64>().map(|v| v.max(0) as u64))
+        .ok()
+        .map(|v| v as usize)
+}
+
+fn read_1d_f64(ds: &hdf5::Dataset) -> Option<Vec<f64>> {
+    ds.read_raw::<f64>()
+        .or_else(|_| {
+            ds.read_raw::<f32>()
+                .map(|v| v.into_iter().map(f64::from).collect())
+        })
+        .ok()
+}
+
+/// Read a string or array-of-strings attribute from a group.
+fn read_attr_strings(group: &hdf5::Group, attr_name: &str) -> Option<Vec<String>> {
+    use hdf5::types::{FixedAscii, VarLenAscii, VarLenUnicode};
+    let attr = group.attr(attr_name).ok()?;
+    attr.read_raw::<VarLenUnicode>()
+        .map(|v| v.iter().map(|s| s.as_str().to_owned()).collect())
+        .or_else(|_| {
+            attr.read_raw::<VarLenAscii>()
+                .map(|v| v.iter().map(|s| s.as_str().to_owned()).collect())
+        })
+        .or_else(|_| {
+            attr.read_raw::<FixedAscii<64>>()
+                .map(|v| v.iter().map(|s| s.as_str().to_owned()).collect())
+        })
+        .ok()
+}
+
 fn read_dataset_attr_string(ds: &hdf5::Dataset, attr_name: &str) -> Option<String> {
     use hdf5::types::{FixedAscii, VarLenAscii, VarLenUnicode};
     let attr = ds.attr(attr_name).ok()?;
@@ -239,7 +2466,748 @@ fn read_dataset_attr_string(ds: &hdf5::Dataset, attr_name: &str) -> Option<Strin
         .ok()
 }
 
-/// E (keV) = hc / λ, with hc = 12.398419843 keV·Å
+/// E (keV) = hc / λ
 fn wavelength_to_energy_kev(wavelength_angstrom: f64) -> f64 {
-    12.398_419_843 / wavelength_angstrom
+    HC_KEV_ANGSTROM / wavelength_angstrom
+}
+
+/// λ (Å) = hc / E
+fn energy_kev_to_wavelength_angstrom(energy_kev: f64) -> f64 {
+    HC_KEV_ANGSTROM / energy_kev
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Masked or overloaded.
+    const M: u16 = u16::MAX;
+
+    const TRUSTED: TrustedRange = TrustedRange {
+        min: 0.0,
+        max: 200.0,
+    };
+
+    /// Write `values` as a one-row, single-frame image dataset and read it
+    /// back through [`read_nxs_pixels`].
+    fn read_converted<T: hdf5::H5Type + Clone>(values: &[T], trusted: TrustedRange) -> Vec<u16> {
+        let dir = tempfile::tempdir().unwrap();
+        let file = hdf5::File::create(dir.path().join("frame.h5")).unwrap();
+        let data = ndarray::Array3::from_shape_vec((1, 1, values.len()), values.to_vec()).unwrap();
+        let dataset = file
+            .new_dataset_builder()
+            .with_data(&data)
+            .create("entry/data/data")
+            .unwrap();
+        let slice = FrameSlice::whole(&dataset, 0);
+        read_nxs_pixels(&dataset, &dataset.dtype().unwrap(), trusted, &slice).unwrap()
+    }
+
+    #[test]
+    fn converts_u8() {
+        assert_eq!(
+            read_converted::<u8>(&[0, 7, 200, 201], TRUSTED),
+            [0, 7, 200, M]
+        );
+    }
+
+    #[test]
+    fn converts_i8() {
+        assert_eq!(
+            read_converted::<i8>(&[-1, 0, 7, 100], TRUSTED),
+            [M, 0, 7, 100]
+        );
+    }
+
+    #[test]
+    fn converts_u16() {
+        assert_eq!(
+            read_converted::<u16>(&[0, 7, 200, 201], TRUSTED),
+            [0, 7, 200, M]
+        );
+    }
+
+    #[test]
+    fn passes_u16_through_a_full_range() {
+        let values = [0, 7, u16::MAX - 1, u16::MAX];
+        assert_eq!(
+            read_converted::<u16>(&values, TrustedRange::default()),
+            values
+        );
+    }
+
+    #[test]
+    fn converts_i16() {
+        assert_eq!(
+            read_converted::<i16>(&[-1, 7, 200, 201], TRUSTED),
+            [M, 7, 200, M]
+        );
+    }
+
+    #[test]
+    fn converts_u32() {
+        assert_eq!(
+            read_converted::<u32>(&[0, 7, 201, u32::MAX], TRUSTED),
+            [0, 7, M, M]
+        );
+    }
+
+    #[test]
+    fn converts_i32() {
+        assert_eq!(
+            read_converted::<i32>(&[-2, 7, 200, 70000], TRUSTED),
+            [M, 7, 200, M]
+        );
+    }
+
+    #[test]
+    fn converts_u64() {
+        assert_eq!(
+            read_converted::<u64>(&[0, 7, 200, 201], TRUSTED),
+            [0, 7, 200, M]
+        );
+    }
+
+    #[test]
+    fn converts_i64() {
+        assert_eq!(
+            read_converted::<i64>(&[-1, 0, 7, 201], TRUSTED),
+            [M, 0, 7, M]
+        );
+    }
+
+    #[test]
+    fn converts_f32() {
+        let values = [-0.5, 2.4, 2.6, f32::NAN];
+        assert_eq!(read_converted::<f32>(&values, TRUSTED), [M, 2, 3, M]);
+    }
+
+    #[test]
+    fn converts_f64() {
+        let values = [-0.5, 199.6, 200.5, f64::NAN];
+        assert_eq!(read_converted::<f64>(&values, TRUSTED), [M, 200, M, M]);
+    }
+
+    #[test]
+    fn masks_values_below_trusted_min() {
+        let trusted = TrustedRange {
+            min: 5.0,
+            max: 200.0,
+        };
+        assert_eq!(read_converted::<u16>(&[4, 5], trusted), [M, 5]);
+        assert_eq!(read_converted::<i32>(&[4, 5], trusted), [M, 5]);
+    }
+
+    #[test]
+    fn saturates_above_u16_within_the_trusted_range() {
+        let trusted = TrustedRange { min: 0.0, max: 1e9 };
+        assert_eq!(
+            read_converted::<u32>(&[0, 100, 70000], trusted),
+            [0, 100, M]
+        );
+    }
+
+    /// Write a single-frame `(1, 2, 3)` image file at `path`, with the
+    /// (empty) detector group metadata needs.
+    fn write_frame(path: &Path, values: [u16; 6]) {
+        let file = hdf5::File::create(path).unwrap();
+        file.create_group("entry/instrument/detector").unwrap();
+        let data = ndarray::Array3::from_shape_vec((1, 2, 3), values.to_vec()).unwrap();
+        file.new_dataset_builder()
+            .with_data(&data)
+            .create("entry/data/data")
+            .unwrap();
+    }
+
+    #[test]
+    fn transposes_a_square_mask_with_the_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = hdf5::File::create(dir.path().join("square.h5")).unwrap();
+        file.new_dataset_builder()
+            .with_data(&ndarray::Array3::<u16>::zeros((1, 2, 2)))
+            .create("entry/data/data")
+            .unwrap();
+        file.new_dataset_builder()
+            .with_data(&ndarray::arr2(&[[0i32, 1], [0, 0]]))
+            .create("entry/instrument/detector/pixel_mask")
+            .unwrap();
+
+        let mask = |transposed| read_nxs_mask(&file, "entry/data/data", transposed).unwrap();
+        assert_eq!(mask(false), Some((vec![0, 1, 0, 0], 2, 2)));
+        assert_eq!(mask(true), Some((vec![0, 0, 1, 0], 2, 2)));
+    }
+
+    #[test]
+    fn falls_back_to_a_sibling_mask_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data_master.h5");
+        write_frame(&path, [1; 6]);
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+        assert_eq!(reader.mask().unwrap(), None);
+
+        write_frame(&dir.path().join("data_mask.h5"), [0, 0, 1, 0, 9, 0]);
+        let expected = vec![0, 0, 1, 0, 1, 0];
+        assert_eq!(reader.mask().unwrap(), Some((expected, 3, 2)));
+
+        // A 2D pixel_mask, as the detector's own would be stored.
+        let file = hdf5::File::create(dir.path().join("data_mask.h5")).unwrap();
+        file.new_dataset_builder()
+            .with_data(&ndarray::arr2(&[[0u32, 4, 0], [1, 0, 0]]))
+            .create("pixel_mask")
+            .unwrap();
+        drop(file);
+        let expected = vec![0, 1, 0, 1, 0, 0];
+        assert_eq!(reader.mask().unwrap(), Some((expected, 3, 2)));
+    }
+
+    fn assert_matrix_eq(actual: [[f64; 3]; 3], expected: [[f64; 3]; 3]) {
+        for (a, e) in actual.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((a - e).abs() < 1e-12, "{actual:?} != {expected:?}");
+        }
+    }
+
+    fn assert_vec_eq(actual: [f64; 3], expected: [f64; 3]) {
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} != {expected:?}");
+        }
+    }
+
+    fn rotation(vector: [f64; 3], degrees: f64) -> Transformation {
+        Transformation {
+            is_translation: false,
+            vector: normalize(vector),
+            value: degrees.to_radians(),
+            offset_mm: [0.0; 3],
+        }
+    }
+
+    #[test]
+    fn rotates_about_a_principal_axis() {
+        let affine = rotation([0.0, 0.0, 1.0], 90.0).to_affine();
+        let expected = [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_matrix_eq(affine.rotation, expected);
+        assert_vec_eq(affine.translation, [0.0; 3]);
+    }
+
+    #[test]
+    fn rotates_about_a_diagonal_axis() {
+        // A third of a turn about (1, 1, 1) takes x to y, y to z and z to x.
+        let affine = rotation([1.0, 1.0, 1.0], 120.0).to_affine();
+        let expected = [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        assert_matrix_eq(affine.rotation, expected);
+    }
+
+    #[test]
+    fn translates_along_the_vector_then_offsets() {
+        let affine = Transformation {
+            is_translation: true,
+            vector: [1.0, 0.0, 0.0],
+            value: 5.0,
+            offset_mm: [0.0, 0.0, 2.0],
+        }
+        .to_affine();
+        assert_matrix_eq(affine.rotation, Affine::IDENTITY.rotation);
+        assert_vec_eq(affine.translation, [5.0, 0.0, 2.0]);
+    }
+
+    #[test]
+    fn composes_inner_transformation_first() {
+        let rotate = rotation([0.0, 0.0, 1.0], 90.0).to_affine();
+        let translate = Affine {
+            rotation: Affine::IDENTITY.rotation,
+            translation: [5.0, 0.0, 0.0],
+        };
+        let p = [1.0, 0.0, 0.0];
+        assert_vec_eq(rotate.then(&translate).apply_point(p), [5.0, 1.0, 0.0]);
+        assert_vec_eq(translate.then(&rotate).apply_point(p), [0.0, 6.0, 0.0]);
+        // Vectors are only rotated.
+        assert_vec_eq(rotate.then(&translate).apply_vector(p), [0.0, 1.0, 0.0]);
+    }
+
+    /// Write a one-value `NXtransformations` axis `name` in `group`.
+    fn write_axis(
+        group: &hdf5::Group,
+        name: &str,
+        kind: &str,
+        vector: [f64; 3],
+        value: f64,
+    ) -> hdf5::Dataset {
+        let ds = group
+            .new_dataset_builder()
+            .with_data(&ndarray::arr1(&[value]))
+            .create(name)
+            .unwrap();
+        write_string_attr(&ds, "transformation_type", kind);
+        write_string_attr(&ds, "units", if kind == "rotation" { "deg" } else { "mm" });
+        ds.new_attr::<f64>()
+            .shape(3)
+            .create("vector")
+            .unwrap()
+            .write(&vector)
+            .unwrap();
+        ds
+    }
+
+    fn write_string_attr(ds: &hdf5::Dataset, name: &str, value: &str) {
+        let value: hdf5::types::VarLenUnicode = value.parse().unwrap();
+        ds.new_attr::<hdf5::types::VarLenUnicode>()
+            .create(name)
+            .unwrap()
+            .write_scalar(&value)
+            .unwrap();
+    }
+
+    #[test]
+    fn resolves_a_depends_on_chain_from_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = hdf5::File::create(dir.path().join("chain.h5")).unwrap();
+        let instrument = file.create_group("entry/instrument").unwrap();
+        let t = instrument.create_group("transformations").unwrap();
+        let det_z = write_axis(&t, "det_z", "translation", [0.0, 0.0, 1.0], 200.0);
+        write_string_attr(&det_z, "depends_on", "two_theta");
+        let two_theta = write_axis(&t, "two_theta", "rotation", [1.0, 0.0, 0.0], 90.0);
+        write_string_attr(&two_theta, "depends_on", ".");
+        let module = file
+            .new_dataset_builder()
+            .with_data(&ndarray::arr1(&[0.0]))
+            .create("entry/instrument/detector/module/fast_pixel_direction")
+            .unwrap();
+        write_string_attr(&module, "depends_on", &det_z.name());
+
+        // 200 mm along z, then a quarter turn about x takes z to -y.
+        let to_lab = resolve_depends_on(&file, &module).unwrap();
+        let expected = [[1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]];
+        assert_matrix_eq(to_lab.rotation, expected);
+        assert_vec_eq(to_lab.apply_point([0.0; 3]), [0.0, -200.0, 0.0]);
+    }
+
+    #[test]
+    fn finds_angles_that_disagree_with_the_increment() {
+        let angles = [10.0, 10.25, 10.52, 10.75];
+        assert_eq!(first_off_increment(&angles, 0.25), None);
+        assert_eq!(
+            first_off_increment(&[10.0, 10.25, 11.0, 10.75], 0.25),
+            Some((2, 10.5))
+        );
+        assert_eq!(first_off_increment(&[10.0, 9.75, 9.5], -0.25), None);
+
+        // The per-frame angles are kept even when the increment disagrees.
+        let dir = tempfile::tempdir().unwrap();
+        let file = hdf5::File::create(dir.path().join("scan.h5")).unwrap();
+        let t = file.create_group("entry/sample/transformations").unwrap();
+        let omega = t
+            .new_dataset_builder()
+            .with_data(&ndarray::arr1(&[0.0, 0.1, 0.5]))
+            .create("omega")
+            .unwrap();
+        write_string_attr(&omega, "transformation_type", "rotation");
+        write_scalar(&t, "omega_increment_set", 0.1);
+        let (_, angles) = read_scan_axis(&file, 3).unwrap();
+        assert_eq!(angles, [0.0, 0.1, 0.5]);
+    }
+
+    /// Write a scalar `value` as dataset `name` in `group`.
+    fn write_scalar<T: hdf5::H5Type>(group: &hdf5::Group, name: &str, value: T) -> hdf5::Dataset {
+        let ds = group.new_dataset::<T>().create(name).unwrap();
+        ds.write_scalar(&value).unwrap();
+        ds
+    }
+
+    /// The metadata of a blank frame whose detector group is filled in by
+    /// `write_detector`.
+    fn detector_metadata(write_detector: impl FnOnce(&hdf5::Group)) -> ImageMetadata {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.h5");
+        write_frame(&path, [0; 6]);
+        let file = hdf5::File::open_rw(&path).unwrap();
+        write_detector(&file.group("entry/instrument/detector").unwrap());
+        drop(file);
+        NxsReader::open(&path, Vfd::Default, None)
+            .unwrap()
+            .metadata()
+            .unwrap()
+    }
+
+    fn text(value: &str) -> hdf5::types::VarLenUnicode {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn reads_the_detector_description_and_sensor() {
+        let metadata = detector_metadata(|detector| {
+            write_scalar(detector, "description", text(" Dectris Eiger2 XE 16M "));
+            write_scalar(detector, "sensor_material", text("Si"));
+            let thickness = write_scalar(detector, "sensor_thickness", 450.0);
+            write_string_attr(&thickness, "units", "um");
+        });
+        let description = metadata.detector_description.as_deref();
+        assert_eq!(description, Some("Dectris Eiger2 XE 16M"));
+        assert_eq!(metadata.sensor_material.as_deref(), Some("Si"));
+        assert_eq!(metadata.sensor_thickness_mm, Some(0.45));
+    }
+
+    #[test]
+    fn reads_sensor_thickness_in_metres_by_default() {
+        let metadata = detector_metadata(|detector| {
+            write_scalar(detector, "description", text(""));
+            write_scalar(detector, "sensor_thickness", 0.00032);
+        });
+        assert_eq!(metadata.detector_description, None);
+        assert_eq!(metadata.sensor_material, None);
+        let thickness = metadata.sensor_thickness_mm.unwrap();
+        assert!((thickness - 0.32).abs() < 1e-12, "{thickness}");
+    }
+
+    #[test]
+    fn ignores_a_zero_sensor_thickness() {
+        let metadata = detector_metadata(|detector| {
+            write_scalar(detector, "sensor_thickness", 0.0);
+        });
+        assert_eq!(metadata.sensor_thickness_mm, None);
+    }
+
+    #[test]
+    fn orients_a_portrait_mounted_detector() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("portrait.h5");
+        write_frame(&path, [0, 1, 2, 3, 4, 5]);
+        let file = hdf5::File::open_rw(&path).unwrap();
+        let detector = file.group("entry/instrument/detector").unwrap();
+        write_scalar(&detector, "detector_orientation", text("portrait"));
+        write_scalar(&detector, "x_pixel_size", 0.0001);
+        write_scalar(&detector, "y_pixel_size", 0.0002);
+        write_scalar(&detector, "beam_center_x", 2.0);
+        write_scalar(&detector, "beam_center_y", 0.5);
+        drop(file);
+
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+        assert_eq!(reader.applied_transforms(), ["transpose"]);
+        let pixels = reader.read_frame(0).unwrap();
+        assert_eq!(pixels, (vec![0, 3, 1, 4, 2, 5], 2, 3));
+        let metadata = reader.metadata().unwrap();
+        assert_eq!(metadata.panel_size_fast_slow, [2, 3]);
+        assert_eq!(metadata.beam_center, [0.5, 2.0]);
+        let pixel_size = metadata.pixel_size;
+        assert!((pixel_size - 0.2).abs() < 1e-12, "{pixel_size}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ignores_empty_time_and_distance_arrays() {
+        let metadata = detector_metadata(|detector| {
+            for name in ["count_time", "frame_time", "distance"] {
+                detector.new_dataset::<f64>().shape(0).create(name).unwrap();
+            }
+        });
+        assert_eq!(metadata.count_time_s, None);
+        assert_eq!(metadata.frame_time_s, None);
+        assert_eq!(metadata.panel_distance_mm, 0.0);
+    }
+
+    #[test]
+    fn only_stale_handles_are_retried() {
+        let os = |errno| anyhow::Error::from(std::io::Error::from_raw_os_error(errno));
+        assert!(is_stale_handle(&os(libc::ESTALE)));
+        let wrapped = os(libc::EBADF).context("Failed to read frame 3");
+        assert!(is_stale_handle(&wrapped));
+        assert!(!is_stale_handle(&os(libc::ENOENT)));
+
+        let driver = |errno| {
+            anyhow!(
+                "H5FD_sec2_read(): file read failed: time = Wed Oct 14 12:00:00 2026, \
+                 filename = 'data.h5', errno = {errno}, error message = '...'"
+            )
+        };
+        assert!(is_stale_handle(&driver(libc::ESTALE)));
+        assert!(!is_stale_handle(&driver(libc::EIO)));
+        assert!(!is_stale_handle(&anyhow!("unable to open dataset")));
+
+        // As `read_u16_frame_into` reports a failed read: the HDF5 error,
+        // whose message ends with the driver's, under the frame being read.
+        let read = |errno| {
+            let message = format!(
+                "H5Dread(): can't read data: file read failed: filename = 'data.h5', \
+                 errno = {errno}, error message = '...'"
+            );
+            anyhow::Error::new(hdf5::Error::from(message))
+                .context("Failed to read frame 3 of /entry/data/data")
+        };
+        assert!(is_stale_handle(&read(libc::EBADF)));
+        assert!(!is_stale_handle(&read(libc::EIO)));
+    }
+
+    #[test]
+    fn reads_a_per_frame_distance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.h5");
+        write_frame(&path, [0; 6]);
+        let file = hdf5::File::open_rw(&path).unwrap();
+        let distance = file
+            .new_dataset_builder()
+            .with_data(&ndarray::arr1(&[150.0, 175.0]))
+            .create("entry/instrument/detector/distance")
+            .unwrap();
+        write_string_attr(&distance, "units", "mm");
+        drop(file);
+
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+        assert_eq!(reader.metadata().unwrap().panel_distance_mm, 150.0);
+        assert_eq!(reader.frame_metadata(0).unwrap().distance_mm, Some(150.0));
+        // The frame index check is the reader's; the file has one frame.
+        let metadata = read_nxs_frame_metadata(&reader.file().unwrap(), "entry/data/data", 1);
+        assert_eq!(metadata.distance_mm, Some(175.0));
+    }
+
+    #[test]
+    fn reads_a_per_frame_wavelength() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.h5");
+        write_frame(&path, [0; 6]);
+        let file = hdf5::File::open_rw(&path).unwrap();
+        let wavelength = file
+            .new_dataset_builder()
+            .with_data(&ndarray::arr1(&[0.1, 0.05]))
+            .create("entry/instrument/beam/incident_wavelength")
+            .unwrap();
+        write_string_attr(&wavelength, "units", "nm");
+        drop(file);
+
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+        let energy = reader.metadata().unwrap().beam_energy_kev.unwrap();
+        assert!((energy - HC_KEV_ANGSTROM).abs() < 1e-9, "{energy}");
+        let frame0 = reader.frame_metadata(0).unwrap();
+        assert_eq!(frame0.incident_energy_kev, Some(energy));
+        let metadata = read_nxs_frame_metadata(&reader.file().unwrap(), "entry/data/data", 1);
+        let wavelength = metadata.wavelength_angstrom.unwrap();
+        assert!((wavelength - 0.5).abs() < 1e-9, "{wavelength}");
+        assert!((metadata.incident_energy_kev.unwrap() - 2.0 * energy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reads_every_frame_time_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.h5");
+        write_frame(&path, [0; 6]);
+        let file = hdf5::File::open_rw(&path).unwrap();
+        let timestamp = file
+            .new_dataset_builder()
+            .with_data(&ndarray::arr1(&[0.0, 500.0]))
+            .create("entry/data/timestamp")
+            .unwrap();
+        write_string_attr(&timestamp, "units", "ms");
+        drop(file);
+
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+        assert_eq!(reader.frame_times().unwrap(), Some(vec![0.0]));
+        let file = reader.file().unwrap();
+        let times = read_nxs_frame_times(&file, "entry/data/data", 2);
+        assert_eq!(times, Some(vec![0.0, 0.5]));
+        // A frame without a timestamp means none are listed.
+        assert_eq!(read_nxs_frame_times(&file, "entry/data/data", 3), None);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mapped_reads_match_hdf5_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contiguous.h5");
+        let file = hdf5::File::create(&path).unwrap();
+        file.create_group("entry/instrument/detector").unwrap();
+        let data = ndarray::Array3::from_shape_fn((3, 4, 5), |(f, y, x)| {
+            // Sixty distinct values, some far outside the trusted range.
+            ((f * 20 + y * 5 + x) as u16).wrapping_mul(1093)
+        });
+        file.new_dataset_builder()
+            .with_data(&data)
+            .create("entry/data/data")
+            .unwrap();
+        drop(file);
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+        let dataset = reader.file().unwrap().dataset("entry/data/data").unwrap();
+        let dtype = dataset.dtype().unwrap();
+
+        let mut buf = vec![9; 100];
+        for frame in 0..3 {
+            let mut mapped = Vec::new();
+            assert!(read_frame_mapped(&dataset, &dtype, frame, &mut mapped).unwrap());
+            let slice = FrameSlice::whole(&dataset, frame);
+            let expected = read_nxs_pixels(&dataset, &dtype, reader.trusted, &slice).unwrap();
+            assert_eq!(reader.read_frame(frame).unwrap(), (expected.clone(), 5, 4));
+            assert_eq!(reader.read_frame_into(frame, &mut buf).unwrap(), (5, 4));
+            assert_eq!(buf, expected);
+        }
+    }
+
+    #[test]
+    fn read_frame_into_matches_read_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.h5");
+        let file = hdf5::File::create(&path).unwrap();
+        file.create_group("entry/instrument/detector").unwrap();
+        let data =
+            ndarray::Array3::from_shape_fn((2, 3, 4), |(f, y, x)| (f * 12 + y * 4 + x) as u16);
+        file.new_dataset_builder()
+            .with_data(&data)
+            .chunk((1, 3, 4))
+            .create("entry/data/data")
+            .unwrap();
+        let signed = data.mapv(|v| i32::from(v) * 10_000 - 5);
+        file.new_dataset_builder()
+            .with_data(&signed)
+            .create("entry/data/signed")
+            .unwrap();
+        drop(file);
+
+        // u16 frames are read straight into the buffer, others converted.
+        for data_path in ["entry/data/data", "entry/data/signed"] {
+            let reader = NxsReader::open(&path, Vfd::Default, Some(data_path)).unwrap();
+            // Stale contents, too long and then too short for a frame.
+            for mut buf in [vec![7; 50], vec![7; 1]] {
+                for frame in 0..2 {
+                    let (pixels, width, height) = reader.read_frame(frame).unwrap();
+                    let size = reader.read_frame_into(frame, &mut buf).unwrap();
+                    assert_eq!(size, (width, height));
+                    assert_eq!(buf, pixels);
+                }
+            }
+        }
+    }
+
+    /// Per-frame read time through the kept-open handle against reopening
+    /// the file for every read, as `NxsReader` used to. Timing-dependent, so
+    /// run on demand: `cargo test --release -- --ignored --nocapture bench_cached_handle`.
+    #[test]
+    #[ignore]
+    fn bench_cached_handle_against_reopening() {
+        const FRAMES: usize = 200;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.h5");
+        let file = hdf5::File::create(&path).unwrap();
+        file.create_group("entry/instrument/detector").unwrap();
+        let data = ndarray::Array3::<u16>::from_elem((FRAMES, 256, 256), 7);
+        file.new_dataset_builder()
+            .with_data(&data)
+            .chunk((1, 256, 256))
+            .create("entry/data/data")
+            .unwrap();
+        drop(file);
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+
+        let time_reads = |reopen: bool| {
+            let t0 = std::time::Instant::now();
+            for frame in 0..FRAMES {
+                if reopen {
+                    reader.handle.lock().unwrap().take();
+                }
+                reader.read_frame(frame).unwrap();
+            }
+            t0.elapsed() / FRAMES as u32
+        };
+        let reopened = time_reads(true);
+        let cached = time_reads(false);
+        eprintln!("per frame: reopening {reopened:?}, cached handle {cached:?}");
+        assert!(cached < reopened);
+    }
+
+    /// A `frames` x `height` x `width` scan of varying values, chunked
+    /// `depth` frames deep.
+    fn write_chunked_scan(path: &Path, frames: usize, depth: usize, size: usize, deflate: bool) {
+        let file = hdf5::File::create(path).unwrap();
+        file.create_group("entry/instrument/detector").unwrap();
+        let data = ndarray::Array3::<u16>::from_shape_fn((frames, size, size), |(f, y, x)| {
+            ((f * 7 + y * 3 + x) % 1000) as u16
+        });
+        let builder = file
+            .new_dataset_builder()
+            .with_data(&data)
+            .chunk((depth, size, size));
+        let builder = if deflate { builder.deflate(4) } else { builder };
+        builder.create("entry/data/data").unwrap();
+    }
+
+    /// Reads an [`NxsReader`]'s frames one at a time, as readers without
+    /// [`Reader::read_frames`] do.
+    struct FrameByFrame<'a>(&'a NxsReader);
+
+    impl Reader for FrameByFrame<'_> {
+        fn format_name(&self) -> &'static str {
+            self.0.format_name()
+        }
+
+        fn path(&self) -> &Path {
+            self.0.path()
+        }
+
+        fn metadata(&self) -> Result<ImageMetadata> {
+            self.0.metadata()
+        }
+
+        fn frame_count(&self) -> Result<usize> {
+            self.0.frame_count()
+        }
+
+        fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+            self.0.read_frame(frame)
+        }
+    }
+
+    fn sum(reader: &dyn Reader, frames: Range<usize>) -> Vec<u32> {
+        let cancel = crate::CancelToken::new(&Default::default());
+        let frames: Vec<usize> = frames.collect();
+        let projection = crate::stats::project(
+            reader,
+            &frames,
+            crate::stats::ProjectionOp::Sum,
+            f64::from(u16::MAX - 1),
+            &cancel,
+        )
+        .unwrap();
+        match projection.pixels {
+            crate::stats::ProjectedPixels::U32(pixels) => pixels,
+            crate::stats::ProjectedPixels::U16(_) => panic!("a sum is u32"),
+        }
+    }
+
+    #[test]
+    fn projects_chunks_of_several_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.h5");
+        write_chunked_scan(&path, 20, 3, 4, false);
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+        assert_eq!(reader.frames_per_chunk(), 3);
+
+        let (pixels, width, height) = reader.read_frames(4..7).unwrap();
+        assert_eq!((width, height), (4, 4));
+        let expected: Vec<u16> = (4..7)
+            .flat_map(|f| reader.read_frame(f).unwrap().0)
+            .collect();
+        assert_eq!(pixels, expected);
+
+        // Starting and ending mid-chunk.
+        assert_eq!(sum(&reader, 2..17), sum(&FrameByFrame(&reader), 2..17));
+    }
+
+    /// Projection time reading each chunk of a chunked, compressed scan once
+    /// against reading it again for every frame. Timing-dependent, so run on
+    /// demand: `cargo test --release -- --ignored --nocapture bench_projecting`.
+    #[test]
+    #[ignore]
+    fn bench_projecting_a_chunk_at_a_time() {
+        const FRAMES: usize = 1000;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.h5");
+        write_chunked_scan(&path, FRAMES, 10, 256, true);
+        let reader = NxsReader::open(&path, Vfd::Default, None).unwrap();
+
+        let time_sum = |reader: &dyn Reader| {
+            let t0 = std::time::Instant::now();
+            let pixels = sum(reader, 0..FRAMES);
+            (t0.elapsed(), pixels)
+        };
+        let (framewise, expected) = time_sum(&FrameByFrame(&reader));
+        let (chunkwise, pixels) = time_sum(&reader);
+        eprintln!("{FRAMES} frames: frame at a time {framewise:?}, chunk at a time {chunkwise:?}");
+        assert_eq!(pixels, expected);
+        assert!(chunkwise < framewise);
+    }
 }
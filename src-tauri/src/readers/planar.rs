@@ -0,0 +1,253 @@
+//! Detectors that store their rows planar: all the even rows of a frame,
+//! then all the odd ones, so read as is the image comes out as two squashed
+//! copies of itself.
+//!
+//! [`PlanarReader`] puts the rows back in order. It wraps a reader when
+//! [`super::OpenOptions::planar_rows`] says to, or, left unset, when the
+//! file's detector description contains one of
+//! [`super::OpenOptions::planar_detectors`]. The mask and gain map are
+//! stored like the frames and reordered alike; stored chunks aren't served,
+//! as a client decompressing them would get the planar rows.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tracing::info;
+
+use super::{FrameMetadata, GainMap, ImageMetadata, Reader};
+
+/// Whether frames described by `description` are stored planar: `planar`
+/// if given, else whether the description contains any of `detectors`,
+/// ignoring case.
+pub fn is_planar(planar: Option<bool>, description: Option<&str>, detectors: &[String]) -> bool {
+    planar.unwrap_or_else(|| {
+        let Some(description) = description else {
+            return false;
+        };
+        let description = description.to_lowercase();
+        detectors
+            .iter()
+            .filter(|detector| !detector.trim().is_empty())
+            .any(|detector| description.contains(&detector.trim().to_lowercase()))
+    })
+}
+
+/// Reorder the rows of a `width` x `height` image stored planar, in place.
+pub fn deinterleave_rows<T: Copy>(pixels: &mut [T], width: usize, height: usize) {
+    let stored = pixels.to_vec();
+    let evens = height.div_ceil(2);
+    for (y, row) in pixels.chunks_exact_mut(width).enumerate() {
+        let from = if y % 2 == 0 { y / 2 } else { evens + y / 2 };
+        row.copy_from_slice(&stored[from * width..(from + 1) * width]);
+    }
+}
+
+/// Wraps a reader of a planar detector, serving its frames in row order.
+pub struct PlanarReader {
+    inner: Box<dyn Reader>,
+}
+
+impl PlanarReader {
+    pub fn new(inner: Box<dyn Reader>) -> Self {
+        info!("planar: reordering the rows of {}", inner.path().display());
+        Self { inner }
+    }
+}
+
+impl Reader for PlanarReader {
+    fn format_name(&self) -> &'static str {
+        self.inner.format_name()
+    }
+
+    fn path(&self) -> &Path {
+        self.inner.path()
+    }
+
+    fn dataset_path(&self) -> Option<&str> {
+        self.inner.dataset_path()
+    }
+
+    fn frame_source(&self, frame: usize) -> Result<(PathBuf, usize)> {
+        self.inner.frame_source(frame)
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        self.inner.metadata()
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        self.inner.frame_count()
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        let (mut pixels, width, height) = self.inner.read_frame(frame)?;
+        deinterleave_rows(&mut pixels, width, height);
+        Ok((pixels, width, height))
+    }
+
+    fn read_frame_into(&self, frame: usize, buf: &mut Vec<u16>) -> Result<(usize, usize)> {
+        let (width, height) = self.inner.read_frame_into(frame, buf)?;
+        deinterleave_rows(buf, width, height);
+        Ok((width, height))
+    }
+
+    fn read_frame_f32(&self, frame: usize) -> Result<(Vec<f32>, usize, usize)> {
+        let (mut pixels, width, height) = self.inner.read_frame_f32(frame)?;
+        deinterleave_rows(&mut pixels, width, height);
+        Ok((pixels, width, height))
+    }
+
+    fn frames_per_chunk(&self) -> usize {
+        self.inner.frames_per_chunk()
+    }
+
+    fn read_frames(&self, frames: Range<usize>) -> Result<(Vec<u16>, usize, usize)> {
+        let (mut pixels, width, height) = self.inner.read_frames(frames)?;
+        for frame in pixels.chunks_exact_mut((width * height).max(1)) {
+            deinterleave_rows(frame, width, height);
+        }
+        Ok((pixels, width, height))
+    }
+
+    fn mask(&self) -> Result<Option<(Vec<u8>, usize, usize)>> {
+        let mut mask = self.inner.mask()?;
+        if let Some((mask, width, height)) = &mut mask {
+            deinterleave_rows(mask, *width, *height);
+        }
+        Ok(mask)
+    }
+
+    fn frame_angles(&self) -> Result<Option<Vec<f64>>> {
+        self.inner.frame_angles()
+    }
+
+    fn sources_available(&self) -> bool {
+        self.inner.sources_available()
+    }
+
+    fn frame_metadata(&self, frame: usize) -> Result<FrameMetadata> {
+        self.inner.frame_metadata(frame)
+    }
+
+    fn frame_times(&self) -> Result<Option<Vec<f64>>> {
+        self.inner.frame_times()
+    }
+
+    fn gain_map(&self) -> Result<Option<GainMap>> {
+        let mut gain_map = self.inner.gain_map()?;
+        if let Some(map) = &mut gain_map {
+            let (width, height) = (map.width, map.height);
+            for stage in map.gain.chunks_exact_mut((width * height).max(1)) {
+                deinterleave_rows(stage, width, height);
+            }
+            if let Some(pedestal) = &mut map.pedestal {
+                for stage in pedestal.chunks_exact_mut((width * height).max(1)) {
+                    deinterleave_rows(stage, width, height);
+                }
+            }
+        }
+        Ok(gain_map)
+    }
+
+    fn applied_transforms(&self) -> Vec<&'static str> {
+        let mut transforms = self.inner.applied_transforms();
+        transforms.push("planar_rows");
+        transforms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::OpenOptions;
+
+    /// Store a 3 frame `width` x `height` image of distinct pixels with its
+    /// rows planar, returning the image in row order.
+    fn write_planar(path: &Path, description: &str, width: usize, height: usize) -> Vec<u16> {
+        let image: Vec<u16> = (0..(3 * width * height) as u16).collect();
+        let mut stored = Vec::with_capacity(image.len());
+        for frame in image.chunks_exact(width * height) {
+            let rows: Vec<_> = frame.chunks_exact(width).collect();
+            stored.extend(rows.iter().step_by(2).flat_map(|row| row.iter()));
+            stored.extend(rows.iter().skip(1).step_by(2).flat_map(|row| row.iter()));
+        }
+        let file = hdf5::File::create(path).unwrap();
+        let detector = file.create_group("entry/instrument/detector").unwrap();
+        let text: hdf5::types::VarLenUnicode = description.parse().unwrap();
+        detector
+            .new_dataset::<hdf5::types::VarLenUnicode>()
+            .create("description")
+            .unwrap()
+            .write_scalar(&text)
+            .unwrap();
+        let data = ndarray::Array3::from_shape_vec((3, height, width), stored).unwrap();
+        file.new_dataset_builder()
+            .with_data(&data)
+            .chunk((3, height, width))
+            .create("entry/data/data")
+            .unwrap();
+        image
+    }
+
+    #[test]
+    fn reorders_the_rows_of_listed_detectors() {
+        let dir = tempfile::tempdir().unwrap();
+        // An odd height, so there is one more even row than odd.
+        let (width, height) = (4, 5);
+        let planar = dir.path().join("planar.h5");
+        let image = write_planar(&planar, "Example Planar 2M", width, height);
+        let options = OpenOptions {
+            planar_detectors: vec!["planar 2m".to_string()],
+            ..Default::default()
+        };
+        let reader = crate::readers::open(&planar, &options).unwrap();
+        assert_eq!(reader.applied_transforms(), ["planar_rows"]);
+        let frame_len = width * height;
+        assert_eq!(
+            reader.read_frame(1).unwrap(),
+            (image[frame_len..2 * frame_len].to_vec(), width, height)
+        );
+        assert_eq!(reader.read_frames(0..3).unwrap().0, image);
+        let region = reader.read_region(2, 1, 1, 2, 3).unwrap();
+        let expected: Vec<u16> = (1..4)
+            .flat_map(|y| {
+                let row = 2 * frame_len + y * width;
+                image[row + 1..row + 3].to_vec()
+            })
+            .collect();
+        assert_eq!(region, expected);
+
+        // Other detectors, and this one with the flag off, are left alone.
+        let other = dir.path().join("other.h5");
+        write_planar(&other, "Example Pixel 2M", width, height);
+        let stored = crate::readers::open(&other, &options)
+            .unwrap()
+            .read_frame(0)
+            .unwrap()
+            .0;
+        assert_ne!(stored, image[..frame_len]);
+        let options = OpenOptions {
+            planar_rows: Some(false),
+            ..options
+        };
+        let unordered = crate::readers::open(&planar, &options)
+            .unwrap()
+            .read_frame(0)
+            .unwrap()
+            .0;
+        assert_eq!(unordered, stored);
+
+        // And the flag alone reorders any detector.
+        let options = OpenOptions {
+            planar_rows: Some(true),
+            ..Default::default()
+        };
+        let reordered = crate::readers::open(&other, &options)
+            .unwrap()
+            .read_frame(0)
+            .unwrap()
+            .0;
+        assert_eq!(reordered, image[..frame_len]);
+    }
+}
@@ -0,0 +1,317 @@
+//! Reader for flat binary `.raw` frame dumps described by a JSON sidecar.
+//!
+//! Detector-development tools write frames back to back, optionally after a
+//! fixed-size header, with no framing of their own. The layout comes from
+//! `<name>.json` next to `<name>.raw`:
+//!
+//! ```json
+//! { "width": 1028, "height": 512, "dtype": "u16", "endianness": "little", "offset": 0 }
+//! ```
+//!
+//! `dtype` is one of `u16`, `i16`, `u32` or `i32`; `endianness` (default
+//! `little`) and `offset`, the header size in bytes (default 0), are
+//! optional, as are `pixel_size_mm`, `distance_mm`, `beam_center` (pixels),
+//! `energy_kev` and `trusted_range_max`. Frame `i` starts at
+//! `offset + i * width * height * bytes_per_pixel`. Like `SmvReader`,
+//! `RawReader` re-reads the file on each call, so a dump still being written
+//! is followed as it grows.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use tracing::debug;
+
+use super::{DEFAULT_PIXEL_SIZE_MM, ImageMetadata, Reader};
+
+pub struct RawReader {
+    path: PathBuf,
+    descriptor: Descriptor,
+}
+
+/// The sidecar's contents.
+#[derive(Debug, Deserialize)]
+struct Descriptor {
+    width: usize,
+    height: usize,
+    dtype: RawDtype,
+    #[serde(default)]
+    endianness: Endianness,
+    /// Bytes before the first frame.
+    #[serde(default)]
+    offset: u64,
+    pixel_size_mm: Option<f64>,
+    distance_mm: Option<f64>,
+    /// Beam centre in pixels `[x, y]`; defaults to the middle of the frame.
+    beam_center: Option<[f64; 2]>,
+    energy_kev: Option<f64>,
+    /// Value above which pixels are untrusted; defaults to `u16::MAX - 1`.
+    trusted_range_max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawDtype {
+    U16,
+    I16,
+    U32,
+    I32,
+}
+
+impl RawDtype {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            RawDtype::U16 | RawDtype::I16 => 2,
+            RawDtype::U32 | RawDtype::I32 => 4,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RawDtype::U16 => "uint16",
+            RawDtype::I16 => "int16",
+            RawDtype::U32 => "uint32",
+            RawDtype::I32 => "int32",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl RawReader {
+    /// Read and validate the sidecar, then return a reader for the file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let sidecar = path.with_extension("json");
+        let text = std::fs::read_to_string(&sidecar).map_err(|e| {
+            anyhow!(
+                "Cannot read the descriptor {} for {}: {e}",
+                sidecar.display(),
+                path.display()
+            )
+        })?;
+        let descriptor: Descriptor = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid raw frame descriptor {}", sidecar.display()))?;
+        if descriptor.width == 0 || descriptor.height == 0 {
+            anyhow::bail!(
+                "Invalid raw frame descriptor {}: width and height must be non-zero",
+                sidecar.display()
+            );
+        }
+        let frame_bytes = descriptor
+            .width
+            .checked_mul(descriptor.height)
+            .and_then(|n| n.checked_mul(descriptor.dtype.bytes_per_pixel()));
+        if frame_bytes.is_none() {
+            anyhow::bail!(
+                "Invalid raw frame descriptor {}: {}x{} pixels are too many",
+                sidecar.display(),
+                descriptor.width,
+                descriptor.height
+            );
+        }
+        let reader = Self {
+            path: path.to_path_buf(),
+            descriptor,
+        };
+        let frames = reader.frame_count()?;
+        debug!(frames, "raw: opened {}", path.display());
+        Ok(reader)
+    }
+
+    fn open_file(&self) -> Result<File> {
+        File::open(&self.path).map_err(|e| anyhow!("Cannot open {}: {e}", self.path.display()))
+    }
+
+    /// Can't overflow or be 0: `open` checked the descriptor.
+    fn frame_bytes(&self) -> usize {
+        let d = &self.descriptor;
+        d.width * d.height * d.dtype.bytes_per_pixel()
+    }
+
+    fn trusted_range_max(&self) -> f64 {
+        self.descriptor
+            .trusted_range_max
+            .unwrap_or((u16::MAX - 1) as f64)
+    }
+}
+
+impl Reader for RawReader {
+    fn format_name(&self) -> &'static str {
+        "raw"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        let d = &self.descriptor;
+        Ok(ImageMetadata {
+            panel_distance_mm: d.distance_mm.unwrap_or(0.0),
+            beam_center: d
+                .beam_center
+                .unwrap_or([d.width as f64 / 2.0, d.height as f64 / 2.0]),
+            pixel_size: d.pixel_size_mm.unwrap_or(DEFAULT_PIXEL_SIZE_MM),
+            panel_size_fast_slow: [d.width as u64, d.height as u64],
+            image_depth: 16,
+            trusted_range_max: self.trusted_range_max(),
+            beam_energy_kev: d.energy_kev,
+            source_dtype: Some(d.dtype.name().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        let len = self.open_file()?.metadata()?.len();
+        let data = len.checked_sub(self.descriptor.offset).ok_or_else(|| {
+            anyhow!(
+                "{} is shorter than its {}-byte header",
+                self.path.display(),
+                self.descriptor.offset
+            )
+        })?;
+        Ok((data / self.frame_bytes() as u64) as usize)
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        let frame_count = self.frame_count()?;
+        if frame >= frame_count {
+            anyhow::bail!("Frame index {frame} out of range (file has {frame_count} frames)");
+        }
+        let t0 = std::time::Instant::now();
+        let d = &self.descriptor;
+        let frame_bytes = self.frame_bytes();
+        let mut file = self.open_file()?;
+        file.seek(SeekFrom::Start(d.offset + (frame * frame_bytes) as u64))?;
+        let mut bytes = vec![0u8; frame_bytes];
+        file.read_exact(&mut bytes)
+            .with_context(|| format!("Frame {frame} of {} is truncated", self.path.display()))?;
+
+        let big_endian = d.endianness == Endianness::Big;
+        let trusted_max = self.trusted_range_max();
+        let convert = |v: f64| super::to_display_u16(v, 0.0, trusted_max, u16::MAX);
+        let pixels = match d.dtype {
+            RawDtype::U16 => bytes
+                .chunks_exact(2)
+                .map(|b| {
+                    let b = [b[0], b[1]];
+                    let v = if big_endian {
+                        u16::from_be_bytes(b)
+                    } else {
+                        u16::from_le_bytes(b)
+                    };
+                    convert(f64::from(v))
+                })
+                .collect(),
+            RawDtype::I16 => bytes
+                .chunks_exact(2)
+                .map(|b| {
+                    let b = [b[0], b[1]];
+                    let v = if big_endian {
+                        i16::from_be_bytes(b)
+                    } else {
+                        i16::from_le_bytes(b)
+                    };
+                    convert(f64::from(v))
+                })
+                .collect(),
+            RawDtype::U32 => bytes
+                .chunks_exact(4)
+                .map(|b| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    let v = if big_endian {
+                        u32::from_be_bytes(b)
+                    } else {
+                        u32::from_le_bytes(b)
+                    };
+                    convert(f64::from(v))
+                })
+                .collect(),
+            RawDtype::I32 => bytes
+                .chunks_exact(4)
+                .map(|b| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    let v = if big_endian {
+                        i32::from_be_bytes(b)
+                    } else {
+                        i32::from_le_bytes(b)
+                    };
+                    convert(f64::from(v))
+                })
+                .collect(),
+        };
+        debug!(
+            elapsed_ms = t0.elapsed().as_millis(),
+            frame,
+            width = d.width,
+            height = d.height,
+            "raw: frame read + convert"
+        );
+        Ok((pixels, d.width, d.height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_frame_into_matches_read_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.raw");
+        std::fs::write(
+            path.with_extension("json"),
+            r#"{"width": 3, "height": 2, "dtype": "i32", "endianness": "big", "offset": 4}"#,
+        )
+        .unwrap();
+        let values: [i32; 12] = [-1, 0, 5, 70000, 2, 3, 9, 8, 7, 6, 5, 4];
+        let mut bytes = vec![0xff; 4];
+        bytes.extend(values.iter().flat_map(|v| v.to_be_bytes()));
+        std::fs::write(&path, bytes).unwrap();
+        let reader = RawReader::open(&path).unwrap();
+
+        // Stale contents, too long and then too short for a frame.
+        for mut buf in [vec![7; 50], vec![7; 1]] {
+            for frame in 0..2 {
+                let (pixels, width, height) = reader.read_frame(frame).unwrap();
+                let size = reader.read_frame_into(frame, &mut buf).unwrap();
+                assert_eq!(size, (width, height));
+                assert_eq!(buf, pixels);
+            }
+        }
+    }
+
+    #[test]
+    fn masks_u16_values_above_the_trusted_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.raw");
+        std::fs::write(
+            path.with_extension("json"),
+            r#"{"width": 2, "height": 1, "dtype": "u16", "trusted_range_max": 100}"#,
+        )
+        .unwrap();
+        std::fs::write(&path, [5u16, 150].map(u16::to_le_bytes).concat()).unwrap();
+        let reader = RawReader::open(&path).unwrap();
+        assert_eq!(reader.read_frame(0).unwrap(), (vec![5, u16::MAX], 2, 1));
+    }
+
+    #[test]
+    fn open_rejects_an_overflowing_frame_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.raw");
+        let width = usize::MAX;
+        let descriptor = format!(r#"{{"width": {width}, "height": 2, "dtype": "u32"}}"#);
+        std::fs::write(path.with_extension("json"), descriptor).unwrap();
+        std::fs::write(&path, [0u8; 16]).unwrap();
+        let err = RawReader::open(&path).err().unwrap().to_string();
+        assert!(err.contains("too many"), "{err}");
+    }
+}
@@ -0,0 +1,248 @@
+//! Composite reader presenting several files as one run of frames.
+//!
+//! Some acquisitions split one rotation scan across files (`sweep_0001.nxs`,
+//! `sweep_0002.nxs`, ...). `SeriesReader` stitches their frames end to end:
+//! global frame `i` is frame `i - first_frame` of the file whose run contains
+//! it. Every file must have the same pixel dimensions and pixel size, checked
+//! on open; metadata is otherwise taken from the first file.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use super::{FrameMetadata, GainMap, ImageMetadata, RawChunk, Reader};
+
+/// Relative difference in distance or beam centre above which files are
+/// reported as not quite matching.
+const GEOMETRY_TOLERANCE: f64 = 1e-3;
+
+pub struct SeriesReader {
+    parts: Vec<Part>,
+    frame_count: usize,
+}
+
+struct Part {
+    reader: Box<dyn Reader>,
+    /// Global index of this file's frame 0.
+    first_frame: usize,
+}
+
+impl SeriesReader {
+    /// Combine already-opened readers, in frame order. Fails if the list is
+    /// empty or the files' pixel dimensions or pixel size differ.
+    pub fn new(readers: Vec<Box<dyn Reader>>) -> Result<Self> {
+        let Some(first) = readers.first() else {
+            anyhow::bail!("A file series needs at least one file");
+        };
+        let reference = first.metadata()?;
+        let mut parts: Vec<Part> = Vec::with_capacity(readers.len());
+        let mut frame_count = 0;
+        for reader in readers {
+            if let Some(first) = parts.first() {
+                check_matches(&reference, first.reader.as_ref(), reader.as_ref())?;
+            }
+            let frames = reader.frame_count()?;
+            parts.push(Part {
+                reader,
+                first_frame: frame_count,
+            });
+            frame_count += frames;
+        }
+        debug!(files = parts.len(), frame_count, "series: opened");
+        Ok(Self { parts, frame_count })
+    }
+
+    /// The file holding global `frame`, and the frame's index within it.
+    fn locate(&self, frame: usize) -> Result<(&dyn Reader, usize)> {
+        if frame >= self.frame_count {
+            anyhow::bail!(
+                "Frame index {frame} out of range (series has {} frames)",
+                self.frame_count
+            );
+        }
+        let part = &self.parts[self.parts.partition_point(|p| p.first_frame <= frame) - 1];
+        Ok((part.reader.as_ref(), frame - part.first_frame))
+    }
+
+    fn first(&self) -> &dyn Reader {
+        self.parts[0].reader.as_ref()
+    }
+}
+
+impl Reader for SeriesReader {
+    fn format_name(&self) -> &'static str {
+        self.first().format_name()
+    }
+
+    fn path(&self) -> &Path {
+        self.first().path()
+    }
+
+    fn dataset_path(&self) -> Option<&str> {
+        self.first().dataset_path()
+    }
+
+    fn frame_source(&self, frame: usize) -> Result<(PathBuf, usize)> {
+        let (reader, local) = self.locate(frame)?;
+        reader.frame_source(local)
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        let mut metadata = self.first().metadata()?;
+        // Per-frame values must cover the whole series, or none of it.
+        metadata.scan_positions = match self.parts.len() {
+            1 => metadata.scan_positions,
+            _ => self
+                .parts
+                .iter()
+                .map(|p| p.reader.metadata().map(|m| m.scan_positions))
+                .collect::<Result<Option<Vec<_>>>>()?
+                .map(|runs| runs.concat()),
+        };
+        Ok(metadata)
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        Ok(self.frame_count)
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        let (reader, local) = self.locate(frame)?;
+        reader.read_frame(local)
+    }
+
+    fn read_region(
+        &self,
+        frame: usize,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> Result<Vec<u16>> {
+        let (reader, local) = self.locate(frame)?;
+        reader.read_region(local, x, y, w, h)
+    }
+
+    /// The files' own, if they all agree and each starts on a chunk
+    /// boundary of the series.
+    fn frames_per_chunk(&self) -> usize {
+        let depth = self.first().frames_per_chunk();
+        let aligned = self.parts[1..]
+            .iter()
+            .all(|p| p.first_frame % depth == 0 && p.reader.frames_per_chunk() == depth);
+        if aligned { depth } else { 1 }
+    }
+
+    fn read_frames(&self, frames: Range<usize>) -> Result<(Vec<u16>, usize, usize)> {
+        if frames.is_empty() {
+            return super::read_each_frame(self, frames);
+        }
+        let (reader, local) = self.locate(frames.start)?;
+        let (_, last) = self.locate(frames.end - 1)?;
+        // Frames spanning two files lose the difference in local indices.
+        if local + frames.len() - 1 != last {
+            return super::read_each_frame(self, frames);
+        }
+        reader.read_frames(local..last + 1)
+    }
+
+    fn read_frame_into(&self, frame: usize, buf: &mut Vec<u16>) -> Result<(usize, usize)> {
+        let (reader, local) = self.locate(frame)?;
+        reader.read_frame_into(local, buf)
+    }
+
+    fn read_frame_f32(&self, frame: usize) -> Result<(Vec<f32>, usize, usize)> {
+        let (reader, local) = self.locate(frame)?;
+        reader.read_frame_f32(local)
+    }
+
+    fn read_raw_chunk(&self, frame: usize) -> Result<Option<RawChunk>> {
+        let (reader, local) = self.locate(frame)?;
+        reader.read_raw_chunk(local)
+    }
+
+    fn sources_available(&self) -> bool {
+        self.parts.iter().all(|p| p.reader.sources_available())
+    }
+
+    fn frame_metadata(&self, frame: usize) -> Result<FrameMetadata> {
+        let (reader, local) = self.locate(frame)?;
+        reader.frame_metadata(local)
+    }
+
+    fn frame_times(&self) -> Result<Option<Vec<f64>>> {
+        let runs = self
+            .parts
+            .iter()
+            .map(|p| p.reader.frame_times())
+            .collect::<Result<Option<Vec<_>>>>()?;
+        Ok(runs.map(|runs| runs.concat()))
+    }
+
+    fn mask(&self) -> Result<Option<(Vec<u8>, usize, usize)>> {
+        self.first().mask()
+    }
+
+    fn gain_map(&self) -> Result<Option<GainMap>> {
+        self.first().gain_map()
+    }
+
+    /// Those of any of the files.
+    fn applied_transforms(&self) -> Vec<&'static str> {
+        let mut transforms = Vec::new();
+        for transform in self
+            .parts
+            .iter()
+            .flat_map(|p| p.reader.applied_transforms())
+        {
+            if !transforms.contains(&transform) {
+                transforms.push(transform);
+            }
+        }
+        transforms
+    }
+
+    fn frame_angles(&self) -> Result<Option<Vec<f64>>> {
+        let runs = self
+            .parts
+            .iter()
+            .map(|p| p.reader.frame_angles())
+            .collect::<Result<Option<Vec<_>>>>()?;
+        Ok(runs.map(|runs| runs.concat()))
+    }
+}
+
+/// Fail if `reader`'s frames can't be shown alongside those of `first`,
+/// whose metadata is `reference`; warn if its geometry differs only slightly.
+fn check_matches(reference: &ImageMetadata, first: &dyn Reader, reader: &dyn Reader) -> Result<()> {
+    let metadata = reader.metadata()?;
+    let name = reader.path().display();
+    let first_name = first.path().display();
+    if metadata.panel_size_fast_slow != reference.panel_size_fast_slow {
+        let [w0, h0] = reference.panel_size_fast_slow;
+        let [w, h] = metadata.panel_size_fast_slow;
+        anyhow::bail!("{name} is {w}x{h} pixels but {first_name} is {w0}x{h0}");
+    }
+    if differs(metadata.pixel_size, reference.pixel_size) {
+        anyhow::bail!(
+            "{name} has {} mm pixels but {first_name} has {} mm",
+            metadata.pixel_size,
+            reference.pixel_size
+        );
+    }
+    if differs(metadata.panel_distance_mm, reference.panel_distance_mm)
+        || differs(metadata.beam_center[0], reference.beam_center[0])
+        || differs(metadata.beam_center[1], reference.beam_center[1])
+    {
+        warn!(
+            "series: {name} has a different distance or beam centre from {first_name}; using the first"
+        );
+    }
+    Ok(())
+}
+
+fn differs(a: f64, b: f64) -> bool {
+    (a - b).abs() > GEOMETRY_TOLERANCE * a.abs().max(b.abs())
+}
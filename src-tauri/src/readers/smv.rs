@@ -0,0 +1,274 @@
+//! Reader for SMV (ADSC) `.img` files.
+//!
+//! An SMV file holds one frame: an ASCII header of `KEY=value;` pairs inside
+//! `{ ... }`, padded to `HEADER_BYTES` (usually 512), followed by the pixels
+//! as a flat, uncompressed `u16` array. `SmvReader` stores only the path and
+//! re-reads the file on each call.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use tracing::debug;
+
+use super::{ImageMetadata, Reader};
+use crate::geometry::HC_KEV_ANGSTROM;
+
+/// Header size assumed until `HEADER_BYTES` has been read.
+const MIN_HEADER_BYTES: usize = 512;
+
+pub struct SmvReader {
+    path: PathBuf,
+}
+
+impl SmvReader {
+    /// Validate the SMV header and that the file holds the pixels it
+    /// describes, then return a reader for the file.
+    pub fn open(path: &Path) -> Result<Self> {
+        let reader = Self {
+            path: path.to_path_buf(),
+        };
+        // Parse the header now to surface errors early.
+        let header = SmvHeader::parse(&reader.read_header()?)?;
+        let data_end = header
+            .frame_bytes()
+            .ok()
+            .and_then(|data| (data as u64).checked_add(header.header_bytes as u64))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid SMV header: {}x{} pixels are too many",
+                    header.width,
+                    header.height
+                )
+            })?;
+        let len = reader.open_file()?.metadata()?.len();
+        if len < data_end {
+            anyhow::bail!(
+                "{} is truncated: its header describes {data_end} bytes but the file holds {len}",
+                path.display()
+            );
+        }
+        Ok(reader)
+    }
+
+    fn open_file(&self) -> Result<File> {
+        File::open(&self.path).map_err(|e| anyhow!("Cannot open {}: {e}", self.path.display()))
+    }
+
+    /// Read the whole header block, however long `HEADER_BYTES` says it is.
+    fn read_header(&self) -> Result<String> {
+        let mut file = self.open_file()?;
+        let mut buf = vec![0u8; MIN_HEADER_BYTES];
+        file.read_exact(&mut buf)
+            .context("File is too short to hold an SMV header")?;
+        let header_bytes = header_fields(&String::from_utf8_lossy(&buf))
+            .get("HEADER_BYTES")
+            .and_then(|v| v.parse::<usize>().ok())
+            .ok_or_else(|| anyhow!("Not an SMV file: no HEADER_BYTES in header"))?;
+        let len = file.metadata()?.len();
+        if header_bytes as u64 > len {
+            anyhow::bail!(
+                "SMV header is truncated: HEADER_BYTES is {header_bytes} but the file holds {len}"
+            );
+        }
+        if header_bytes > MIN_HEADER_BYTES {
+            buf.resize(header_bytes, 0);
+            file.read_exact(&mut buf[MIN_HEADER_BYTES..])
+                .context("SMV header is truncated")?;
+        }
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+impl Reader for SmvReader {
+    fn format_name(&self) -> &'static str {
+        "smv"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        let header = SmvHeader::parse(&self.read_header()?)?;
+        Ok(header.metadata())
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        Ok(1)
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        if frame != 0 {
+            anyhow::bail!("Frame index {frame} out of range (SMV files hold 1 frame)");
+        }
+        let t0 = std::time::Instant::now();
+        let header = SmvHeader::parse(&self.read_header()?)?;
+        let mut file = self.open_file()?;
+        file.seek(SeekFrom::Start(header.header_bytes as u64))?;
+        let mut bytes = vec![0u8; header.frame_bytes()?];
+        file.read_exact(&mut bytes)
+            .context("SMV pixel data is truncated")?;
+        let pixels = bytes
+            .chunks_exact(2)
+            .map(|b| {
+                let b = [b[0], b[1]];
+                if header.big_endian {
+                    u16::from_be_bytes(b)
+                } else {
+                    u16::from_le_bytes(b)
+                }
+            })
+            .collect();
+        debug!(
+            elapsed_ms = t0.elapsed().as_millis(),
+            width = header.width,
+            height = header.height,
+            "smv: frame read"
+        );
+        Ok((pixels, header.width, header.height))
+    }
+}
+
+// ── Private helpers ──────────────────────────────────────────────────────────
+
+/// The header fields this reader uses.
+struct SmvHeader {
+    header_bytes: usize,
+    width: usize,
+    height: usize,
+    big_endian: bool,
+    fields: HashMap<String, String>,
+}
+
+impl SmvHeader {
+    fn parse(text: &str) -> Result<Self> {
+        let fields = header_fields(text);
+        let usize_field = |key: &str| -> Result<usize> {
+            fields
+                .get(key)
+                .with_context(|| format!("SMV header has no {key}"))?
+                .parse()
+                .with_context(|| format!("Invalid {key} in SMV header"))
+        };
+        match fields.get("TYPE") {
+            Some(kind) if !kind.eq_ignore_ascii_case("unsigned_short") => {
+                anyhow::bail!(
+                    "Unsupported SMV pixel TYPE '{kind}'; only unsigned_short is supported"
+                )
+            }
+            _ => {}
+        }
+        let big_endian = match fields
+            .get("BYTE_ORDER")
+            .map(|v| v.to_lowercase())
+            .as_deref()
+        {
+            Some("big_endian") => true,
+            Some("little_endian") | None => false,
+            Some(other) => anyhow::bail!("Unknown SMV BYTE_ORDER '{other}'"),
+        };
+        Ok(Self {
+            header_bytes: usize_field("HEADER_BYTES")?,
+            width: usize_field("SIZE1")?,
+            height: usize_field("SIZE2")?,
+            big_endian,
+            fields,
+        })
+    }
+
+    /// Size of the pixel data: `SIZE1 * SIZE2` `u16` values.
+    fn frame_bytes(&self) -> Result<usize> {
+        self.width
+            .checked_mul(self.height)
+            .and_then(|n| n.checked_mul(2))
+            .ok_or_else(|| anyhow!("Invalid SMV header: {}x{} pixels", self.width, self.height))
+    }
+
+    fn f64_field(&self, key: &str) -> Option<f64> {
+        self.fields.get(key)?.parse().ok()
+    }
+
+    /// Geometry from the header. SMV lengths, including the beam centre, are
+    /// in mm.
+    fn metadata(&self) -> ImageMetadata {
+        let pixel_size = self.f64_field("PIXEL_SIZE").unwrap_or(0.1);
+        let beam_center_px = |key: &str, default: usize| {
+            self.f64_field(key)
+                .filter(|_| pixel_size > 0.0)
+                .map_or(default as f64 / 2.0, |mm| mm / pixel_size)
+        };
+        let trusted_range_max = self
+            .f64_field("SATURATED_VALUE")
+            .map_or((u16::MAX - 1) as f64, |saturated| saturated - 1.0);
+        ImageMetadata {
+            panel_distance_mm: self.f64_field("DISTANCE").unwrap_or(0.0),
+            beam_center: [
+                beam_center_px("BEAM_CENTER_X", self.width),
+                beam_center_px("BEAM_CENTER_Y", self.height),
+            ],
+            pixel_size,
+            panel_size_fast_slow: [self.width as u64, self.height as u64],
+            image_depth: 16,
+            trusted_range_max,
+            beam_energy_kev: self
+                .f64_field("WAVELENGTH")
+                .filter(|&a| a > 0.0)
+                .map(|angstrom| HC_KEV_ANGSTROM / angstrom),
+            source_dtype: Some("uint16".to_string()),
+            count_time_s: self.f64_field("TIME"),
+            ..Default::default()
+        }
+    }
+}
+
+/// `KEY=value;` pairs from an SMV header, keys upper-cased.
+fn header_fields(text: &str) -> HashMap<String, String> {
+    let body = text.split('}').next().unwrap_or(text);
+    body.split(';')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let key = key.trim().trim_start_matches('{').trim();
+            Some((key.to_uppercase(), value.trim().to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An SMV file of 3x2 pixels with a 512-byte header, followed by `data`.
+    fn write_smv(path: &Path, data: &[u8]) {
+        let mut header =
+            b"{\nHEADER_BYTES=512;\nSIZE1=3;\nSIZE2=2;\nTYPE=unsigned_short;\n}".to_vec();
+        header.resize(512, b' ');
+        std::fs::write(path, [&header[..], data].concat()).unwrap();
+    }
+
+    #[test]
+    fn open_rejects_truncated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.img");
+        // 3x2 pixels need 12 bytes.
+        write_smv(&path, &[0; 11]);
+        let err = SmvReader::open(&path).err().unwrap().to_string();
+        assert!(err.contains("truncated"), "{err}");
+
+        write_smv(&path, &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0]);
+        let reader = SmvReader::open(&path).unwrap();
+        assert_eq!(
+            reader.read_frame(0).unwrap(),
+            (vec![1, 2, 3, 4, 5, 6], 3, 2)
+        );
+
+        // A header that claims more bytes than the file holds.
+        let text = std::fs::read(&path).unwrap();
+        let text = String::from_utf8_lossy(&text).replace("HEADER_BYTES=512", "HEADER_BYTES=9999");
+        std::fs::write(&path, text).unwrap();
+        let err = SmvReader::open(&path).err().unwrap().to_string();
+        assert!(err.contains("truncated"), "{err}");
+    }
+}
@@ -0,0 +1,136 @@
+//! Reader for single- and multi-page grayscale TIFF files.
+//!
+//! Each page (IFD) is one frame. 8- and 16-bit pages are supported; 8-bit
+//! pixels are widened to u16. `TiffReader` stores only the path (and page
+//! count) and re-opens the file on each call.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use tiff::decoder::{Decoder, DecodingResult, ifd::Value};
+use tiff::tags::Tag;
+use tracing::debug;
+
+use super::{DEFAULT_PIXEL_SIZE_MM, ImageMetadata, Reader};
+
+pub struct TiffReader {
+    path: PathBuf,
+    /// Number of pages, counted on open.
+    pages: usize,
+}
+
+impl TiffReader {
+    /// Open the file and count its pages.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut reader = Self {
+            path: path.to_path_buf(),
+            pages: 0,
+        };
+        let mut decoder = reader.decoder()?;
+        let mut pages = 1;
+        while decoder.more_images() {
+            decoder.next_image()?;
+            pages += 1;
+        }
+        reader.pages = pages;
+        debug!(pages, "tiff: opened {}", path.display());
+        Ok(reader)
+    }
+
+    fn decoder(&self) -> Result<Decoder<BufReader<File>>> {
+        let file = File::open(&self.path)
+            .map_err(|e| anyhow!("Cannot open {}: {e}", self.path.display()))?;
+        Ok(Decoder::new(BufReader::new(file))?)
+    }
+}
+
+impl Reader for TiffReader {
+    fn format_name(&self) -> &'static str {
+        "tiff"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn metadata(&self) -> Result<ImageMetadata> {
+        let mut decoder = self.decoder()?;
+        let (width, height) = decoder.dimensions()?;
+        let source_dtype = match decoder.colortype()? {
+            tiff::ColorType::Gray(8) => Some("uint8".to_string()),
+            tiff::ColorType::Gray(16) => Some("uint16".to_string()),
+            _ => None,
+        };
+        let pixel_size = read_pixel_size_mm(&mut decoder, Tag::XResolution);
+        let pixel_size_y = read_pixel_size_mm(&mut decoder, Tag::YResolution);
+        let pixel_size = pixel_size.or(pixel_size_y).unwrap_or(DEFAULT_PIXEL_SIZE_MM);
+
+        Ok(ImageMetadata {
+            panel_distance_mm: 0.0,
+            beam_center: [width as f64 / 2.0, height as f64 / 2.0],
+            pixel_size,
+            panel_size_fast_slow: [width as u64, height as u64],
+            image_depth: 16,
+            trusted_range_max: (u16::MAX - 1) as f64,
+            source_dtype,
+            ..Default::default()
+        })
+    }
+
+    fn frame_count(&self) -> Result<usize> {
+        Ok(self.pages)
+    }
+
+    fn read_frame(&self, frame: usize) -> Result<(Vec<u16>, usize, usize)> {
+        if frame >= self.pages {
+            anyhow::bail!(
+                "Frame index {frame} out of range (file has {} pages)",
+                self.pages
+            );
+        }
+        let t0 = std::time::Instant::now();
+        let mut decoder = self.decoder()?;
+        decoder.seek_to_image(frame)?;
+        let (width, height) = decoder.dimensions()?;
+        match decoder.colortype()? {
+            tiff::ColorType::Gray(_) => {}
+            other => anyhow::bail!("Unsupported TIFF colour type {other:?}; expected grayscale"),
+        }
+        let pixels = match decoder.read_image()? {
+            DecodingResult::U16(pixels) => pixels,
+            DecodingResult::U8(pixels) => pixels.into_iter().map(u16::from).collect(),
+            DecodingResult::F32(_) | DecodingResult::F64(_) => anyhow::bail!(
+                "Floating-point TIFF pages are not supported; frames are sent as 16-bit integers"
+            ),
+            _ => anyhow::bail!(
+                "Unsupported TIFF sample format; only 8- and 16-bit unsigned pages are supported"
+            ),
+        };
+        debug!(
+            elapsed_ms = t0.elapsed().as_millis(),
+            frame, width, height, "tiff: page read + decode"
+        );
+        Ok((pixels, width as usize, height as usize))
+    }
+}
+
+// ── Private helpers ──────────────────────────────────────────────────────────
+
+/// Pixel size in mm from a resolution tag (pixels per `ResolutionUnit`).
+/// `None` if the tag is missing or the unit is not inches or centimetres.
+fn read_pixel_size_mm(decoder: &mut Decoder<BufReader<File>>, tag: Tag) -> Option<f64> {
+    let per_unit = match decoder.find_tag(tag).ok()?? {
+        Value::Rational(n, d) if d != 0 => f64::from(n) / f64::from(d),
+        Value::RationalBig(n, d) if d != 0 => n as f64 / d as f64,
+        _ => return None,
+    };
+    // ResolutionUnit: 1 = none, 2 = inch (the default), 3 = centimetre.
+    let unit_mm = match decoder.find_tag_unsigned::<u16>(Tag::ResolutionUnit).ok()? {
+        None | Some(2) => 25.4,
+        Some(3) => 10.0,
+        _ => return None,
+    };
+    (per_unit > 0.0).then(|| unit_mm / per_unit)
+}
@@ -0,0 +1,66 @@
+//! Recently opened files, most recent first, kept in a small JSON file in
+//! the app config directory so the list survives restarts.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+/// Longest the list gets; older entries are dropped.
+pub const MAX_RECENT_FILES: usize = 20;
+
+const FILE_NAME: &str = "recent_files.json";
+
+pub struct RecentFiles {
+    /// The JSON file the list is saved to; `None` if the platform has no
+    /// config directory, in which case the list only lasts this session.
+    store: Option<PathBuf>,
+    paths: Vec<String>,
+}
+
+impl RecentFiles {
+    /// Load the list saved in `config_dir`. A missing file is an empty list;
+    /// so is an unreadable one, which is overwritten on the next `add`.
+    pub fn load(config_dir: Option<&Path>) -> Self {
+        let store = config_dir.map(|dir| dir.join(FILE_NAME));
+        let paths = store
+            .as_deref()
+            .and_then(|store| match std::fs::read(store) {
+                Ok(json) => serde_json::from_slice(&json)
+                    .inspect_err(|e| {
+                        tracing::warn!("Ignoring invalid recent files {}: {e}", store.display())
+                    })
+                    .ok(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    tracing::warn!("Cannot read recent files {}: {e}", store.display());
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self { store, paths }
+    }
+
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// Move `path` to the front of the list, adding it if it is new, and
+    /// save the list.
+    pub fn add(&mut self, path: String) -> Result<()> {
+        self.paths.retain(|p| *p != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT_FILES);
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        if let Some(dir) = store.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(store, serde_json::to_vec_pretty(&self.paths)?)
+            .map_err(|e| anyhow::anyhow!("Cannot write {}: {e}", store.display()))
+    }
+}
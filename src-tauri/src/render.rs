@@ -0,0 +1,278 @@
+//! Pixel kernels for turning raw frames into display-ready previews.
+//!
+//! Everything here is plain CPU work on `&[u16]` frames and is meant to be
+//! called from inside `spawn_blocking`, after the frame has been read.
+
+use std::io::Cursor;
+
+use anyhow::Result;
+use image::{ImageFormat, RgbImage};
+
+// ── 8-bit autoscaling ────────────────────────────────────────────────────────
+
+/// Byte value reserved for masked / untrusted pixels in 8-bit frames.
+/// Trusted pixels are scaled into `0..DEPTH8_MASKED`.
+pub const DEPTH8_MASKED: u8 = u8::MAX;
+
+/// Fraction of trusted pixels that must lie at or below `display_max`.
+/// Using a high percentile rather than the true maximum stops a few hot
+/// pixels from squashing the rest of the frame into the bottom few levels.
+const AUTOSCALE_PERCENTILE: f64 = 0.999;
+
+pub struct Scaled8 {
+    pub bytes: Vec<u8>,
+    pub display_min: u16,
+    pub display_max: u16,
+}
+
+/// Display limits for a frame: the smallest trusted value and the
+/// [`AUTOSCALE_PERCENTILE`] of the trusted values. Pixels above
+/// `trusted_max` are ignored. Returns `(0, 0)` if no pixel is trusted.
+pub fn autoscale_limits(pixels: &[u16], trusted_max: f64) -> (u16, u16) {
+    let mut hist = vec![0usize; u16::MAX as usize + 1];
+    let mut trusted = 0usize;
+    for &v in pixels {
+        if f64::from(v) <= trusted_max {
+            hist[v as usize] += 1;
+            trusted += 1;
+        }
+    }
+    if trusted == 0 {
+        return (0, 0);
+    }
+
+    let min = hist.iter().position(|&c| c > 0).unwrap_or(0) as u16;
+    let target = (trusted as f64 * AUTOSCALE_PERCENTILE).ceil() as usize;
+    let mut seen = 0usize;
+    let mut max = min;
+    for (value, &count) in hist.iter().enumerate() {
+        seen += count;
+        if seen >= target {
+            max = value as u16;
+            break;
+        }
+    }
+    (min, max)
+}
+
+/// Linearly map a frame onto one byte per pixel between its autoscale limits.
+/// Pixels above `trusted_max` become [`DEPTH8_MASKED`].
+pub fn scale_to_depth8(pixels: &[u16], trusted_max: f64) -> Scaled8 {
+    let (display_min, display_max) = autoscale_limits(pixels, trusted_max);
+    let span = f64::from(display_max.saturating_sub(display_min)).max(1.0);
+    let top = f64::from(DEPTH8_MASKED - 1);
+
+    let bytes = pixels
+        .iter()
+        .map(|&v| {
+            if f64::from(v) > trusted_max {
+                DEPTH8_MASKED
+            } else {
+                let clamped = v.clamp(display_min, display_max);
+                (f64::from(clamped - display_min) / span * top).round() as u8
+            }
+        })
+        .collect();
+
+    Scaled8 {
+        bytes,
+        display_min,
+        display_max,
+    }
+}
+
+// ── Downsampling ─────────────────────────────────────────────────────────────
+
+/// A frame reduced in size, still in raw detector counts.
+pub struct Binned {
+    pub pixels: Vec<u16>,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Smallest integer binning factor that fits `width`×`height` inside a
+/// `size`×`size` box.
+pub fn bin_factor(width: usize, height: usize, size: usize) -> usize {
+    width.max(height).div_ceil(size.max(1)).max(1)
+}
+
+/// Max-pool a frame by `factor` in both directions, so isolated strong
+/// pixels (spots) survive the reduction.
+///
+/// Only trusted pixels (`<= trusted_max`) contribute to a bin. A bin with no
+/// trusted pixels takes the largest untrusted value instead, so it still
+/// reads as masked to any later `> trusted_max` test.
+pub fn bin_max(
+    pixels: &[u16],
+    width: usize,
+    height: usize,
+    factor: usize,
+    trusted_max: f64,
+) -> Binned {
+    let factor = factor.max(1);
+    let out_w = width.div_ceil(factor);
+    let out_h = height.div_ceil(factor);
+    let mut trusted: Vec<Option<u16>> = vec![None; out_w * out_h];
+    let mut untrusted = vec![0u16; out_w * out_h];
+
+    for y in 0..height {
+        let row = &pixels[y * width..(y + 1) * width];
+        let out_row = (y / factor) * out_w;
+        for (x, &v) in row.iter().enumerate() {
+            let i = out_row + x / factor;
+            if f64::from(v) <= trusted_max {
+                trusted[i] = Some(trusted[i].map_or(v, |m| m.max(v)));
+            } else {
+                untrusted[i] = untrusted[i].max(v);
+            }
+        }
+    }
+
+    let pixels = trusted
+        .into_iter()
+        .zip(untrusted)
+        .map(|(t, u)| t.unwrap_or(u))
+        .collect();
+
+    Binned {
+        pixels,
+        width: out_w,
+        height: out_h,
+    }
+}
+
+/// Mean-pool a frame by `factor` in both directions, so each output pixel is
+/// the average count over the area it covers.
+///
+/// As in [`bin_max`], only trusted pixels contribute; a bin with no trusted
+/// pixels takes the largest untrusted value so it stays masked.
+pub fn bin_mean(
+    pixels: &[u16],
+    width: usize,
+    height: usize,
+    factor: usize,
+    trusted_max: f64,
+) -> Binned {
+    let factor = factor.max(1);
+    let out_w = width.div_ceil(factor);
+    let out_h = height.div_ceil(factor);
+    let mut sum = vec![0u64; out_w * out_h];
+    let mut count = vec![0u32; out_w * out_h];
+    let mut untrusted = vec![0u16; out_w * out_h];
+
+    for y in 0..height {
+        let row = &pixels[y * width..(y + 1) * width];
+        let out_row = (y / factor) * out_w;
+        for (x, &v) in row.iter().enumerate() {
+            let i = out_row + x / factor;
+            if f64::from(v) <= trusted_max {
+                sum[i] += u64::from(v);
+                count[i] += 1;
+            } else {
+                untrusted[i] = untrusted[i].max(v);
+            }
+        }
+    }
+
+    let pixels = sum
+        .into_iter()
+        .zip(count)
+        .zip(untrusted)
+        .map(|((sum, n), u)| {
+            if n == 0 {
+                u
+            } else {
+                (sum as f64 / f64::from(n)).round() as u16
+            }
+        })
+        .collect();
+
+    Binned {
+        pixels,
+        width: out_w,
+        height: out_h,
+    }
+}
+
+// ── Tone mapping and PNG output ──────────────────────────────────────────────
+
+/// Colour used for masked / untrusted pixels in rendered previews.
+pub const MASKED_RGB: [u8; 3] = [255, 0, 255];
+
+/// Log-scaled grayscale: 0 maps to black and `max` to white.
+/// Pixels above `trusted_max` are drawn in [`MASKED_RGB`].
+fn tone_map(value: u16, max: u16, trusted_max: f64) -> [u8; 3] {
+    if f64::from(value) > trusted_max {
+        return MASKED_RGB;
+    }
+    let norm = if max == 0 {
+        0.0
+    } else {
+        (f64::from(value).ln_1p() / f64::from(max).ln_1p()).min(1.0)
+    };
+    let g = (norm * 255.0).round() as u8;
+    [g, g, g]
+}
+
+/// Largest trusted value across a set of binned frames; used as the common
+/// white level so tiles in a montage are directly comparable.
+pub fn trusted_max_value<'a>(
+    frames: impl IntoIterator<Item = &'a Binned>,
+    trusted_max: f64,
+) -> u16 {
+    frames
+        .into_iter()
+        .flat_map(|b| b.pixels.iter().copied())
+        .filter(|&v| f64::from(v) <= trusted_max)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Tile binned frames into a grid of `cols` columns of `cell`×`cell` cells,
+/// each frame drawn at the top-left of its cell on a black background, and
+/// encode the result as PNG.
+pub fn montage_png(
+    tiles: &[Binned],
+    cols: usize,
+    cell: usize,
+    trusted_max: f64,
+) -> Result<Vec<u8>> {
+    let cols = cols.max(1);
+    let rows = tiles.len().div_ceil(cols).max(1);
+    let white = trusted_max_value(tiles, trusted_max);
+    let mut img = RgbImage::new((cols * cell) as u32, (rows * cell) as u32);
+
+    for (i, tile) in tiles.iter().enumerate() {
+        let x0 = (i % cols) * cell;
+        let y0 = (i / cols) * cell;
+        for y in 0..tile.height.min(cell) {
+            for x in 0..tile.width.min(cell) {
+                let v = tile.pixels[y * tile.width + x];
+                img.put_pixel(
+                    (x0 + x) as u32,
+                    (y0 + y) as u32,
+                    image::Rgb(tone_map(v, white, trusted_max)),
+                );
+            }
+        }
+    }
+
+    encode_png(&img)
+}
+
+/// Encode one binned frame as a PNG of its own size, log-scaled with its
+/// brightest trusted pixel as white.
+pub fn thumbnail_png(tile: &Binned, trusted_max: f64) -> Result<Vec<u8>> {
+    let white = trusted_max_value([tile], trusted_max);
+    let img = RgbImage::from_fn(tile.width as u32, tile.height as u32, |x, y| {
+        let v = tile.pixels[y as usize * tile.width + x as usize];
+        image::Rgb(tone_map(v, white, trusted_max))
+    });
+    encode_png(&img)
+}
+
+fn encode_png(img: &RgbImage) -> Result<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    img.write_to(&mut out, ImageFormat::Png)?;
+    Ok(out.into_inner())
+}
@@ -1,85 +1,1133 @@
 use axum::{
     Router,
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{
+        Path, Query, Request, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    http::{HeaderName, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
     Json,
 };
+use serde::{Deserialize, Serialize};
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::SharedReader;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tracing::Instrument;
+
+use crate::cache::{CachedFrame, FrameCache};
+use crate::disk_cache::DiskCache;
+use crate::geometry;
+use crate::readers::{ImageMetadata, RawChunk};
+use crate::render::{self, DEPTH8_MASKED, Scaled8};
+use crate::stats::{self, FrameStats, MeanVariance, ProjectedPixels, ProjectionOp};
+use crate::{AnalysisEpoch, CancelToken, ReaderGeneration, SharedActivity, SharedReader};
+
+/// Default memory budget of the decoded-frame cache, overridable with
+/// [`crate::FRAME_CACHE_BYTES_ENV`].
+pub const DEFAULT_FRAME_CACHE_BYTES: usize = 256 << 20;
+
+/// Default number of frames on each side of a requested one to prefetch,
+/// overridable with [`crate::PREFETCH_RADIUS_ENV`].
+pub const DEFAULT_PREFETCH_RADIUS: usize = 1;
+
+/// Default niceness of the threads bulk analysis runs on, overridable with
+/// [`crate::ANALYSIS_NICENESS_ENV`].
+pub const DEFAULT_ANALYSIS_NICENESS: i32 = 10;
+
+/// Default for [`ServerConfig::max_scan_frames`], overridable with
+/// [`crate::MAX_SCAN_FRAMES_ENV`].
+pub const DEFAULT_MAX_SCAN_FRAMES: usize = 10_000;
+
+/// Server tunables, read from the environment in `lib.rs`.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerConfig {
+    /// `/image` bodies larger than this many bytes are streamed.
+    pub stream_threshold: usize,
+    /// Memory budget of the decoded-frame cache; 0 disables it.
+    pub frame_cache_bytes: usize,
+    /// Frames on each side of a requested one to prefetch into the cache;
+    /// 0 disables prefetching.
+    pub prefetch_radius: usize,
+    /// Threads in the pool `/project`, `/montage` and `/stats/stream` decode
+    /// on; 0 means one per CPU.
+    pub analysis_threads: usize,
+    /// Niceness of those threads on Linux, 0 (the same priority as
+    /// interactive reads) to 19 (the lowest). Ignored on other platforms,
+    /// where it would apply to the whole process.
+    pub analysis_niceness: i32,
+    /// Most frames an aggregate request (`/project`, `/stats/stream`,
+    /// `/next_active` and the mean/variance images) reads; beyond it, an
+    /// evenly spaced sample of this many frames stands for the rest. 0 means
+    /// no limit.
+    pub max_scan_frames: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            stream_threshold: DEFAULT_STREAM_THRESHOLD,
+            frame_cache_bytes: DEFAULT_FRAME_CACHE_BYTES,
+            prefetch_radius: DEFAULT_PREFETCH_RADIUS,
+            analysis_threads: 0,
+            analysis_niceness: DEFAULT_ANALYSIS_NICENESS,
+            max_scan_frames: DEFAULT_MAX_SCAN_FRAMES,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The frames of `frames` an aggregate request reads: all of them, or
+    /// [`stats::sample_frames`] of them if there are more than
+    /// `max_scan_frames`.
+    fn scan_frames(&self, frames: std::ops::Range<usize>) -> Vec<usize> {
+        stats::sample_frames(frames.len(), self.scan_limit())
+            .into_iter()
+            .map(|i| frames.start + i)
+            .collect()
+    }
+
+    /// `max_scan_frames`, with 0 meaning no limit.
+    fn scan_limit(&self) -> usize {
+        match self.max_scan_frames {
+            0 => usize::MAX,
+            max => max,
+        }
+    }
+}
 
 #[derive(Clone)]
-struct ServerState {
+pub struct ServerState {
     reader: SharedReader,
+    generation: ReaderGeneration,
+    /// Bumped by `cancel_analysis`; see [`CancelToken`].
+    analysis_epoch: AnalysisEpoch,
+    /// Last request time, for the idle timeout.
+    activity: SharedActivity,
+    /// Lazily built mean/variance images for `?normalize=zscore`.
+    mean_variance: Arc<std::sync::Mutex<Option<CachedMeanVariance>>>,
+    /// Metadata of the open file, read on first use.
+    metadata: Arc<std::sync::Mutex<Option<CachedMetadata>>>,
+    /// Pixel mask of the open file, read on first use.
+    mask: Arc<std::sync::Mutex<Option<CachedMask>>>,
+    config: ServerConfig,
+    /// Recently decoded frames for `/image`.
+    frame_cache: Arc<FrameCache>,
+    /// Decoded frames kept across sessions, behind `frame_cache`; `None` if
+    /// disabled.
+    disk_cache: Option<Arc<DiskCache>>,
+    /// Where bulk analysis runs; see [`analysis_pool`].
+    analysis_pool: Arc<rayon::ThreadPool>,
+}
+
+/// The rayon pool bulk analysis decodes frames on, separate from the global
+/// pool so its threads can run at `config.analysis_niceness`, keeping
+/// interactive reads and the rest of the machine responsive under it.
+fn analysis_pool(config: ServerConfig) -> rayon::ThreadPool {
+    let niceness = config.analysis_niceness;
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(config.analysis_threads)
+        .thread_name(|i| format!("analysis-{i}"))
+        .start_handler(move |_| set_thread_niceness(niceness))
+        .build()
+        .expect("failed to build the analysis thread pool")
+}
+
+/// Set the calling thread's niceness. Only Linux gives each thread its own,
+/// so elsewhere this does nothing.
+fn set_thread_niceness(niceness: i32) {
+    #[cfg(target_os = "linux")]
+    if niceness != 0 {
+        // SAFETY: setpriority has no memory-safety preconditions. On Linux,
+        // PRIO_PROCESS with `who` 0 means the calling thread.
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) } != 0 {
+            tracing::warn!(
+                "Cannot set analysis thread niceness to {niceness}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = niceness;
+}
+
+/// Mean/variance images tagged with what they were computed from. They are
+/// rebuilt when the reader is replaced or a live file gains frames.
+struct CachedMeanVariance {
+    generation: u64,
+    frame_count: usize,
+    stats: Arc<MeanVariance>,
+}
+
+/// Metadata tagged with what it was read from, like [`CachedMeanVariance`],
+/// and re-read on the same terms: per-frame sections such as
+/// `scan_positions` cover every frame of a live file.
+struct CachedMetadata {
+    generation: u64,
+    frame_count: usize,
+    metadata: Arc<ImageMetadata>,
+}
+
+/// The pixel mask (see [`crate::readers::Reader::mask`]) of the reader of
+/// `generation`, `None` if it has none. Unlike the metadata it doesn't
+/// change as a live file gains frames.
+struct CachedMask {
+    generation: u64,
+    mask: Option<Arc<(Vec<u8>, usize, usize)>>,
+}
+
+impl ServerState {
+    pub fn new(
+        reader: SharedReader,
+        generation: ReaderGeneration,
+        analysis_epoch: AnalysisEpoch,
+        activity: SharedActivity,
+        config: ServerConfig,
+        disk_cache: Option<DiskCache>,
+    ) -> Self {
+        Self {
+            reader,
+            generation,
+            analysis_epoch,
+            activity,
+            mean_variance: Arc::new(std::sync::Mutex::new(None)),
+            metadata: Arc::new(std::sync::Mutex::new(None)),
+            mask: Arc::new(std::sync::Mutex::new(None)),
+            config,
+            frame_cache: Arc::new(FrameCache::new(config.frame_cache_bytes)),
+            disk_cache: disk_cache.map(Arc::new),
+            analysis_pool: Arc::new(analysis_pool(config)),
+        }
+    }
+
+    /// Drop the decoded frame, mean/variance, metadata and mask caches,
+    /// reporting how much was freed (not counting metadata or the mask). All
+    /// refill on demand. The on-disk frame cache is kept; see
+    /// `POST /admin/clear_cache?disk=1`.
+    pub fn clear_caches(&self) -> ClearCacheResult {
+        let (frames_freed, frame_bytes) = self.frame_cache.clear();
+        self.metadata
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        self.mask
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        let mean_variance = self
+            .mean_variance
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        let mean_variance_bytes = mean_variance
+            .as_ref()
+            .map_or(0, |cached| cached.stats.size_bytes());
+        ClearCacheResult {
+            frames_freed,
+            bytes_freed: frame_bytes + mean_variance_bytes,
+            mean_variance_freed: mean_variance.is_some(),
+            disk_bytes_freed: 0,
+        }
+    }
+
+    /// `reader.read_frame(frame)` through the frame cache `/image` uses, for
+    /// the `read_frame` command. The caller holds the reader guard.
+    pub fn read_frame(
+        &self,
+        reader: &dyn crate::readers::Reader,
+        frame: usize,
+    ) -> anyhow::Result<CachedFrame> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        read_frame_cached(self, reader, generation, frame)
+    }
+
+    /// `reader.metadata()` through the cache the handlers use, for commands.
+    /// The caller holds the reader guard.
+    pub fn metadata(
+        &self,
+        reader: &dyn crate::readers::Reader,
+    ) -> anyhow::Result<Arc<ImageMetadata>> {
+        cached_metadata(self, reader)
+    }
+
+    /// Seed the metadata cache with what was read while opening the reader
+    /// just installed, so the first requests don't read it again. The caller
+    /// holds the reader guard and has already bumped the generation.
+    pub fn cache_metadata(&self, frame_count: usize, metadata: ImageMetadata) {
+        *self
+            .metadata
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(CachedMetadata {
+            generation: self.generation.load(Ordering::SeqCst),
+            frame_count,
+            metadata: Arc::new(metadata),
+        });
+    }
 }
 
-pub fn create_router(reader: SharedReader) -> Router {
+pub fn create_router(state: ServerState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        // Let the webview read the X-* headers describing each response.
+        .expose_headers(Any);
 
     Router::new()
         .route("/metadata", axum::routing::get(get_metadata))
+        .route("/manifest", axum::routing::get(get_manifest))
+        .route("/info", axum::routing::get(get_info))
         .route("/image/{frame}", axum::routing::get(get_image))
-        .with_state(ServerState { reader })
+        .route("/frame/{frame}", axum::routing::get(get_frame))
+        .route("/chunk/{frame}", axum::routing::get(get_chunk))
+        .route("/mask", axum::routing::get(get_mask))
+        .route("/gainmap", axum::routing::get(get_gainmap))
+        .route("/angles", axum::routing::get(get_angles))
+        .route("/frame_meta/{frame}", axum::routing::get(get_frame_meta))
+        .route("/max_pixel/{frame}", axum::routing::get(get_max_pixel))
+        .route("/histogram/{frame}", axum::routing::get(get_histogram))
+        .route("/mipmap/{frame}", axum::routing::get(get_mipmap))
+        .route("/resolution_map", axum::routing::get(get_resolution_map))
+        .route("/montage", axum::routing::get(get_montage))
+        .route("/project", axum::routing::get(get_project))
+        .route("/frames", axum::routing::get(get_frames))
+        .route("/thumbnail/{frame}", axum::routing::get(get_thumbnail))
+        .route("/stats/stream", axum::routing::get(get_stats_stream))
+        .route("/stream", axum::routing::get(get_stream))
+        .route("/next_active/{frame}", axum::routing::get(get_next_active))
+        .route("/admin/clear_cache", axum::routing::post(clear_cache))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            track_activity,
+        ))
+        // Outside the activity layer: neither counts as use nor fails once idle.
+        .route("/version", axum::routing::get(get_version))
+        .layer(axum::middleware::from_fn(request_id))
+        .with_state(state)
+        // Inside CORS so preflights are answered before negotiation and CORS
+        // headers are added to the compressed response. The default predicate
+        // skips small bodies, images, SSE and ranged (206) responses.
+        .layer(CompressionLayer::new().gzip(true).deflate(true))
         .layer(cors)
 }
 
-/// Return detector metadata for the currently-open file as JSON.
-/// The `?v=...` query param used by the frontend for cache-busting is ignored.
-async fn get_metadata(State(state): State<ServerState>) -> impl IntoResponse {
+/// Close the active file once no request has arrived for `timeout`, dropping
+/// the reader and cached images. Runs until the server shuts down.
+pub async fn close_when_idle(state: ServerState, timeout: Duration) {
+    let interval = (timeout / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+    loop {
+        tokio::time::sleep(interval).await;
+        if state.activity.idle_for() < timeout {
+            continue;
+        }
+        let mut guard = state.reader.lock().await;
+        // Re-check under the lock: a request may have just come in.
+        if guard.is_none() || state.activity.idle_for() < timeout {
+            continue;
+        }
+        *guard = None;
+        state.generation.fetch_add(1, Ordering::SeqCst);
+        state.activity.set_closed_idle(true);
+        drop(guard);
+        state.clear_caches();
+        tracing::info!("Closed file after {}s idle", timeout.as_secs());
+    }
+}
+
+/// Record each request for the idle timeout. Once the file has been closed
+/// for inactivity, requests fail with 410 Gone and a message saying so, so
+/// the frontend can tell this apart from no file ever being open and reopen.
+async fn track_activity(
+    State(state): State<ServerState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.activity.closed_idle() {
+        return (StatusCode::GONE, "File closed due to inactivity").into_response();
+    }
+    state.activity.touch();
+    next.run(request).await
+}
+
+static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied `X-Request-Id` that is used as given.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Run each request in a tracing span carrying a correlation ID, taken from
+/// the `X-Request-Id` header or generated, and echo the ID in the response.
+/// Together with [`spawn_blocking`] this ties reader timings in the log to the
+/// request that caused them.
+async fn request_id(request: Request, next: Next) -> Response {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("{:06x}", NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+    let span = tracing::info_span!(
+        "request",
+        id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), value);
+    }
+    response
+}
+
+/// `tokio::task::spawn_blocking`, but running `f` inside the caller's
+/// tracing span so its logs keep the request's correlation ID.
+fn spawn_blocking<F, R>(f: F) -> tokio::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(f))
+}
+
+#[derive(Serialize)]
+struct FileInfo {
+    open: bool,
+    frame_count: Option<usize>,
+    path: Option<String>,
+    /// Reader format, e.g. `"nxs"`.
+    format: Option<&'static str>,
+    /// Image dataset within an HDF5 file.
+    dataset_path: Option<String>,
+    /// Whether every file the frames are read from is present; `false` if
+    /// some source files of a virtual dataset are missing, so frames
+    /// backed by them will fail to load.
+    vds_sources_available: Option<bool>,
+}
+
+/// Return whether a file is open and, if so, its path, format, image dataset,
+/// frame count and whether all its data files are present as JSON, so the
+/// frontend can restore its view after a reload. With no file open this is
+/// `{"open": false, ...}` with the other fields null, not an error.
+async fn get_info(State(state): State<ServerState>) -> impl IntoResponse {
     let reader_arc = state.reader.clone();
 
-    let result = tokio::task::spawn_blocking(move || {
+    let result = spawn_blocking(move || {
         let guard = reader_arc.blocking_lock();
         let Some(reader) = guard.as_ref() else {
-            return Err("No file open".to_string());
+            return Ok(FileInfo {
+                open: false,
+                frame_count: None,
+                path: None,
+                format: None,
+                dataset_path: None,
+                vds_sources_available: None,
+            });
         };
-        reader.metadata().map_err(|e| e.to_string())
+        Ok::<_, String>(FileInfo {
+            open: true,
+            frame_count: Some(reader.frame_count().map_err(|e| e.to_string())?),
+            path: Some(reader.path().display().to_string()),
+            format: Some(reader.format_name()),
+            dataset_path: reader.dataset_path().map(str::to_owned),
+            vds_sources_available: Some(reader.sources_available()),
+        })
     })
     .await;
 
     match result {
-        Ok(Ok(meta)) => Json(meta).into_response(),
+        Ok(Ok(info)) => Json(info).into_response(),
         Ok(Err(e)) => {
-            tracing::error!("metadata read error: {e}");
+            tracing::error!("info error: {e}");
             (StatusCode::INTERNAL_SERVER_ERROR, e).into_response()
         }
-        Err(_) => (StatusCode::NOT_FOUND, "No file open").into_response(),
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Return detector metadata for the currently-open file as JSON.
+/// The `?v=...` query param used by the frontend for cache-busting is ignored.
+/// The metadata is read once per file; see [`cached_metadata`].
+async fn get_metadata(State(state): State<ServerState>) -> impl IntoResponse {
+    let result = spawn_blocking(move || {
+        let guard = state.reader.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        cached_metadata(&state, reader.as_ref())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(meta)) => Json(&*meta).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("metadata read error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Everything the frontend needs to initialise a view, in one document.
+#[derive(Serialize)]
+struct Manifest {
+    /// Detector metadata, including optional per-frame sections such as
+    /// `scan_positions` when the file provides them.
+    metadata: ImageMetadata,
+    frame_count: usize,
+    /// Timestamp of each frame in seconds (see
+    /// [`crate::readers::FrameMetadata::timestamp_s`]), if every frame has
+    /// one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_times: Option<Vec<f64>>,
+    format: &'static str,
+    /// Value untrusted pixels are sent as in 16-bit frames.
+    masked_value: u16,
+}
+
+/// Return the metadata, frame count, frame timestamps and format of the open
+/// file, and the value masked pixels are sent as, as one JSON document, so a
+/// client can bootstrap with a single request.
+async fn get_manifest(State(state): State<ServerState>) -> impl IntoResponse {
+    let result = spawn_blocking(move || {
+        let guard = state.reader.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let build = || -> anyhow::Result<Manifest> {
+            Ok(Manifest {
+                metadata: cached_metadata(&state, reader.as_ref())?.as_ref().clone(),
+                frame_count: reader.frame_count()?,
+                frame_times: reader.frame_times()?,
+                format: reader.format_name(),
+                masked_value: u16::MAX,
+            })
+        };
+        build().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(manifest)) => Json(manifest).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("manifest read error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VersionInfo {
+    app_version: &'static str,
+    /// Short commit hash the backend was built from, if known.
+    git_sha: Option<&'static str>,
+    hdf5_version: String,
+    supported_formats: &'static [&'static str],
+}
+
+/// Return the backend's version and build info, for compatibility checks and
+/// bug reports. Works whether or not a file is open.
+async fn get_version() -> impl IntoResponse {
+    let (major, minor, patch) = hdf5::library_version();
+    Json(VersionInfo {
+        app_version: env!("CARGO_PKG_VERSION"),
+        git_sha: option_env!("DIFFRANT_GIT_SHA"),
+        hdf5_version: format!("{major}.{minor}.{patch}"),
+        supported_formats: crate::readers::SUPPORTED_EXTENSIONS,
+    })
+}
+
+/// Byte order used to serialize u16 pixels in the `/image` response.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ByteOrder {
+    #[default]
+    Le,
+    Be,
+}
+
+impl ByteOrder {
+    fn as_str(self) -> &'static str {
+        match self {
+            ByteOrder::Le => "le",
+            ByteOrder::Be => "be",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageQuery {
+    #[serde(default)]
+    byteorder: ByteOrder,
+    /// Bits per transferred pixel: 16 (raw, default) or 8 (autoscaled).
+    #[serde(default = "default_depth")]
+    depth: u8,
+    normalize: Option<Normalize>,
+    subtract_pedestal: Option<SubtractPedestal>,
+    #[serde(default)]
+    units: Units,
+    #[serde(default)]
+    dtype: PixelDtype,
+    /// Non-zero to add `X-Debug-*` headers describing how the frame was decoded.
+    #[serde(default)]
+    debug: u8,
+    /// Region to send instead of the whole frame: left column, top row,
+    /// width and height. All four or none.
+    x: Option<usize>,
+    y: Option<usize>,
+    w: Option<usize>,
+    h: Option<usize>,
+}
+
+/// Type pixels are read and sent as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PixelDtype {
+    /// Display counts, as decoded by [`crate::readers::Reader::read_frame`].
+    #[default]
+    U16,
+    /// Values as stored, for float-valued (e.g. corrected) data; see
+    /// [`crate::readers::Reader::read_frame_f32`].
+    F32,
+}
+
+/// Optional normalization applied to a frame before it is sent.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Normalize {
+    /// Per-pixel `(frame - mean) / σ` against a sample of frames, sent as f32.
+    Zscore,
+}
+
+/// Baseline offset removed from a frame before it is sent.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SubtractPedestal {
+    /// Estimated per frame from the image corners.
+    Auto,
+}
+
+/// Physical units pixels are sent in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Units {
+    /// Raw detector counts (u16).
+    #[default]
+    Counts,
+    /// Counts divided by the detector gain (f32).
+    Photons,
+    /// Photons divided by the exposure time (f32).
+    PhotonsPerS,
+}
+
+impl Units {
+    fn as_str(self) -> &'static str {
+        match self {
+            Units::Counts => "counts",
+            Units::Photons => "photons",
+            Units::PhotonsPerS => "photons_per_s",
+        }
+    }
+
+    /// Factor converting counts to these units, or why it can't be known.
+    fn factor(self, metadata: &ImageMetadata) -> Result<f64, String> {
+        let photons = 1.0 / metadata.gain.unwrap_or(1.0);
+        match self {
+            Units::Counts => Ok(1.0),
+            Units::Photons => Ok(photons),
+            Units::PhotonsPerS => match metadata.count_time_s {
+                Some(t) if t > 0.0 => Ok(photons / t),
+                _ => Err("Exposure time is unknown for this file".to_string()),
+            },
+        }
     }
 }
 
-/// Return a raw frame as little-endian u16 bytes (application/octet-stream).
-/// `:frame` is a 0-based frame index.
+fn default_depth() -> u8 {
+    16
+}
+
+/// A decoded frame in the representation requested by the client.
+enum FrameBytes {
+    /// Shared with the frame cache when sent untransformed.
+    U16(Arc<Vec<u16>>),
+    F32(Vec<f32>),
+    U8(Scaled8),
+    ZScore {
+        pixels: Vec<f32>,
+        frames_used: usize,
+    },
+    Calibrated {
+        pixels: Vec<f32>,
+        units: Units,
+        factor: f64,
+    },
+}
+
+/// Return a raw frame as u16 bytes (application/octet-stream).
+/// `:frame` is a 0-based frame index. Pixels are little-endian unless
+/// `?byteorder=be` is given; the order used is echoed in `X-Byte-Order`.
+/// Bodies larger than [`DEFAULT_STREAM_THRESHOLD`] (or the configured
+/// threshold) are streamed in chunks as they are serialized. Decoded frames
+/// are kept in a shared LRU cache (see [`crate::cache`]), so revisiting a
+/// recent frame skips the read, and its neighbours are prefetched into it
+/// (see [`prefetch_neighbours`]).
+///
+/// With `?depth=8` the frame is autoscaled to one byte per pixel instead
+/// (see [`render::scale_to_depth8`]); the limits used are returned in the
+/// `X-Display-Min`/`X-Display-Max` headers and masked pixels are sent as
+/// the value in `X-Masked-Value`.
+///
+/// With `?normalize=zscore` each pixel is sent as an f32 z-score against the
+/// per-pixel mean and variance of up to [`stats::MEAN_VARIANCE_SAMPLE_FRAMES`]
+/// evenly spaced frames. Those images are computed on first use and cached
+/// until the file changes or grows. Untrusted pixels are NaN.
+///
+/// With `?subtract_pedestal=auto` a baseline offset estimated from the median
+/// of the image corners (see [`stats::corner_pedestal`]) is subtracted from
+/// trusted pixels, clamping at 0, and reported in `X-Pedestal`. This applies
+/// to 16- and 8-bit frames but not to z-scores.
+///
+/// With `?units=photons` or `?units=photons_per_s` pixels are sent as f32
+/// counts divided by the detector gain (1 if the file has none, as for
+/// photon-counting detectors) and, for a rate, by the exposure time. The
+/// factor applied is returned in `X-Calibration-Factor` and untrusted pixels
+/// are NaN. A rate for a file with no exposure time is a 422.
+///
+/// With `?dtype=f32` pixels are read with
+/// [`crate::readers::Reader::read_frame_f32`] and sent as f32, so float data
+/// keeps its fractional and negative values and large integer counts aren't
+/// clipped; `X-Pixel-Dtype` is `f32`. These frames bypass the frame cache.
+///
+/// With `?debug=1`, `X-Debug-*` headers report the on-disk dtype, decode
+/// time, number of saturated pixels, whether bytes were swapped from host
+/// order, and the transforms applied, comma-separated: the reader's (such as
+/// `transpose` for fast-major data), then the server's (such as `depth8`),
+/// or `none`.
+///
+/// Responses carry `Accept-Ranges: bytes`. A single `Range: bytes=...`
+/// request returns 206 with just those bytes of the body and
+/// `Content-Range`; the whole frame is still decoded. A range starting past
+/// the end is a 416, and malformed or multi-range headers get the full body.
+///
+/// With `?x=&y=&w=&h=` only that region of the frame is sent; see
+/// [`get_image_region`].
+///
+/// With `Accept: application/json` a summary of the frame is returned
+/// instead of its pixels; see [`get_image_stats`].
 async fn get_image(
     State(state): State<ServerState>,
     Path(frame): Path<usize>,
+    Query(query): Query<ImageQuery>,
+    headers: axum::http::HeaderMap,
 ) -> impl IntoResponse {
+    if query.depth != 8 && query.depth != 16 {
+        return (StatusCode::BAD_REQUEST, "depth must be 8 or 16").into_response();
+    }
+    if query.normalize.is_some() && query.depth != 16 {
+        return (
+            StatusCode::BAD_REQUEST,
+            "normalize cannot be combined with depth",
+        )
+            .into_response();
+    }
+    if query.units != Units::Counts && (query.normalize.is_some() || query.depth != 16) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "units cannot be combined with normalize or depth",
+        )
+            .into_response();
+    }
+    if query.normalize.is_some() && query.subtract_pedestal.is_some() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "normalize cannot be combined with subtract_pedestal",
+        )
+            .into_response();
+    }
+    if query.dtype == PixelDtype::F32
+        && (query.depth != 16
+            || query.normalize.is_some()
+            || query.subtract_pedestal.is_some()
+            || query.units != Units::Counts)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            "dtype=f32 cannot be combined with depth, normalize, subtract_pedestal or units",
+        )
+            .into_response();
+    }
+    let region = match (query.x, query.y, query.w, query.h) {
+        (None, None, None, None) => None,
+        (Some(x), Some(y), Some(w), Some(h)) => Some([x, y, w, h]),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "x, y, w and h must be given together",
+            )
+                .into_response();
+        }
+    };
+    let transformed = query.depth != 16
+        || query.normalize.is_some()
+        || query.subtract_pedestal.is_some()
+        || query.units != Units::Counts
+        || query.dtype != PixelDtype::U16;
+    if accepts_json(&headers) {
+        if transformed || region.is_some() {
+            return (
+                StatusCode::BAD_REQUEST,
+                "JSON stats cannot be combined with a region, depth, normalize, \
+                 subtract_pedestal, units or dtype",
+            )
+                .into_response();
+        }
+        return get_image_stats(state, frame).await;
+    }
+    if let Some(region) = region {
+        if transformed {
+            return (
+                StatusCode::BAD_REQUEST,
+                "a region cannot be combined with depth, normalize, subtract_pedestal, units or dtype",
+            )
+                .into_response();
+        }
+        return get_image_region(state, frame, region, query.byteorder, &headers).await;
+    }
     let reader_arc = state.reader.clone();
+    let stream_threshold = state.config.stream_threshold;
+    let prefetch_state = state.clone();
+    let depth = query.depth;
+    let normalize = query.normalize;
+    let subtract_pedestal = query.subtract_pedestal.is_some();
+    let units = query.units;
+    let dtype = query.dtype;
+    let debug = query.debug != 0;
 
-    let result = tokio::task::spawn_blocking(move || {
+    let result = spawn_blocking(move || {
         let guard = reader_arc.blocking_lock();
         let Some(reader) = guard.as_ref() else {
-            return Err("No file open".to_string());
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+        let t0 = std::time::Instant::now();
+        let generation = state.generation.load(Ordering::SeqCst);
+        if dtype == PixelDtype::F32 {
+            // Not cached: the frame cache holds u16 frames.
+            let (pixels, _, _) = reader.read_frame_f32(frame).map_err(internal)?;
+            let decode_ms = t0.elapsed().as_secs_f64() * 1000.0;
+            let provenance = if debug {
+                let metadata = cached_metadata(&state, reader.as_ref()).map_err(internal)?;
+                let trusted_max = metadata.trusted_range_max;
+                Some(DecodeProvenance {
+                    source_dtype: metadata.source_dtype.clone(),
+                    reader_transforms: reader.applied_transforms(),
+                    decode_ms,
+                    saturated: pixels
+                        .iter()
+                        .filter(|&&v| f64::from(v) > trusted_max)
+                        .count(),
+                })
+            } else {
+                None
+            };
+            return Ok((FrameBytes::F32(pixels), provenance, None, generation));
+        }
+        let cached =
+            read_frame_cached(&state, reader.as_ref(), generation, frame).map_err(internal)?;
+        let (width, height) = (cached.width, cached.height);
+        // Still the frame cache's copy; only a pedestal subtraction makes one.
+        let mut pixels = cached.pixels;
+        let decode_ms = t0.elapsed().as_secs_f64() * 1000.0;
+
+        let metadata = if debug
+            || normalize.is_some()
+            || depth == 8
+            || subtract_pedestal
+            || units != Units::Counts
+        {
+            Some(cached_metadata(&state, reader.as_ref()).map_err(internal)?)
+        } else {
+            None
+        };
+        let trusted_max = metadata.as_ref().map_or(0.0, |m| m.trusted_range_max);
+        let calibration = match &metadata {
+            Some(m) if units != Units::Counts => Some(
+                units
+                    .factor(m)
+                    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?,
+            ),
+            _ => None,
+        };
+        let provenance = if debug {
+            Some(DecodeProvenance {
+                source_dtype: metadata.and_then(|m| m.source_dtype.clone()),
+                reader_transforms: reader.applied_transforms(),
+                decode_ms,
+                saturated: pixels
+                    .iter()
+                    .filter(|&&v| f64::from(v) > trusted_max)
+                    .count(),
+            })
+        } else {
+            None
+        };
+        let pedestal = if subtract_pedestal {
+            let pedestal = stats::corner_pedestal(&pixels, width, height, trusted_max);
+            let pixels = Arc::make_mut(&mut pixels);
+            stats::subtract_pedestal(pixels, pedestal, trusted_max);
+            Some(pedestal)
+        } else {
+            None
+        };
+
+        let bytes = if let Some(Normalize::Zscore) = normalize {
+            let mv = cached_mean_variance(&state, reader.as_ref(), generation, trusted_max)
+                .map_err(internal)?;
+            FrameBytes::ZScore {
+                pixels: mv.zscore(&pixels, trusted_max).map_err(internal)?,
+                frames_used: mv.frames_used,
+            }
+        } else if let Some(factor) = calibration {
+            FrameBytes::Calibrated {
+                pixels: pixels
+                    .iter()
+                    .map(|&v| {
+                        if f64::from(v) > trusted_max {
+                            f32::NAN
+                        } else {
+                            (f64::from(v) * factor) as f32
+                        }
+                    })
+                    .collect(),
+                units,
+                factor,
+            }
+        } else if depth == 8 {
+            FrameBytes::U8(render::scale_to_depth8(&pixels, trusted_max))
+        } else {
+            FrameBytes::U16(pixels)
         };
-        reader.read_frame(frame).map_err(|e| e.to_string())
+        Ok((bytes, provenance, pedestal, generation))
     })
     .await;
 
     match result {
-        Ok(Ok((pixels, _width, _height))) => {
-            let bytes: Vec<u8> = pixels.iter().flat_map(|&v| v.to_le_bytes()).collect();
-            (
-                [(axum::http::header::CONTENT_TYPE, "application/octet-stream")],
+        Ok(Ok((bytes, provenance, pedestal, generation))) => {
+            if !matches!(bytes, FrameBytes::F32(_)) {
+                prefetch_neighbours(prefetch_state, frame, generation);
+            }
+            let transform = match &bytes {
+                FrameBytes::U16(_) | FrameBytes::F32(_) => "none",
+                FrameBytes::U8(_) => "depth8",
+                FrameBytes::ZScore { .. } => "zscore",
+                FrameBytes::Calibrated { .. } => "calibrated",
+            };
+            let mut response = frame_response(
                 bytes,
-            )
-                .into_response()
+                query.byteorder,
+                stream_threshold,
+                headers.get(header::RANGE),
+            );
+            if let Some(pedestal) = pedestal {
+                response.headers_mut().insert(
+                    HeaderName::from_static("x-pedestal"),
+                    HeaderValue::from(pedestal),
+                );
+            }
+            if let Some(provenance) = provenance {
+                provenance.apply(&mut response, query.byteorder, transform);
+            }
+            response
         }
-        Ok(Err(e)) => {
-            tracing::error!("frame read error: {e}");
-            (StatusCode::INTERNAL_SERVER_ERROR, e).into_response()
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("frame read error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Whether the `Accept` header asks for JSON rather than the default octet
+/// stream: it lists `application/json` and not `application/octet-stream`.
+fn accepts_json(headers: &axum::http::HeaderMap) -> bool {
+    let mut json = false;
+    for value in headers.get_all(header::ACCEPT) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for media_type in value.split(',') {
+            let media_type = media_type.split(';').next().unwrap_or("").trim();
+            if media_type.eq_ignore_ascii_case("application/octet-stream") {
+                return false;
+            }
+            json |= media_type.eq_ignore_ascii_case("application/json");
+        }
+    }
+    json
+}
+
+/// The `/image` JSON summary of a frame.
+#[derive(Serialize)]
+struct ImageStats {
+    width: usize,
+    height: usize,
+    min: u16,
+    max: u16,
+    mean: f64,
+    /// Pixels above `trusted_range_max`.
+    saturated_count: usize,
+}
+
+/// The `/image` response for `Accept: application/json`: the frame's size
+/// and the minimum, maximum and mean of its trusted pixels, as for
+/// [`FrameStats`], with the untrusted ones counted in `saturated_count`.
+/// The frame is read through the frame cache.
+async fn get_image_stats(state: ServerState, frame: usize) -> Response {
+    let result = spawn_blocking(move || {
+        let guard = state.reader.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let generation = state.generation.load(Ordering::SeqCst);
+        let cached =
+            read_frame_cached(&state, reader.as_ref(), generation, frame).map_err(internal)?;
+        let trusted_max = cached_metadata(&state, reader.as_ref())
+            .map_err(internal)?
+            .trusted_range_max;
+        let stats = FrameStats::compute(frame, &cached.pixels, trusted_max);
+        Ok(ImageStats {
+            width: cached.width,
+            height: cached.height,
+            min: stats.min,
+            max: stats.max,
+            mean: stats.mean,
+            saturated_count: stats.overloads,
+        })
+    })
+    .await;
+
+    match result {
+        Ok(Ok(stats)) => Json(stats).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("frame stats error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// The `/image` response for a `w` x `h` region at (`x`, `y`): row-major u16
+/// pixels of just that region, clamped to the frame, with the region used in
+/// `X-Region-X`, `X-Region-Y`, `X-Width` and `X-Height`. A region with no
+/// pixels in the frame is a 400.
+///
+/// A frame in the frame cache is cropped from it; otherwise only the region
+/// is read (see [`crate::readers::Reader::read_region`]), so for chunked
+/// HDF5 data just the overlapping chunks are decompressed. Regions are not
+/// cached and don't trigger prefetching.
+async fn get_image_region(
+    state: ServerState,
+    frame: usize,
+    [x, y, w, h]: [usize; 4],
+    byteorder: ByteOrder,
+    headers: &axum::http::HeaderMap,
+) -> Response {
+    let stream_threshold = state.config.stream_threshold;
+    let result = spawn_blocking(move || {
+        let guard = state.reader.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let generation = state.generation.load(Ordering::SeqCst);
+        let cached = state.frame_cache.get(generation, frame);
+        let (width, height) = match &cached {
+            Some(cached) => (cached.width, cached.height),
+            None => {
+                let [width, height] = cached_metadata(&state, reader.as_ref())
+                    .map_err(internal)?
+                    .panel_size_fast_slow;
+                (width as usize, height as usize)
+            }
+        };
+        let clamped_w = w.min(width.saturating_sub(x));
+        let clamped_h = h.min(height.saturating_sub(y));
+        if clamped_w == 0 || clamped_h == 0 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Region {w}x{h} at ({x}, {y}) has no pixels in the {width}x{height} frame"),
+            ));
+        }
+        let (w, h) = (clamped_w, clamped_h);
+        let pixels = match cached {
+            Some(cached) => crate::readers::crop(&cached.pixels, width, x, y, w, h),
+            None => reader.read_region(frame, x, y, w, h).map_err(internal)?,
+        };
+        Ok((pixels, w, h))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((pixels, w, h))) => {
+            let mut response = frame_response(
+                FrameBytes::U16(Arc::new(pixels)),
+                byteorder,
+                stream_threshold,
+                headers.get(header::RANGE),
+            );
+            let response_headers = response.headers_mut();
+            for (name, value) in [
+                ("x-region-x", x),
+                ("x-region-y", y),
+                ("x-width", w),
+                ("x-height", h),
+            ] {
+                response_headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+            }
+            response
+        }
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("region read error: {e}");
+            }
+            (status, e).into_response()
         }
         Err(e) => {
             tracing::error!("spawn_blocking panicked: {e}");
@@ -87,3 +1135,2559 @@ async fn get_image(
         }
     }
 }
+
+/// `reader.read_frame(frame)` through the shared frame cache, then the disk
+/// cache if there is one.
+fn read_frame_cached(
+    state: &ServerState,
+    reader: &dyn crate::readers::Reader,
+    generation: u64,
+    frame: usize,
+) -> anyhow::Result<CachedFrame> {
+    if let Some(cached) = state.frame_cache.get(generation, frame) {
+        return Ok(cached);
+    }
+    let disk_cache = state.disk_cache.as_deref();
+    if let Some(cached) = disk_cache.and_then(|disk| disk.get(reader, frame)) {
+        state.frame_cache.insert(generation, frame, cached.clone());
+        return Ok(cached);
+    }
+    let (pixels, width, height) = reader.read_frame(frame)?;
+    let cached = CachedFrame {
+        pixels: Arc::new(pixels),
+        width,
+        height,
+    };
+    if let Some(disk) = disk_cache {
+        if let Err(e) = disk.insert(reader, frame, &cached) {
+            tracing::warn!("disk cache: cannot store frame {frame}: {e}");
+        }
+    }
+    state.frame_cache.insert(generation, frame, cached.clone());
+    Ok(cached)
+}
+
+/// Decode the frames within `prefetch_radius` of `frame` into the frame
+/// cache in the background, nearest first, so stepping to a neighbour is a
+/// cache hit. Frames already cached or out of range are skipped.
+///
+/// Prefetching is low priority: it only proceeds while the reader is free,
+/// giving way to any request that holds it, and stops as soon as the reader
+/// is replaced.
+fn prefetch_neighbours(state: ServerState, frame: usize, generation: u64) {
+    let radius = state.config.prefetch_radius;
+    if radius == 0 || !state.frame_cache.is_enabled() {
+        return;
+    }
+    spawn_blocking(move || {
+        let neighbours = (1..=radius)
+            .flat_map(|d| [frame.checked_add(d), frame.checked_sub(d)])
+            .flatten();
+        for neighbour in neighbours {
+            if state.frame_cache.contains(generation, neighbour) {
+                continue;
+            }
+            let Ok(guard) = state.reader.try_lock() else {
+                return;
+            };
+            let Some(reader) = guard.as_ref() else {
+                return;
+            };
+            if state.generation.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            if neighbour >= reader.frame_count().unwrap_or(0) {
+                continue;
+            }
+            match reader.read_frame(neighbour) {
+                Ok((pixels, width, height)) => {
+                    let cached = CachedFrame {
+                        pixels: Arc::new(pixels),
+                        width,
+                        height,
+                    };
+                    state.frame_cache.insert(generation, neighbour, cached);
+                }
+                Err(e) => {
+                    tracing::debug!("prefetch of frame {neighbour} failed: {e}");
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// `/image` bodies up to this size are serialized in one buffer unless
+/// [`crate::STREAM_THRESHOLD_ENV`] says otherwise; larger ones are streamed.
+pub const DEFAULT_STREAM_THRESHOLD: usize = 16 << 20;
+
+/// Pixels serialized per chunk of a streamed body.
+const STREAM_CHUNK_PIXELS: usize = 256 * 1024;
+
+/// Serialize bytes `range` of `pixels`, as encoded by `to_bytes`, into a
+/// response body. Only the pixels overlapping `range` are encoded. Bodies up
+/// to `threshold` bytes are built in one buffer; larger ones are streamed in
+/// chunks, so the serialized copy of a big frame is never held whole.
+fn pixel_body<T, const N: usize>(
+    pixels: Arc<Vec<T>>,
+    range: Range<usize>,
+    threshold: usize,
+    to_bytes: fn(T) -> [u8; N],
+) -> axum::body::Body
+where
+    T: Copy + Send + Sync + 'static,
+{
+    let first = range.start / N;
+    let last = range.end.div_ceil(N);
+    let mut skip = range.start - first * N;
+    let mut remaining = range.len();
+    if remaining <= threshold {
+        let bytes: Vec<u8> = pixels[first..last]
+            .iter()
+            .flat_map(|&v| to_bytes(v))
+            .skip(skip)
+            .take(remaining)
+            .collect();
+        return bytes.into();
+    }
+    let chunks = (last - first).div_ceil(STREAM_CHUNK_PIXELS);
+    let stream = tokio_stream::iter((0..chunks).map(move |i| {
+        let start = first + i * STREAM_CHUNK_PIXELS;
+        let end = (start + STREAM_CHUNK_PIXELS).min(last);
+        let bytes: Vec<u8> = pixels[start..end]
+            .iter()
+            .flat_map(|&v| to_bytes(v))
+            .skip(skip)
+            .take(remaining)
+            .collect();
+        skip = 0;
+        remaining -= bytes.len();
+        Ok::<_, std::convert::Infallible>(bytes)
+    }));
+    axum::body::Body::from_stream(stream)
+}
+
+/// [`pixel_body`] in the requested byte order.
+fn u16_body(
+    pixels: Arc<Vec<u16>>,
+    byteorder: ByteOrder,
+    range: Range<usize>,
+    threshold: usize,
+) -> axum::body::Body {
+    match byteorder {
+        ByteOrder::Le => pixel_body(pixels, range, threshold, u16::to_le_bytes),
+        ByteOrder::Be => pixel_body(pixels, range, threshold, u16::to_be_bytes),
+    }
+}
+
+/// [`pixel_body`] in the requested byte order.
+fn f32_body(
+    pixels: Vec<f32>,
+    byteorder: ByteOrder,
+    range: Range<usize>,
+    threshold: usize,
+) -> axum::body::Body {
+    match byteorder {
+        ByteOrder::Le => pixel_body(Arc::new(pixels), range, threshold, f32::to_le_bytes),
+        ByteOrder::Be => pixel_body(Arc::new(pixels), range, threshold, f32::to_be_bytes),
+    }
+}
+
+/// How a `Range` request header applies to a body of known length.
+enum ByteRange {
+    /// No usable range: serve the whole body with 200.
+    Full,
+    /// Serve these bytes with 206.
+    Partial(Range<usize>),
+    /// The range starts past the end of the body: 416.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Interpret a single `bytes=start-end`, `bytes=start-` or `bytes=-suffix`
+    /// range (end inclusive, as in HTTP) against a body of `len` bytes.
+    /// Malformed headers and multi-range requests fall back to [`Full`].
+    ///
+    /// [`Full`]: ByteRange::Full
+    fn parse(header: Option<&HeaderValue>, len: usize) -> Self {
+        let Some(spec) = header
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.trim().strip_prefix("bytes="))
+        else {
+            return Self::Full;
+        };
+        if spec.contains(',') {
+            return Self::Full;
+        }
+        let Some((start, end)) = spec.split_once('-') else {
+            return Self::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+        let range = match (start.parse::<usize>(), end.parse::<usize>()) {
+            (Ok(start), Ok(end)) if start <= end => start..end.saturating_add(1).min(len),
+            (Ok(start), Err(_)) if end.is_empty() => start..len,
+            (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => {
+                len.saturating_sub(suffix)..len
+            }
+            _ => return Self::Full,
+        };
+        if range.start >= len {
+            Self::Unsatisfiable
+        } else {
+            Self::Partial(range)
+        }
+    }
+}
+
+/// Serialize a decoded frame into an octet-stream response with the headers
+/// describing its representation. Bodies over `stream_threshold` bytes are
+/// streamed; `Content-Length` is set either way.
+///
+/// A single-range `Range` header is honoured with a 206 response carrying
+/// only those bytes; see [`ByteRange::parse`].
+fn frame_response(
+    bytes: FrameBytes,
+    byteorder: ByteOrder,
+    stream_threshold: usize,
+    range_header: Option<&HeaderValue>,
+) -> Response {
+    let total = match &bytes {
+        FrameBytes::U16(pixels) => pixels.len() * 2,
+        FrameBytes::U8(scaled) => scaled.bytes.len(),
+        FrameBytes::F32(pixels)
+        | FrameBytes::ZScore { pixels, .. }
+        | FrameBytes::Calibrated { pixels, .. } => pixels.len() * 4,
+    };
+    let (range, partial) = match ByteRange::parse(range_header, total) {
+        ByteRange::Full => (0..total, false),
+        ByteRange::Partial(range) => (range, true),
+        ByteRange::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{total}"))],
+            )
+                .into_response();
+        }
+    };
+    let len = range.len();
+
+    let mut response = match bytes {
+        FrameBytes::U16(pixels) => {
+            let body = u16_body(pixels, byteorder, range.clone(), stream_threshold);
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream"),
+                    (HeaderName::from_static("x-byte-order"), byteorder.as_str()),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        FrameBytes::F32(pixels) => {
+            let body = f32_body(pixels, byteorder, range.clone(), stream_threshold);
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream"),
+                    (HeaderName::from_static("x-pixel-dtype"), "f32"),
+                    (HeaderName::from_static("x-byte-order"), byteorder.as_str()),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        FrameBytes::ZScore {
+            pixels,
+            frames_used,
+        } => {
+            let body = f32_body(pixels, byteorder, range.clone(), stream_threshold);
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (HeaderName::from_static("x-pixel-dtype"), "f32".to_string()),
+                    (
+                        HeaderName::from_static("x-byte-order"),
+                        byteorder.as_str().to_string(),
+                    ),
+                    (HeaderName::from_static("x-normalize"), "zscore".to_string()),
+                    (
+                        HeaderName::from_static("x-normalize-frames"),
+                        frames_used.to_string(),
+                    ),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        FrameBytes::Calibrated {
+            pixels,
+            units,
+            factor,
+        } => {
+            let body = f32_body(pixels, byteorder, range.clone(), stream_threshold);
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (HeaderName::from_static("x-pixel-dtype"), "f32".to_string()),
+                    (
+                        HeaderName::from_static("x-byte-order"),
+                        byteorder.as_str().to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-pixel-units"),
+                        units.as_str().to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-calibration-factor"),
+                        factor.to_string(),
+                    ),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        FrameBytes::U8(scaled) => {
+            let mut body = scaled.bytes;
+            body.truncate(range.end);
+            body.drain(..range.start);
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (HeaderName::from_static("x-image-depth"), "8".to_string()),
+                    (
+                        HeaderName::from_static("x-display-min"),
+                        scaled.display_min.to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-display-max"),
+                        scaled.display_max.to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-masked-value"),
+                        DEPTH8_MASKED.to_string(),
+                    ),
+                ],
+                body,
+            )
+                .into_response()
+        }
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if partial {
+        let content_range = format!("bytes {}-{}/{total}", range.start, range.end - 1);
+        if let Ok(value) = HeaderValue::from_str(&content_range) {
+            headers.insert(header::CONTENT_RANGE, value);
+        }
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    }
+    response
+}
+
+/// What the backend did to produce a frame, reported with `?debug=1`.
+struct DecodeProvenance {
+    source_dtype: Option<String>,
+    /// See [`crate::readers::Reader::applied_transforms`].
+    reader_transforms: Vec<&'static str>,
+    decode_ms: f64,
+    /// Pixels above `trusted_range_max`.
+    saturated: usize,
+}
+
+impl DecodeProvenance {
+    /// Add `X-Debug-*` headers describing this decode to `response`.
+    /// `transform` is what the server did to the decoded frame, listed in
+    /// `X-Debug-Transforms` after the reader's own.
+    fn apply(&self, response: &mut Response, byteorder: ByteOrder, transform: &str) {
+        let native = if cfg!(target_endian = "little") {
+            ByteOrder::Le
+        } else {
+            ByteOrder::Be
+        };
+        let mut transforms = self.reader_transforms.clone();
+        if transform != "none" || transforms.is_empty() {
+            transforms.push(transform);
+        }
+        let values = [
+            (
+                "x-debug-source-dtype",
+                self.source_dtype
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_owned()),
+            ),
+            ("x-debug-decode-ms", format!("{:.2}", self.decode_ms)),
+            ("x-debug-saturated", self.saturated.to_string()),
+            (
+                "x-debug-byte-swap",
+                (byteorder.as_str() != native.as_str()).to_string(),
+            ),
+            ("x-debug-transforms", transforms.join(",")),
+        ];
+        let headers = response.headers_mut();
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClearCacheQuery {
+    /// Non-zero to also empty the on-disk frame cache.
+    #[serde(default)]
+    disk: u8,
+}
+
+#[derive(Serialize)]
+pub struct ClearCacheResult {
+    pub frames_freed: usize,
+    /// Total bytes released from the in-memory caches.
+    pub bytes_freed: usize,
+    pub mean_variance_freed: bool,
+    /// Bytes deleted from the on-disk frame cache; 0 unless asked to clear it.
+    pub disk_bytes_freed: u64,
+}
+
+/// Drop the in-memory caches (decoded frames and mean/variance images) to
+/// reclaim memory in a long session, and report how much was freed. Both
+/// refill on demand. With `?disk=1`, the on-disk frame cache, which
+/// otherwise persists across sessions, is emptied too.
+async fn clear_cache(
+    State(state): State<ServerState>,
+    Query(query): Query<ClearCacheQuery>,
+) -> impl IntoResponse {
+    let mut result = state.clear_caches();
+    if let Some(disk_cache) = state.disk_cache.clone().filter(|_| query.disk != 0) {
+        result.disk_bytes_freed = tokio::task::spawn_blocking(move || disk_cache.clear())
+            .await
+            .unwrap_or(0);
+    }
+    tracing::info!(
+        "Cleared caches: {} frames, {} bytes, {} bytes on disk",
+        result.frames_freed,
+        result.bytes_freed,
+        result.disk_bytes_freed
+    );
+    Json(result)
+}
+
+/// `reader.metadata()`, read once per file and then served from
+/// `state.metadata` until the reader is replaced or the file gains frames.
+/// The caller must hold the reader lock, so `reader` is the current one.
+fn cached_metadata(
+    state: &ServerState,
+    reader: &dyn crate::readers::Reader,
+) -> anyhow::Result<Arc<ImageMetadata>> {
+    let generation = state.generation.load(Ordering::SeqCst);
+    let frame_count = reader.frame_count()?;
+    let mut cache = state
+        .metadata
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(cached) = cache.as_ref() {
+        if cached.generation == generation && cached.frame_count == frame_count {
+            return Ok(cached.metadata.clone());
+        }
+    }
+    let metadata = Arc::new(reader.metadata()?);
+    *cache = Some(CachedMetadata {
+        generation,
+        frame_count,
+        metadata: metadata.clone(),
+    });
+    Ok(metadata)
+}
+
+/// `reader.mask()`, read once per file and then served from `state.mask`
+/// until the reader is replaced. The caller must hold the reader lock, so
+/// `reader` is the current one.
+fn cached_mask(
+    state: &ServerState,
+    reader: &dyn crate::readers::Reader,
+) -> anyhow::Result<Option<Arc<(Vec<u8>, usize, usize)>>> {
+    let generation = state.generation.load(Ordering::SeqCst);
+    let mut cache = state
+        .mask
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(cached) = cache.as_ref() {
+        if cached.generation == generation {
+            return Ok(cached.mask.clone());
+        }
+    }
+    let mask = reader.mask()?.map(Arc::new);
+    *cache = Some(CachedMask {
+        generation,
+        mask: mask.clone(),
+    });
+    Ok(mask)
+}
+
+/// Return the mean/variance images for the given reader, computing them if
+/// the cache is empty or was built for a different file or frame count.
+///
+/// Must be called with the reader lock held, so `generation` is the current
+/// reader's. The cache lock is held while computing so concurrent requests
+/// wait for one computation rather than each starting their own.
+fn cached_mean_variance(
+    state: &ServerState,
+    reader: &dyn crate::readers::Reader,
+    generation: u64,
+    trusted_max: f64,
+) -> anyhow::Result<Arc<MeanVariance>> {
+    let frame_count = reader.frame_count()?;
+    let mut cache = state
+        .mean_variance
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(cached) = cache.as_ref() {
+        if cached.generation == generation && cached.frame_count == frame_count {
+            return Ok(cached.stats.clone());
+        }
+    }
+
+    let max = stats::MEAN_VARIANCE_SAMPLE_FRAMES.min(state.config.scan_limit());
+    let frames = stats::sample_frames(frame_count, max);
+    let t0 = std::time::Instant::now();
+    let cancel = CancelToken::new(&state.analysis_epoch);
+    let mv = Arc::new(MeanVariance::compute(
+        reader,
+        &frames,
+        trusted_max,
+        &cancel,
+    )?);
+    tracing::info!(
+        frames = frames.len(),
+        elapsed_ms = t0.elapsed().as_millis(),
+        "built mean/variance images"
+    );
+    *cache = Some(CachedMeanVariance {
+        generation,
+        frame_count,
+        stats: mv.clone(),
+    });
+    Ok(mv)
+}
+
+/// JSON header of a `/frame/{frame}` container.
+#[derive(Serialize)]
+struct FrameHeader<'a> {
+    frame: usize,
+    width: usize,
+    height: usize,
+    /// Pixel type of the payload; always `"u16"`.
+    dtype: &'static str,
+    /// Byte order of the payload; always `"le"`.
+    byte_order: &'static str,
+    metadata: &'a ImageMetadata,
+    /// What the file records for this frame, e.g. its distance if the
+    /// detector moves.
+    frame_metadata: crate::readers::FrameMetadata,
+    /// Rotation angle of the frame in degrees, for rotation scans.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    angle: Option<f64>,
+}
+
+/// Return a frame together with its geometry in a single response, so a
+/// consumer can never pair pixels with geometry from a different file. The
+/// header carries the file's metadata and what is recorded for the frame
+/// itself: its [`crate::readers::FrameMetadata`] and rotation angle.
+///
+/// Layout (application/octet-stream):
+///
+/// ```text
+/// u32 LE   header length N
+/// N bytes  UTF-8 JSON header (see `FrameHeader`)
+/// ...      width * height little-endian u16 pixels
+/// ```
+async fn get_frame(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let frame_count = reader.frame_count().map_err(internal)?;
+        if frame >= frame_count {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Frame {frame} out of range (file has {frame_count} frames)"),
+            ));
+        }
+        let metadata = cached_metadata(&state, reader.as_ref()).map_err(internal)?;
+        let (pixels, width, height) = reader.read_frame(frame).map_err(internal)?;
+        let angles = reader.frame_angles().map_err(internal)?;
+        let header = serde_json::to_vec(&FrameHeader {
+            frame,
+            width,
+            height,
+            dtype: "u16",
+            byte_order: "le",
+            metadata: &metadata,
+            frame_metadata: reader.frame_metadata(frame).map_err(internal)?,
+            angle: angles.and_then(|angles| angles.get(frame).copied()),
+        })
+        .map_err(|e| internal(e.into()))?;
+
+        let mut body = Vec::with_capacity(4 + header.len() + pixels.len() * 2);
+        body.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        body.extend_from_slice(&header);
+        body.extend(pixels.iter().flat_map(|&v| v.to_le_bytes()));
+        Ok(body)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(body)) => {
+            ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response()
+        }
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("frame container error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChunkHeader {
+    frame: usize,
+    #[serde(flatten)]
+    chunk: RawChunk,
+}
+
+/// Return the stored bytes of the chunk holding a frame, without decoding,
+/// for clients that implement the filters (e.g. bitshuffle/LZ4) themselves.
+/// Only available when each chunk holds exactly one full frame.
+///
+/// Layout (application/octet-stream):
+///
+/// ```text
+/// u32 LE   header length N
+/// N bytes  UTF-8 JSON header: { frame, chunk_shape, dtype, filters,
+///          filter_mask } (see `RawChunk`)
+/// ...      the chunk bytes as stored in the file
+/// ```
+async fn get_chunk(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let Some(mut chunk) = reader.read_raw_chunk(frame).map_err(internal)? else {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Raw chunks need a chunked dataset with one frame per chunk".to_string(),
+            ));
+        };
+        let data = std::mem::take(&mut chunk.data);
+        let header =
+            serde_json::to_vec(&ChunkHeader { frame, chunk }).map_err(|e| internal(e.into()))?;
+
+        let mut body = Vec::with_capacity(4 + header.len() + data.len());
+        body.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        body.extend_from_slice(&header);
+        body.extend_from_slice(&data);
+        Ok(body)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(body)) => {
+            ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response()
+        }
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("raw chunk error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Return the detector pixel mask as a packed bitfield
+/// (application/octet-stream): one bit per pixel in row-major order, least
+/// significant bit first, set where the pixel is bad. `X-Width` and
+/// `X-Height` give the dimensions; pixels are padded to a whole byte.
+///
+/// Returns 204 No Content if the file has no mask.
+async fn get_mask(State(state): State<ServerState>) -> impl IntoResponse {
+    let result = spawn_blocking(move || {
+        let guard = state.reader.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let mask = cached_mask(&state, reader.as_ref())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(mask.map(|mask| {
+            let (flags, width, height) = &*mask;
+            let mut bits = vec![0u8; flags.len().div_ceil(8)];
+            for (i, &flag) in flags.iter().enumerate() {
+                if flag != 0 {
+                    bits[i / 8] |= 1 << (i % 8);
+                }
+            }
+            (bits, *width, *height)
+        }))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some((bits, width, height)))) => (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (HeaderName::from_static("x-width"), width.to_string()),
+                (HeaderName::from_static("x-height"), height.to_string()),
+                (
+                    HeaderName::from_static("x-mask-encoding"),
+                    "bitfield-lsb".to_string(),
+                ),
+            ],
+            bits,
+        )
+            .into_response(),
+        Ok(Ok(None)) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("mask error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Return the gain-stage calibration of a gain-switching detector such as
+/// Jungfrau (application/octet-stream), for the client to convert raw ADUs:
+/// little-endian f32 gains, one row-major image per stage, followed by the
+/// pedestals in the same layout if `X-Has-Pedestal` is `true`. `X-Width`,
+/// `X-Height` and `X-Gain-Stages` give the dimensions.
+///
+/// Returns 204 No Content if the file has no gain map.
+async fn get_gainmap(State(state): State<ServerState>) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        reader
+            .gain_map()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(map))) => {
+            let body: Vec<u8> = map
+                .gain
+                .iter()
+                .chain(map.pedestal.iter().flatten())
+                .flat_map(|&v| v.to_le_bytes())
+                .collect();
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (HeaderName::from_static("x-pixel-dtype"), "f32".to_string()),
+                    (HeaderName::from_static("x-width"), map.width.to_string()),
+                    (HeaderName::from_static("x-height"), map.height.to_string()),
+                    (
+                        HeaderName::from_static("x-gain-stages"),
+                        map.stages.to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-has-pedestal"),
+                        map.pedestal.is_some().to_string(),
+                    ),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        Ok(Ok(None)) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("gain map error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Return the rotation angle of each frame in degrees as a JSON array, for
+/// labelling rotation scans. Returns 204 No Content if the file records no
+/// rotation axis.
+async fn get_angles(State(state): State<ServerState>) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        reader
+            .frame_angles()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(angles))) => Json(angles).into_response(),
+        Ok(Ok(None)) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("angles error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MaxPixelQuery {
+    #[serde(default)]
+    include_overloads: u8,
+}
+
+/// Return `{ "x", "y", "value" }` of the brightest pixel in the frame that is
+/// not masked, excluding overloads unless `?include_overloads=1`. The body is
+/// `null` if no pixel qualifies.
+async fn get_max_pixel(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+    Query(query): Query<MaxPixelQuery>,
+) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+    let include_overloads = query.include_overloads != 0;
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let trusted_max = cached_metadata(&state, reader.as_ref())
+            .map_err(internal)?
+            .trusted_range_max;
+        stats::max_pixel(reader.as_ref(), frame, trusted_max, include_overloads).map_err(internal)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(max)) => Json(max).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("max pixel error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Return what the file records about one frame as JSON (see
+/// [`crate::readers::FrameMetadata`]): its timestamp, exposure time,
+/// distance if the detector moves, beam energy and wavelength for energy
+/// scans, and per-frame signals such as ring current. Fields the file
+/// doesn't record are left out.
+async fn get_frame_meta(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let frame_count = reader.frame_count().map_err(internal)?;
+        if frame >= frame_count {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Frame {frame} out of range (file has {frame_count} frames)"),
+            ));
+        }
+        reader.frame_metadata(frame).map_err(internal)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(meta)) => Json(meta).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("frame metadata error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Upper bound on `?bins` for `/histogram`.
+const MAX_HISTOGRAM_BINS: usize = 65536;
+
+#[derive(Debug, Deserialize)]
+struct HistogramQuery {
+    #[serde(default = "default_histogram_bins")]
+    bins: usize,
+}
+
+fn default_histogram_bins() -> usize {
+    256
+}
+
+/// Return a histogram of the frame's pixel values as JSON (see
+/// [`stats::Histogram`]), so clients can autoscale without downloading the
+/// frame. Masked pixels are left out, and pixels above `trusted_range_max`
+/// are counted in `overflow` instead of the bins.
+async fn get_histogram(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+    Query(query): Query<HistogramQuery>,
+) -> impl IntoResponse {
+    if query.bins == 0 || query.bins > MAX_HISTOGRAM_BINS {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("bins must be between 1 and {MAX_HISTOGRAM_BINS}"),
+        )
+            .into_response();
+    }
+
+    let result = spawn_blocking(move || {
+        let guard = state.reader.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let generation = state.generation.load(Ordering::SeqCst);
+        let cached =
+            read_frame_cached(&state, reader.as_ref(), generation, frame).map_err(internal)?;
+        let trusted_max = cached_metadata(&state, reader.as_ref())
+            .map_err(internal)?
+            .trusted_range_max;
+        let mask = cached_mask(&state, reader.as_ref())
+            .map_err(internal)?
+            .filter(|mask| (mask.1, mask.2) == (cached.width, cached.height));
+        Ok(stats::Histogram::compute(
+            &cached.pixels,
+            mask.as_ref().map(|mask| mask.0.as_slice()),
+            trusted_max,
+            query.bins,
+        ))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(histogram)) => Json(histogram).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("histogram error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Number of levels in a `/mipmap` response: full resolution, 1/2, 1/4, 1/8.
+const MIPMAP_LEVELS: u32 = 4;
+
+/// One level's entry in the `/mipmap` header table.
+#[derive(Serialize)]
+struct MipLevel {
+    level: u32,
+    width: usize,
+    height: usize,
+    /// Byte offset of this level from the start of the pixel data.
+    offset: usize,
+    /// Length of this level in bytes.
+    length: usize,
+}
+
+#[derive(Serialize)]
+struct MipmapHeader {
+    frame: usize,
+    dtype: &'static str,
+    byte_order: &'static str,
+    levels: Vec<MipLevel>,
+}
+
+/// Return a frame as a pyramid of [`MIPMAP_LEVELS`] resolution levels in one
+/// response, for uploading as texture mip levels.
+///
+/// Level `k` is the frame mean-pooled by `2^k`, so level 0 is the raw frame.
+/// Each level is computed from the full-resolution frame, and only trusted
+/// pixels contribute to a mean. Layout (application/octet-stream):
+///
+/// ```text
+/// u32 LE   header length N
+/// N bytes  UTF-8 JSON header: { frame, dtype, byte_order, levels: [...] }
+/// ...      levels in order 0, 1, 2, 3 as little-endian u16, at the
+///          offsets given in the header (relative to the end of the header)
+/// ```
+async fn get_mipmap(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let build = || -> anyhow::Result<Vec<u8>> {
+            let trusted_max = cached_metadata(&state, reader.as_ref())?.trusted_range_max;
+            let (pixels, width, height) = reader.read_frame(frame)?;
+
+            let mut levels = Vec::new();
+            let mut data = Vec::new();
+            for level in 0..MIPMAP_LEVELS {
+                let binned;
+                let (level_pixels, level_w, level_h) = if level == 0 {
+                    (&pixels, width, height)
+                } else {
+                    binned = render::bin_mean(&pixels, width, height, 1 << level, trusted_max);
+                    (&binned.pixels, binned.width, binned.height)
+                };
+                levels.push(MipLevel {
+                    level,
+                    width: level_w,
+                    height: level_h,
+                    offset: data.len(),
+                    length: level_pixels.len() * 2,
+                });
+                data.extend(level_pixels.iter().flat_map(|&v| v.to_le_bytes()));
+            }
+
+            let header = serde_json::to_vec(&MipmapHeader {
+                frame,
+                dtype: "u16",
+                byte_order: "le",
+                levels,
+            })?;
+            let mut body = Vec::with_capacity(4 + header.len() + data.len());
+            body.extend_from_slice(&(header.len() as u32).to_le_bytes());
+            body.extend_from_slice(&header);
+            body.extend_from_slice(&data);
+            Ok(body)
+        };
+        build().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(body)) => {
+            ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response()
+        }
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("mipmap error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolutionMapQuery {
+    #[serde(default = "default_resolution_downsample")]
+    downsample: usize,
+    /// Frame whose own geometry to use, for scans that move the detector.
+    frame: Option<usize>,
+}
+
+fn default_resolution_downsample() -> usize {
+    4
+}
+
+#[derive(Serialize)]
+struct ResolutionMapHeader {
+    width: usize,
+    height: usize,
+    downsample: usize,
+    /// What each value is; always `"d_spacing_angstrom"`.
+    quantity: &'static str,
+    dtype: &'static str,
+    byte_order: &'static str,
+}
+
+/// Return the resolution (d-spacing in Å) across the detector, sampled at the
+/// centre of each `downsample`×`downsample` block, for shading or masking the
+/// image by resolution. Computed from geometry alone; no frame is read.
+///
+/// The geometry is the file's, as in `/metadata`, unless `?frame=` is given:
+/// then that frame's distance and beam energy (see
+/// [`crate::readers::FrameMetadata`]) are used where it has them. A frame
+/// out of range is a 400.
+///
+/// Layout (application/octet-stream):
+///
+/// ```text
+/// u32 LE   header length N
+/// N bytes  UTF-8 JSON header (see `ResolutionMapHeader`)
+/// ...      width * height little-endian f32 values, +inf at the beam centre
+/// ```
+///
+/// Fails with 422 if the file has no beam energy / wavelength or detector
+/// distance.
+async fn get_resolution_map(
+    State(state): State<ServerState>,
+    Query(query): Query<ResolutionMapQuery>,
+) -> impl IntoResponse {
+    if query.downsample == 0 {
+        return (StatusCode::BAD_REQUEST, "downsample must be at least 1").into_response();
+    }
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let mut metadata = cached_metadata(&state, reader.as_ref()).map_err(internal)?;
+        if let Some(frame) = query.frame {
+            let frame_count = reader.frame_count().map_err(internal)?;
+            if frame >= frame_count {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Frame {frame} out of range (file has {frame_count} frames)"),
+                ));
+            }
+            let frame_metadata = reader.frame_metadata(frame).map_err(internal)?;
+            metadata = Arc::new(frame_geometry(&metadata, &frame_metadata));
+        }
+        let Some(map) = geometry::resolution_map(&metadata, query.downsample) else {
+            let unknown = match metadata.beam_energy_kev {
+                None => "Wavelength",
+                Some(_) => "Detector distance",
+            };
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("{unknown} is unknown for this file"),
+            ));
+        };
+        let header = serde_json::to_vec(&ResolutionMapHeader {
+            width: map.width,
+            height: map.height,
+            downsample: query.downsample,
+            quantity: "d_spacing_angstrom",
+            dtype: "f32",
+            byte_order: "le",
+        })
+        .map_err(|e| internal(e.into()))?;
+
+        let mut body = Vec::with_capacity(4 + header.len() + map.values.len() * 4);
+        body.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        body.extend_from_slice(&header);
+        body.extend(map.values.iter().flat_map(|&v| v.to_le_bytes()));
+        Ok(body)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(body)) => {
+            ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response()
+        }
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("resolution map error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// `metadata` with the geometry recorded for one frame in place of the
+/// file's, where `frame_metadata` has it.
+fn frame_geometry(
+    metadata: &ImageMetadata,
+    frame_metadata: &crate::readers::FrameMetadata,
+) -> ImageMetadata {
+    let mut metadata = metadata.clone();
+    if let Some(distance_mm) = frame_metadata.distance_mm {
+        metadata.panel_distance_mm = distance_mm;
+    }
+    if let Some(energy_kev) = frame_metadata.incident_energy_kev {
+        metadata.beam_energy_kev = Some(energy_kev);
+    }
+    metadata
+}
+
+/// Upper bound on frames in a single montage, to keep the output image and
+/// the decode work for one request reasonable.
+const MAX_MONTAGE_FRAMES: usize = 400;
+
+/// Upper bound on the montage cell size in pixels.
+const MAX_MONTAGE_CELL: usize = 512;
+
+#[derive(Debug, Deserialize)]
+struct MontageQuery {
+    #[serde(default)]
+    start: usize,
+    /// Exclusive end frame; defaults to as many frames as are allowed.
+    end: Option<usize>,
+    /// Grid columns; defaults to a roughly square grid.
+    cols: Option<usize>,
+    #[serde(default = "default_montage_cell")]
+    cell: usize,
+}
+
+fn default_montage_cell() -> usize {
+    128
+}
+
+/// Return a contact sheet of frames `[start, end)` as a single PNG.
+///
+/// Each frame is max-pooled to fit a `cell`×`cell` tile and tiles are laid
+/// out row-major in `cols` columns. All tiles share one log-scaled grey level
+/// so frames can be compared by eye. Frames are decoded in parallel on the
+/// analysis pool (see [`ServerConfig::analysis_niceness`]).
+/// Fails with 409 if `cancel_analysis` is called while it runs.
+async fn get_montage(
+    State(state): State<ServerState>,
+    Query(query): Query<MontageQuery>,
+) -> impl IntoResponse {
+    if query.cell == 0 || query.cell > MAX_MONTAGE_CELL {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("cell must be between 1 and {MAX_MONTAGE_CELL}"),
+        )
+            .into_response();
+    }
+    let reader_arc = state.reader.clone();
+    let cancel = CancelToken::new(&state.analysis_epoch);
+
+    let result = spawn_blocking(move || {
+        use rayon::prelude::*;
+
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let frame_count = reader.frame_count().map_err(internal)?;
+        let start = query.start;
+        let end = query
+            .end
+            .unwrap_or_else(|| frame_count.min(start + MAX_MONTAGE_FRAMES));
+        if start >= end || end > frame_count {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid frame range {start}..{end} (file has {frame_count} frames)"),
+            ));
+        }
+        let n = end - start;
+        if n > MAX_MONTAGE_FRAMES {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Montage of {n} frames exceeds the limit of {MAX_MONTAGE_FRAMES}"),
+            ));
+        }
+        let cols = query
+            .cols
+            .unwrap_or_else(|| (n as f64).sqrt().ceil() as usize)
+            .clamp(1, n);
+
+        let trusted_max = cached_metadata(&state, reader.as_ref())
+            .map_err(internal)?
+            .trusted_range_max;
+        let tiles = state.analysis_pool.install(|| {
+            (start..end)
+                .into_par_iter()
+                .map(|frame| {
+                    if cancel.is_cancelled() {
+                        anyhow::bail!("Cancelled");
+                    }
+                    let (pixels, width, height) = reader.read_frame(frame)?;
+                    let factor = render::bin_factor(width, height, query.cell);
+                    Ok(render::bin_max(&pixels, width, height, factor, trusted_max))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        });
+        if cancel.is_cancelled() {
+            return Err((StatusCode::CONFLICT, "Montage cancelled".to_string()));
+        }
+        let tiles = tiles.map_err(internal)?;
+
+        render::montage_png(&tiles, cols, query.cell, trusted_max).map_err(internal)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(png)) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("montage error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectQuery {
+    #[serde(default)]
+    start: usize,
+    /// Exclusive end frame; defaults to the end of the file.
+    end: Option<usize>,
+    op: ProjectionOp,
+}
+
+/// Combine frames `[start, end)` pixel by pixel into one frame, for a
+/// "powder" view of weak rings: `op` is `sum`, `mean` or `max`.
+///
+/// Only trusted, unmasked pixels contribute; see [`stats::project`]. The
+/// body is little-endian pixels, `X-Pixel-Dtype` `u32` for `sum` (so it
+/// can't overflow) and `u16` otherwise, with the size in `X-Width` and
+/// `X-Height`. Pixels with no usable value in any frame are
+/// `X-Masked-Value`. Ranges longer than [`ServerConfig::max_scan_frames`]
+/// are sampled, and the number of frames combined is in `X-Frames-Used`.
+/// Frames are read in parallel on the analysis pool (see
+/// [`ServerConfig::analysis_niceness`]) and not cached. Fails with
+/// 400 for an invalid range, and 409 if `cancel_analysis` is called while it
+/// runs.
+async fn get_project(
+    State(state): State<ServerState>,
+    Query(query): Query<ProjectQuery>,
+) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+    let cancel = CancelToken::new(&state.analysis_epoch);
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let frame_count = reader.frame_count().map_err(internal)?;
+        let start = query.start;
+        let end = query.end.unwrap_or(frame_count);
+        if start >= end || end > frame_count {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid frame range {start}..{end} (file has {frame_count} frames)"),
+            ));
+        }
+        let trusted_max = cached_metadata(&state, reader.as_ref())
+            .map_err(internal)?
+            .trusted_range_max;
+        let frames = state.config.scan_frames(start..end);
+        let t0 = std::time::Instant::now();
+        let projection = state
+            .analysis_pool
+            .install(|| stats::project(reader.as_ref(), &frames, query.op, trusted_max, &cancel));
+        if cancel.is_cancelled() {
+            return Err((StatusCode::CONFLICT, "Projection cancelled".to_string()));
+        }
+        let projection = projection.map_err(internal)?;
+        tracing::debug!(
+            frames = frames.len(),
+            elapsed_ms = t0.elapsed().as_millis(),
+            "projected frames"
+        );
+        Ok((projection, frames.len()))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((projection, frames_used))) => {
+            let (dtype, masked, body) = match projection.pixels {
+                ProjectedPixels::U16(pixels) => (
+                    "u16",
+                    u32::from(u16::MAX),
+                    pixels
+                        .iter()
+                        .flat_map(|&v| v.to_le_bytes())
+                        .collect::<Vec<_>>(),
+                ),
+                ProjectedPixels::U32(pixels) => (
+                    "u32",
+                    u32::MAX,
+                    pixels.iter().flat_map(|&v| v.to_le_bytes()).collect(),
+                ),
+            };
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (HeaderName::from_static("x-pixel-dtype"), dtype.to_string()),
+                    (
+                        HeaderName::from_static("x-width"),
+                        projection.width.to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-height"),
+                        projection.height.to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-masked-value"),
+                        masked.to_string(),
+                    ),
+                    (
+                        HeaderName::from_static("x-frames-used"),
+                        frames_used.to_string(),
+                    ),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("projection error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Bytes of frame index and pixel byte length before the pixels of each
+/// `/frames` record.
+const FRAMES_RECORD_HEADER_BYTES: usize = 8;
+
+/// `/frames` records read ahead of the client; bounds the memory a slow
+/// client can pin.
+const FRAMES_READ_AHEAD: usize = 2;
+
+#[derive(Debug, Deserialize)]
+struct FramesQuery {
+    #[serde(default)]
+    start: usize,
+    /// Exclusive end frame; defaults to the end of the file.
+    end: Option<usize>,
+}
+
+/// Stream frames `[start, end)` in one response, for exporting a clip
+/// without a request per frame.
+///
+/// The body is one record per frame, in order: the frame index and the
+/// pixel data's length in bytes, each a little-endian u32, then the pixels
+/// as little-endian u16. Every frame has the size given by `X-Width` and
+/// `X-Height`. Frames are read one at a time as the client consumes them,
+/// through the frame cache. Fails with 400 for an invalid range; an error
+/// after the first frame, or the file changing, aborts the body.
+async fn get_frames(
+    State(state): State<ServerState>,
+    Query(query): Query<FramesQuery>,
+) -> impl IntoResponse {
+    let first = {
+        let state = state.clone();
+        spawn_blocking(move || {
+            let guard = state.reader.blocking_lock();
+            let Some(reader) = guard.as_ref() else {
+                return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+            };
+            let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+            let frame_count = reader.frame_count().map_err(internal)?;
+            let start = query.start;
+            let end = query.end.unwrap_or(frame_count);
+            if start >= end || end > frame_count {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid frame range {start}..{end} (file has {frame_count} frames)"),
+                ));
+            }
+            // Read the first frame up front, for its size and so a file that
+            // can't be read fails with a status rather than a cut-off body.
+            let generation = state.generation.load(Ordering::SeqCst);
+            let cached =
+                read_frame_cached(&state, reader.as_ref(), generation, start).map_err(internal)?;
+            Ok((start..end, generation, cached))
+        })
+        .await
+    };
+
+    let (frames, stream_generation, first) = match first {
+        Ok(Ok(first)) => first,
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("frames error: {e}");
+            }
+            return (status, e).into_response();
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let (width, height) = (first.width, first.height);
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Vec<u8>>>(FRAMES_READ_AHEAD);
+    let task = async move {
+        if tx
+            .send(Ok(frames_record(frames.start, &first.pixels)))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        drop(first);
+        for frame in frames.start + 1..frames.end {
+            let record = read_frames_record(&state, stream_generation, frame)
+                .await
+                .map_err(std::io::Error::other);
+            let failed = record.is_err();
+            if tx.send(record).await.is_err() || failed {
+                // Client went away, or the body is being aborted.
+                return;
+            }
+        }
+    };
+    tokio::spawn(task.in_current_span());
+
+    let body = axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx));
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (HeaderName::from_static("x-width"), width.to_string()),
+            (HeaderName::from_static("x-height"), height.to_string()),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Read `frame` into a `/frames` record. Fails if the reader is no longer
+/// the one the response began on.
+async fn read_frames_record(
+    state: &ServerState,
+    stream_generation: u64,
+    frame: usize,
+) -> Result<Vec<u8>, String> {
+    let state = state.clone();
+    let result = spawn_blocking(move || {
+        let guard = state.reader.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err("File closed".to_string());
+        };
+        if state.generation.load(Ordering::SeqCst) != stream_generation {
+            return Err("File changed".to_string());
+        }
+        let cached =
+            read_frame_cached(&state, reader.as_ref(), stream_generation, frame).map_err(|e| {
+                tracing::error!("frames: frame {frame} read error: {e}");
+                format!("Frame {frame} read error")
+            })?;
+        Ok(frames_record(frame, &cached.pixels))
+    })
+    .await;
+    result.unwrap_or_else(|e| {
+        tracing::error!("spawn_blocking panicked: {e}");
+        Err("Internal error".to_string())
+    })
+}
+
+/// Serialize one `/frames` record; see [`get_frames`].
+fn frames_record(frame: usize, pixels: &[u16]) -> Vec<u8> {
+    let len = pixels.len() * 2;
+    let mut record = Vec::with_capacity(FRAMES_RECORD_HEADER_BYTES + len);
+    record.extend_from_slice(&(frame as u32).to_le_bytes());
+    record.extend_from_slice(&(len as u32).to_le_bytes());
+    for &v in pixels {
+        record.extend_from_slice(&v.to_le_bytes());
+    }
+    record
+}
+
+/// Upper bound on the thumbnail size in pixels.
+const MAX_THUMBNAIL_SIZE: usize = 1024;
+
+#[derive(Debug, Deserialize)]
+struct ThumbnailQuery {
+    #[serde(default = "default_thumbnail_size")]
+    size: usize,
+}
+
+fn default_thumbnail_size() -> usize {
+    256
+}
+
+/// Return a small PNG preview of one frame, for filmstrips.
+///
+/// The frame is max-pooled by the smallest factor that fits it within a
+/// `size`×`size` box and drawn log-scaled up to its brightest trusted pixel,
+/// with untrusted and masked pixels in [`render::MASKED_RGB`]. Frames are
+/// read directly rather than through the frame cache, so scanning a whole
+/// file doesn't evict the frames being viewed.
+async fn get_thumbnail(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+    Query(query): Query<ThumbnailQuery>,
+) -> impl IntoResponse {
+    if query.size == 0 || query.size > MAX_THUMBNAIL_SIZE {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("size must be between 1 and {MAX_THUMBNAIL_SIZE}"),
+        )
+            .into_response();
+    }
+    let reader_arc = state.reader.clone();
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        // Capped so the u16::MAX that masked pixels are set to is untrusted.
+        let trusted_max = cached_metadata(&state, reader.as_ref())
+            .map_err(internal)?
+            .trusted_range_max
+            .min(f64::from(u16::MAX - 1));
+        let (mut pixels, width, height) = reader.read_frame(frame).map_err(internal)?;
+        if let Some(mask) = cached_mask(&state, reader.as_ref())
+            .map_err(internal)?
+            .filter(|mask| (mask.1, mask.2) == (width, height))
+        {
+            for (v, _) in pixels.iter_mut().zip(&mask.0).filter(|(_, &m)| m != 0) {
+                *v = u16::MAX;
+            }
+        }
+        let factor = render::bin_factor(width, height, query.size);
+        let binned = render::bin_max(&pixels, width, height, factor, trusted_max);
+        render::thumbnail_png(&binned, trusted_max).map_err(internal)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(png)) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("thumbnail error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ScanDirection {
+    #[default]
+    Fwd,
+    Back,
+}
+
+#[derive(Debug, Deserialize)]
+struct NextActiveQuery {
+    #[serde(default)]
+    direction: ScanDirection,
+    /// Minimum mean trusted pixel value for a frame to count as active.
+    #[serde(default)]
+    threshold: f64,
+}
+
+#[derive(Serialize)]
+struct NextActive {
+    /// The active frame found, or `null` if there is none in that direction.
+    frame: Option<usize>,
+    /// Statistics of `frame`, when one was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<FrameStats>,
+    /// How many frames were read before the scan stopped.
+    frames_used: usize,
+}
+
+/// Find the nearest frame after (`direction=fwd`, the default) or before
+/// (`direction=back`) `frame` whose mean trusted pixel value exceeds
+/// `threshold` (default 0), for skipping runs of blank frames. Returns
+/// `{"frame": null}` if every frame in that direction is blank.
+///
+/// Frames are scanned one by one under the reader lock and the scan stops at
+/// the first match. Beyond [`ServerConfig::max_scan_frames`] frames in that
+/// direction, only an evenly spaced sample of them is scanned; `frames_used`
+/// counts the frames read. Fails with 409 if `cancel_analysis` is called
+/// while it runs.
+async fn get_next_active(
+    State(state): State<ServerState>,
+    Path(frame): Path<usize>,
+    Query(query): Query<NextActiveQuery>,
+) -> impl IntoResponse {
+    let reader_arc = state.reader.clone();
+    let cancel = CancelToken::new(&state.analysis_epoch);
+
+    let result = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err((StatusCode::NOT_FOUND, "No file open".to_string()));
+        };
+        let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let frame_count = reader.frame_count().map_err(internal)?;
+        if frame >= frame_count {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Frame {frame} out of range (file has {frame_count} frames)"),
+            ));
+        }
+        let trusted_max = cached_metadata(&state, reader.as_ref())
+            .map_err(internal)?
+            .trusted_range_max;
+        let mut frames = match query.direction {
+            ScanDirection::Fwd => state.config.scan_frames(frame + 1..frame_count),
+            ScanDirection::Back => state.config.scan_frames(0..frame),
+        };
+        if matches!(query.direction, ScanDirection::Back) {
+            frames.reverse();
+        }
+        let found = stats::find_active_frame(
+            reader.as_ref(),
+            frames.iter().copied(),
+            trusted_max,
+            query.threshold,
+            &cancel,
+        );
+        if cancel.is_cancelled() {
+            return Err((StatusCode::CONFLICT, "Scan cancelled".to_string()));
+        }
+        let stats = found.map_err(internal)?;
+        let frames_used = match &stats {
+            Some(stats) => frames.iter().position(|&f| f == stats.frame).unwrap_or(0) + 1,
+            None => frames.len(),
+        };
+        Ok(NextActive {
+            frame: stats.as_ref().map(|s| s.frame),
+            stats,
+            frames_used,
+        })
+    })
+    .await;
+
+    match result {
+        Ok(Ok(next)) => Json(next).into_response(),
+        Ok(Err((status, e))) => {
+            if status.is_server_error() {
+                tracing::error!("next_active error: {e}");
+            }
+            (status, e).into_response()
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Frames decoded together (in parallel, under one reader lock) per batch of
+/// `/stats/stream`. Bounds both memory and how long interactive requests can
+/// be kept waiting for the reader.
+const STATS_STREAM_BATCH: usize = 8;
+
+/// Stream per-frame statistics for every frame as newline-delimited JSON
+/// (`application/x-ndjson`), one [`FrameStats`] object per line in frame
+/// order, as they are computed.
+///
+/// Frames are processed in batches of [`STATS_STREAM_BATCH`], decoded in
+/// parallel on the analysis pool; the reader lock is released between
+/// batches. A file with more than [`ServerConfig::max_scan_frames`] frames
+/// when the stream starts is sampled, and a file growing beyond it is
+/// followed only that far. A final `{"frames_used": n}` line ends a
+/// complete stream. If a frame fails to read, a final
+/// `{"frame": n, "error": "..."}` line is sent and the stream ends; likewise
+/// an `{"error": "..."}` line if the file is closed or replaced mid-stream, or
+/// if `cancel_analysis` is called.
+/// The work stops early if the client disconnects.
+async fn get_stats_stream(State(state): State<ServerState>) -> impl IntoResponse {
+    use tokio_stream::StreamExt;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(STATS_STREAM_BATCH);
+    let reader_arc = state.reader.clone();
+    let generation = state.generation.clone();
+    let cancel = CancelToken::new(&state.analysis_epoch);
+
+    let task = async move {
+        let mut start = 0usize;
+        let mut stream_generation = None;
+        // Frames streamed, if sampled rather than taken in order.
+        let mut sample: Option<Arc<Vec<usize>>> = None;
+        loop {
+            if cancel.is_cancelled() {
+                let _ = tx
+                    .send(ndjson_line(&serde_json::json!({ "error": "Cancelled" })))
+                    .await;
+                return;
+            }
+            let reader_arc = reader_arc.clone();
+            let generation = generation.clone();
+            let state = state.clone();
+            let mut batch_sample = sample.clone();
+            let batch = spawn_blocking(move || -> Result<_, String> {
+                use rayon::prelude::*;
+
+                let guard = reader_arc.blocking_lock();
+                let Some(reader) = guard.as_ref() else {
+                    return Err("No file open".to_string());
+                };
+                let current = generation.load(Ordering::SeqCst);
+                if stream_generation.is_some_and(|g| g != current) {
+                    return Err("File changed during stream".to_string());
+                }
+                let frame_count = reader.frame_count().map_err(|e| e.to_string())?;
+                let trusted_max = cached_metadata(&state, reader.as_ref())
+                    .map_err(|e| e.to_string())?
+                    .trusted_range_max;
+                let limit = state.config.scan_limit();
+                if stream_generation.is_none() && frame_count > limit {
+                    batch_sample = Some(Arc::new(state.config.scan_frames(0..frame_count)));
+                }
+                let frames: Vec<usize> = match &batch_sample {
+                    Some(sample) => sample
+                        .iter()
+                        .skip(start)
+                        .take(STATS_STREAM_BATCH)
+                        .copied()
+                        .collect(),
+                    None => (start..frame_count.min(limit))
+                        .take(STATS_STREAM_BATCH)
+                        .collect(),
+                };
+                let stats = state.analysis_pool.install(|| {
+                    frames
+                        .into_par_iter()
+                        .map_init(Vec::new, |buf, frame| {
+                            reader
+                                .read_frame_into(frame, buf)
+                                .map(|_| FrameStats::compute(frame, buf, trusted_max))
+                                .map_err(|e| (frame, e.to_string()))
+                        })
+                        .collect::<Vec<_>>()
+                });
+                Ok((current, stats, batch_sample))
+            })
+            .await;
+
+            let results = match batch {
+                Ok(Ok((current, results, batch_sample))) => {
+                    stream_generation = Some(current);
+                    sample = batch_sample;
+                    results
+                }
+                Ok(Err(e)) => {
+                    let _ = tx
+                        .send(ndjson_line(&serde_json::json!({ "error": e })))
+                        .await;
+                    return;
+                }
+                Err(e) => {
+                    tracing::error!("spawn_blocking panicked: {e}");
+                    return;
+                }
+            };
+            if results.is_empty() {
+                let _ = tx
+                    .send(ndjson_line(&serde_json::json!({ "frames_used": start })))
+                    .await;
+                return;
+            }
+            start += results.len();
+
+            for result in results {
+                let line = match result {
+                    Ok(stats) => ndjson_line(&stats),
+                    Err((frame, e)) => {
+                        tracing::error!("stats stream: frame {frame} read error: {e}");
+                        let _ = tx
+                            .send(ndjson_line(
+                                &serde_json::json!({ "frame": frame, "error": e }),
+                            ))
+                            .await;
+                        return;
+                    }
+                };
+                if tx.send(line).await.is_err() {
+                    // Client went away.
+                    return;
+                }
+            }
+        }
+    };
+    tokio::spawn(task.in_current_span());
+
+    let body = axum::body::Body::from_stream(
+        tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok::<_, std::convert::Infallible>),
+    );
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body)
+}
+
+fn ndjson_line<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut line = serde_json::to_vec(value).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
+
+/// Upper bound on the `/stream` playback rate.
+const MAX_STREAM_FPS: f64 = 120.0;
+
+/// Bytes of frame index, width and height before the pixels of each
+/// `/stream` frame message.
+const STREAM_HEADER_BYTES: usize = 12;
+
+/// A `/stream` control message.
+#[derive(Debug, Deserialize)]
+struct StreamRequest {
+    #[serde(default)]
+    start: usize,
+    /// Exclusive end frame; defaults to the end of the file.
+    end: Option<usize>,
+    fps: f64,
+}
+
+/// Playback in progress on a `/stream` socket.
+struct Playback {
+    start: usize,
+    end: usize,
+    fps: f64,
+    began: tokio::time::Instant,
+    interval: tokio::time::Interval,
+    /// The last frame sent, so a frame is never sent twice.
+    last_sent: Option<usize>,
+}
+
+/// Play frames over a WebSocket, for movie-style playback without a request
+/// per frame.
+///
+/// The client sends a JSON text message `{"start": 0, "end": 100, "fps": 10}`
+/// (`end` is exclusive and defaults to the frame count), and the server then
+/// sends one binary message per frame: the frame index, width and height as
+/// little-endian u32s, then the pixels as little-endian u16s. Frames are
+/// timed from the start of playback, so when reading or sending falls
+/// behind, the frames that are late are skipped rather than queued. A new
+/// control message restarts playback with its parameters; invalid ones are
+/// answered with an `{"error": "..."}` text message.
+///
+/// The socket is closed with code 1001 if the file is closed or replaced, or
+/// a frame can't be read.
+async fn get_stream(State(state): State<ServerState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| play_stream(state, socket).in_current_span())
+}
+
+async fn play_stream(state: ServerState, mut socket: WebSocket) {
+    let stream_generation = state.generation.load(Ordering::SeqCst);
+    let mut playback: Option<Playback> = None;
+    let mut scratch = Vec::new();
+    loop {
+        let tick = async {
+            match playback.as_mut() {
+                Some(playback) => playback.interval.tick().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => return,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        tracing::debug!("stream: socket error: {e}");
+                        return;
+                    }
+                };
+                match start_playback(&state, &text).await {
+                    Ok(started) => playback = Some(started),
+                    Err(e) => {
+                        let error = serde_json::json!({ "error": e }).to_string();
+                        if socket.send(Message::Text(error.into())).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            _ = tick => {
+                let Some(current) = playback.as_mut() else {
+                    continue;
+                };
+                let elapsed = current.began.elapsed().as_secs_f64();
+                let frame = current.start + (elapsed * current.fps) as usize;
+                if current.last_sent.is_some_and(|last| frame <= last) {
+                    continue;
+                }
+                if frame >= current.end {
+                    playback = None;
+                    continue;
+                }
+                current.last_sent = Some(frame);
+                state.activity.touch();
+                match read_stream_frame(&state, stream_generation, frame, &mut scratch).await {
+                    Ok(message) => {
+                        if socket.send(Message::Binary(message.into())).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(reason) => {
+                        let close = CloseFrame {
+                            code: close_code::AWAY,
+                            reason: reason.into(),
+                        };
+                        let _ = socket.send(Message::Close(Some(close))).await;
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Validate a control message against the open file and start playing it.
+async fn start_playback(state: &ServerState, text: &str) -> Result<Playback, String> {
+    let request: StreamRequest =
+        serde_json::from_str(text).map_err(|e| format!("Invalid control message: {e}"))?;
+    if !(request.fps > 0.0 && request.fps <= MAX_STREAM_FPS) {
+        return Err(format!("fps must be above 0 and at most {MAX_STREAM_FPS}"));
+    }
+    let reader_arc = state.reader.clone();
+    let frame_count = spawn_blocking(move || {
+        let guard = reader_arc.blocking_lock();
+        let Some(reader) = guard.as_ref() else {
+            return Err("No file open".to_string());
+        };
+        reader.frame_count().map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("task error: {e}"))??;
+    let end = request.end.unwrap_or(frame_count);
+    if request.start >= end || end > frame_count {
+        return Err(format!(
+            "Invalid frame range {}..{end} (file has {frame_count} frames)",
+            request.start
+        ));
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / request.fps));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    Ok(Playback {
+        start: request.start,
+        end,
+        fps: request.fps,
+        began: tokio::time::Instant::now(),
+        interval,
+        last_sent: None,
+    })
+}
+
+/// Read `frame` into a `/stream` frame message. Fails with the reason to
+/// close the socket if the reader is no longer the one the stream began on.
+///
+/// With the frame cache disabled, frames are decoded into `scratch`, kept by
+/// the connection, instead of a new buffer each time.
+async fn read_stream_frame(
+    state: &ServerState,
+    stream_generation: u64,
+    frame: usize,
+    scratch: &mut Vec<u16>,
+) -> Result<Vec<u8>, String> {
+    let state = state.clone();
+    let mut buf = std::mem::take(scratch);
+    let result = spawn_blocking(move || {
+        let message = stream_frame_message(&state, stream_generation, frame, &mut buf);
+        (message, buf)
+    })
+    .await;
+    match result {
+        Ok((message, buf)) => {
+            *scratch = buf;
+            message
+        }
+        Err(e) => {
+            tracing::error!("spawn_blocking panicked: {e}");
+            Err("Internal error".to_string())
+        }
+    }
+}
+
+/// The blocking half of [`read_stream_frame`].
+fn stream_frame_message(
+    state: &ServerState,
+    stream_generation: u64,
+    frame: usize,
+    scratch: &mut Vec<u16>,
+) -> Result<Vec<u8>, String> {
+    let guard = state.reader.blocking_lock();
+    let Some(reader) = guard.as_ref() else {
+        return Err("File closed".to_string());
+    };
+    if state.generation.load(Ordering::SeqCst) != stream_generation {
+        return Err("File changed".to_string());
+    }
+    let read_error = |e: anyhow::Error| {
+        tracing::error!("stream: frame {frame} read error: {e}");
+        format!("Frame {frame} read error")
+    };
+    let cached;
+    let (pixels, width, height): (&[u16], _, _) = if state.frame_cache.is_enabled() {
+        cached = read_frame_cached(state, reader.as_ref(), stream_generation, frame)
+            .map_err(read_error)?;
+        (&cached.pixels, cached.width, cached.height)
+    } else {
+        let (width, height) = reader.read_frame_into(frame, scratch).map_err(read_error)?;
+        (scratch, width, height)
+    };
+    let mut message = Vec::with_capacity(STREAM_HEADER_BYTES + pixels.len() * 2);
+    for value in [frame, width, height] {
+        message.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+    for &v in pixels {
+        message.extend_from_slice(&v.to_le_bytes());
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    /// A 64x64 file of four blank frames.
+    #[derive(Default)]
+    struct FakeReader {
+        mask: Option<Vec<u8>>,
+        /// Frame `i` is stamped at `i / 2` seconds.
+        timestamps: bool,
+        /// A known beam energy, and a detector moving back 100 mm a frame.
+        geometry: bool,
+        /// What `applied_transforms` reports.
+        transforms: Vec<&'static str>,
+        /// Incremented by every `metadata` call.
+        metadata_calls: Arc<std::sync::atomic::AtomicUsize>,
+        /// Incremented by every `mask` call.
+        mask_calls: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl crate::readers::Reader for FakeReader {
+        fn format_name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn path(&self) -> &std::path::Path {
+            std::path::Path::new("fake.h5")
+        }
+
+        fn metadata(&self) -> anyhow::Result<ImageMetadata> {
+            self.metadata_calls.fetch_add(1, Ordering::SeqCst);
+            let mut metadata = ImageMetadata {
+                panel_size_fast_slow: [64, 64],
+                trusted_range_max: 65534.0,
+                ..Default::default()
+            };
+            if self.geometry {
+                metadata.panel_distance_mm = 100.0;
+                metadata.pixel_size = 0.075;
+                metadata.beam_energy_kev = Some(12.4);
+            }
+            Ok(metadata)
+        }
+
+        fn frame_count(&self) -> anyhow::Result<usize> {
+            Ok(4)
+        }
+
+        fn read_frame(&self, _frame: usize) -> anyhow::Result<(Vec<u16>, usize, usize)> {
+            Ok((vec![0; 64 * 64], 64, 64))
+        }
+
+        fn mask(&self) -> anyhow::Result<Option<(Vec<u8>, usize, usize)>> {
+            self.mask_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.mask.clone().map(|mask| (mask, 64, 64)))
+        }
+
+        fn frame_metadata(&self, frame: usize) -> anyhow::Result<crate::readers::FrameMetadata> {
+            Ok(crate::readers::FrameMetadata {
+                timestamp_s: self.timestamps.then_some(frame as f64 / 2.0),
+                distance_mm: self.geometry.then_some(100.0 * (frame + 1) as f64),
+                ..Default::default()
+            })
+        }
+
+        fn applied_transforms(&self) -> Vec<&'static str> {
+            self.transforms.clone()
+        }
+    }
+
+    async fn get(state: ServerState, uri: &str) -> Response {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        create_router(state).oneshot(request).await.unwrap()
+    }
+
+    fn state_with(reader: impl crate::readers::Reader + 'static) -> ServerState {
+        state_with_config(reader, ServerConfig::default())
+    }
+
+    fn state_with_config(
+        reader: impl crate::readers::Reader + 'static,
+        config: ServerConfig,
+    ) -> ServerState {
+        let reader: Box<dyn crate::readers::Reader> = Box::new(reader);
+        ServerState::new(
+            Arc::new(tokio::sync::Mutex::new(Some(reader))),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            config,
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn compresses_frames_for_clients_accepting_gzip() {
+        let request = Request::builder()
+            .uri("/image/0")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = create_router(state_with(FakeReader::default()))
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    }
+
+    #[tokio::test]
+    async fn sends_frames_uncompressed_otherwise() {
+        let response = get(state_with(FakeReader::default()), "/image/0").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    }
+
+    #[tokio::test]
+    async fn reports_reader_transforms_in_debug_headers() {
+        let transforms = |response: Response| response.headers()["x-debug-transforms"].clone();
+        let state = state_with(FakeReader::default());
+        let response = get(state, "/image/0?debug=1").await;
+        assert_eq!(transforms(response), "none");
+
+        let reader = FakeReader {
+            transforms: vec!["transpose", "planar_rows"],
+            ..Default::default()
+        };
+        let state = state_with(reader);
+        let response = get(state.clone(), "/image/0?debug=1").await;
+        assert_eq!(transforms(response), "transpose,planar_rows");
+        let response = get(state, "/image/0?debug=1&depth=8").await;
+        assert_eq!(transforms(response), "transpose,planar_rows,depth8");
+    }
+
+    #[tokio::test]
+    async fn draws_masked_pixels_in_thumbnails() {
+        let mut mask = vec![0; 64 * 64];
+        mask[0] = 1;
+        let reader = FakeReader {
+            mask: Some(mask),
+            ..Default::default()
+        };
+        let response = get(state_with(reader), "/thumbnail/0?size=64").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let png = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let thumbnail = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(thumbnail.get_pixel(0, 0).0, render::MASKED_RGB);
+        assert_ne!(thumbnail.get_pixel(1, 0).0, render::MASKED_RGB);
+    }
+
+    #[tokio::test]
+    async fn reads_metadata_once_per_file() {
+        let reader = FakeReader::default();
+        let metadata_calls = reader.metadata_calls.clone();
+        let state = state_with(reader);
+        let uris = [
+            "/metadata",
+            "/image/0?depth=8",
+            "/image/1?units=photons",
+            "/frame/0",
+            "/mipmap/0",
+            "/histogram/0",
+            "/max_pixel/0",
+            "/thumbnail/0",
+            "/next_active/0",
+            "/montage",
+            "/project?op=max",
+            "/stats/stream",
+        ];
+        for _ in 0..2 {
+            for uri in uris {
+                let response = get(state.clone(), uri).await;
+                assert_eq!(response.status(), StatusCode::OK, "{uri}");
+                // Stream bodies are produced as they are read.
+                axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+            }
+            let response = get(state.clone(), "/resolution_map").await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        assert_eq!(metadata_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn reads_the_mask_once_per_file() {
+        let reader = FakeReader {
+            mask: Some(vec![0; 64 * 64]),
+            ..Default::default()
+        };
+        let mask_calls = reader.mask_calls.clone();
+        let state = state_with(reader);
+        for _ in 0..2 {
+            for uri in ["/mask", "/histogram/0", "/thumbnail/0"] {
+                let response = get(state.clone(), uri).await;
+                assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            }
+        }
+        assert_eq!(mask_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn answers_404_with_no_file_open() {
+        let state = state_with(FakeReader::default());
+        state.reader.lock().await.take();
+        let uris = [
+            "/metadata",
+            "/manifest",
+            "/mask",
+            "/histogram/0",
+            "/max_pixel/0",
+            "/mipmap/0",
+        ];
+        for uri in uris {
+            let response = get(state.clone(), uri).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+    }
+
+    async fn get_json(state: ServerState, uri: &str) -> serde_json::Value {
+        let response = get(state, uri).await;
+        assert_eq!(response.status(), StatusCode::OK, "{uri}");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_frame_times_in_the_manifest() {
+        let reader = FakeReader {
+            timestamps: true,
+            ..Default::default()
+        };
+        let manifest = get_json(state_with(reader), "/manifest").await;
+        assert_eq!(manifest["frame_count"], 4);
+        assert_eq!(manifest["masked_value"], u16::MAX);
+        let times = serde_json::json!([0.0, 0.5, 1.0, 1.5]);
+        assert_eq!(manifest["frame_times"], times);
+
+        let manifest = get_json(state_with(FakeReader::default()), "/manifest").await;
+        assert!(manifest.get("frame_times").is_none(), "{manifest}");
+    }
+
+    #[tokio::test]
+    async fn clears_the_disk_cache_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0123456789abcdef.frame"), [0u8; 100]).unwrap();
+        let reader: Box<dyn crate::readers::Reader> = Box::new(FakeReader::default());
+        let state = ServerState::new(
+            Arc::new(tokio::sync::Mutex::new(Some(reader))),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            ServerConfig::default(),
+            Some(DiskCache::new(dir.path().to_path_buf(), 1 << 20)),
+        );
+
+        let disk_bytes_freed = |query: &'static str| {
+            let state = state.clone();
+            async move {
+                let request = Request::builder()
+                    .method("POST")
+                    .uri(format!("/admin/clear_cache{query}"))
+                    .body(Body::empty())
+                    .unwrap();
+                let response = create_router(state).oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                result["disk_bytes_freed"].as_u64().unwrap()
+            }
+        };
+        assert_eq!(disk_bytes_freed("").await, 0);
+        assert_eq!(disk_bytes_freed("?disk=1").await, 100);
+        assert_eq!(disk_bytes_freed("?disk=1").await, 0);
+    }
+
+    /// The single value of a `/resolution_map` of the whole 64x64 frame.
+    async fn resolution(state: ServerState, query: &str) -> f32 {
+        let uri = format!("/resolution_map?downsample=64{query}");
+        let response = get(state, &uri).await;
+        assert_eq!(response.status(), StatusCode::OK, "{uri}");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let header_len = u32::from_le_bytes(body[..4].try_into().unwrap()) as usize;
+        let value = &body[4 + header_len..];
+        f32::from_le_bytes(value.try_into().unwrap())
+    }
+
+    #[tokio::test]
+    async fn maps_resolution_at_a_frame_distance() {
+        let reader = FakeReader {
+            geometry: true,
+            ..Default::default()
+        };
+        let state = state_with(reader);
+        let file = resolution(state.clone(), "").await;
+        // Frame 0 is where the file's distance comes from.
+        assert_eq!(resolution(state.clone(), "&frame=0").await, file);
+        // Twice as far back, the same pixel sees about twice the d-spacing.
+        let frame1 = resolution(state.clone(), "&frame=1").await;
+        assert!((frame1 / file - 2.0).abs() < 0.01, "{file} {frame1}");
+
+        let response = get(state, "/resolution_map?frame=4").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn embeds_frame_geometry_in_frame_containers() {
+        let reader = FakeReader {
+            geometry: true,
+            ..Default::default()
+        };
+        let state = state_with(reader);
+        let response = get(state.clone(), "/frame/1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let header_len = u32::from_le_bytes(body[..4].try_into().unwrap()) as usize;
+        let header: serde_json::Value = serde_json::from_slice(&body[4..4 + header_len]).unwrap();
+        assert_eq!(header["metadata"]["panel_distance_mm"], 100.0);
+        assert_eq!(header["frame_metadata"]["distance_mm"], 200.0);
+        assert_eq!(body.len(), 4 + header_len + 64 * 64 * 2);
+
+        let response = get(state, "/frame/4").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let closed = state_with(FakeReader::default());
+        closed.reader.lock().await.take();
+        assert_eq!(
+            get(closed, "/frame/0").await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn samples_aggregates_beyond_the_scan_limit() {
+        let config = ServerConfig {
+            max_scan_frames: 2,
+            ..Default::default()
+        };
+        let state = state_with_config(FakeReader::default(), config);
+
+        let response = get(state.clone(), "/project?op=max").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-frames-used"], "2");
+
+        let next = get_json(state.clone(), "/next_active/0").await;
+        assert_eq!(next["frame"], serde_json::Value::Null);
+        assert_eq!(next["frames_used"], 2);
+
+        let response = get(state, "/stats/stream").await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<serde_json::Value> = body
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        let frames: Vec<_> = lines.iter().filter_map(|line| line.get("frame")).collect();
+        assert_eq!(frames, [0, 2]);
+        assert_eq!(lines.last().unwrap()["frames_used"], 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn runs_analysis_at_the_configured_niceness() {
+        let state = state_with(FakeReader::default());
+        // SAFETY: getpriority has no memory-safety preconditions.
+        let niceness = state
+            .analysis_pool
+            .install(|| unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) });
+        // Higher if the tests themselves were started niced beyond it.
+        assert!(niceness >= DEFAULT_ANALYSIS_NICENESS, "{niceness}");
+    }
+}
@@ -0,0 +1,611 @@
+//! Pixel statistics computed over one or more frames.
+//!
+//! Like `render`, these are CPU-bound kernels meant to run inside
+//! `spawn_blocking`.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::CancelToken;
+use crate::readers::Reader;
+
+/// Summary statistics of a single frame.
+///
+/// `min`, `max` and `mean` are over trusted pixels only (`<= trusted_range_max`);
+/// untrusted pixels are counted in `overloads` instead. With no trusted
+/// pixels `min`, `max` and `mean` are 0.
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameStats {
+    pub frame: usize,
+    pub min: u16,
+    pub max: u16,
+    pub mean: f64,
+    pub overloads: usize,
+}
+
+impl FrameStats {
+    pub fn compute(frame: usize, pixels: &[u16], trusted_max: f64) -> Self {
+        let mut min = u16::MAX;
+        let mut max = 0u16;
+        let mut sum = 0u64;
+        let mut trusted = 0usize;
+        let mut overloads = 0usize;
+        for &v in pixels {
+            if f64::from(v) > trusted_max {
+                overloads += 1;
+                continue;
+            }
+            min = min.min(v);
+            max = max.max(v);
+            sum += u64::from(v);
+            trusted += 1;
+        }
+        if trusted == 0 {
+            min = 0;
+        }
+        Self {
+            frame,
+            min,
+            max,
+            mean: if trusted == 0 {
+                0.0
+            } else {
+                sum as f64 / trusted as f64
+            },
+            overloads,
+        }
+    }
+}
+
+/// First of `frames` whose mean trusted pixel value exceeds `threshold`, with
+/// its statistics, or `None` if none does. Frames are read one at a time in
+/// the order given, so the scan stops as soon as a match is found. Fails if
+/// `cancel` fires.
+pub fn find_active_frame(
+    reader: &dyn Reader,
+    frames: impl IntoIterator<Item = usize>,
+    trusted_max: f64,
+    threshold: f64,
+    cancel: &CancelToken,
+) -> Result<Option<FrameStats>> {
+    for frame in frames {
+        if cancel.is_cancelled() {
+            anyhow::bail!("Cancelled");
+        }
+        let (pixels, _, _) = reader.read_frame(frame)?;
+        let stats = FrameStats::compute(frame, &pixels, trusted_max);
+        if stats.mean > threshold {
+            return Ok(Some(stats));
+        }
+    }
+    Ok(None)
+}
+
+/// Position and value of the brightest pixel in a frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct MaxPixel {
+    pub x: usize,
+    pub y: usize,
+    pub value: u16,
+}
+
+/// Brightest pixel of `frame` that is not masked (see [`Reader::mask`]) and,
+/// unless `include_overloads`, not above `trusted_max`. Ties go to the first
+/// in row-major order. `None` if every pixel is excluded.
+pub fn max_pixel(
+    reader: &dyn Reader,
+    frame: usize,
+    trusted_max: f64,
+    include_overloads: bool,
+) -> Result<Option<MaxPixel>> {
+    let (pixels, width, height) = reader.read_frame(frame)?;
+    let mask = reader
+        .mask()?
+        .filter(|&(_, w, h)| (w, h) == (width, height))
+        .map(|(mask, _, _)| mask);
+    let trusted_max = if include_overloads {
+        f64::INFINITY
+    } else {
+        trusted_max
+    };
+
+    let mut best: Option<(usize, u16)> = None;
+    for (i, &v) in pixels.iter().enumerate() {
+        if f64::from(v) > trusted_max || mask.as_ref().is_some_and(|m| m[i] != 0) {
+            continue;
+        }
+        if best.is_none_or(|(_, max)| v > max) {
+            best = Some((i, v));
+        }
+    }
+    Ok(best.map(|(i, value)| MaxPixel {
+        x: i % width,
+        y: i / width,
+        value,
+    }))
+}
+
+/// Distribution of trusted pixel values in a frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct Histogram {
+    /// Smallest and largest trusted, unmasked value; both 0 if there are none.
+    pub min: u16,
+    pub max: u16,
+    /// Pixel counts in equal-width bins spanning `min..=max`.
+    pub bins: Vec<u64>,
+    /// `bins.len() + 1` edges; bin `i` holds values in `[edges[i], edges[i + 1])`.
+    pub bin_edges: Vec<f64>,
+    /// Pixels above `trusted_range_max`, kept out of the bins.
+    pub overflow: u64,
+    /// Pixels excluded by the mask.
+    pub masked: u64,
+}
+
+impl Histogram {
+    /// Histogram `pixels` into `bins` bins, skipping pixels where `mask` is
+    /// nonzero and counting those above `trusted_max` as overflow.
+    pub fn compute(pixels: &[u16], mask: Option<&[u8]>, trusted_max: f64, bins: usize) -> Self {
+        let bins = bins.max(1);
+        let is_masked = |i: usize| mask.is_some_and(|m| m[i] != 0);
+        let mut min = u16::MAX;
+        let mut max = 0u16;
+        let mut overflow = 0u64;
+        let mut masked = 0u64;
+        for (i, &v) in pixels.iter().enumerate() {
+            if is_masked(i) {
+                masked += 1;
+            } else if f64::from(v) > trusted_max {
+                overflow += 1;
+            } else {
+                min = min.min(v);
+                max = max.max(v);
+            }
+        }
+        if min > max {
+            min = 0;
+            max = 0;
+        }
+
+        // Integer values, so the top bin edge is `max + 1`.
+        let span = f64::from(max) - f64::from(min) + 1.0;
+        let width = span / bins as f64;
+        let mut counts = vec![0u64; bins];
+        for (i, &v) in pixels.iter().enumerate() {
+            if is_masked(i) || f64::from(v) > trusted_max {
+                continue;
+            }
+            let bin = (f64::from(v - min) / width) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Self {
+            min,
+            max,
+            bins: counts,
+            bin_edges: (0..=bins)
+                .map(|i| f64::from(min) + i as f64 * width)
+                .collect(),
+            overflow,
+            masked,
+        }
+    }
+}
+
+/// Side of each corner square used by [`corner_pedestal`], as a fraction of
+/// the shorter image side.
+const PEDESTAL_CORNER_FRACTION: usize = 16;
+
+/// Estimate a constant baseline offset (pedestal) from the median of the four
+/// image corners, which rarely see diffraction. Untrusted pixels are ignored;
+/// returns 0 if no corner pixel is trusted.
+pub fn corner_pedestal(pixels: &[u16], width: usize, height: usize, trusted_max: f64) -> u16 {
+    let side = (width.min(height) / PEDESTAL_CORNER_FRACTION).max(1);
+    let mut corner = Vec::with_capacity(4 * side * side);
+    for y in (0..side).chain(height.saturating_sub(side)..height) {
+        let row = &pixels[y * width..(y + 1) * width];
+        for x in (0..side).chain(width.saturating_sub(side)..width) {
+            let v = row[x];
+            if f64::from(v) <= trusted_max {
+                corner.push(v);
+            }
+        }
+    }
+    if corner.is_empty() {
+        return 0;
+    }
+    let mid = corner.len() / 2;
+    *corner.select_nth_unstable(mid).1
+}
+
+/// Subtract `pedestal` from every trusted pixel, clamping at 0. Untrusted
+/// pixels are left alone so they stay above `trusted_max`.
+pub fn subtract_pedestal(pixels: &mut [u16], pedestal: u16, trusted_max: f64) {
+    for v in pixels {
+        if f64::from(*v) <= trusted_max {
+            *v = v.saturating_sub(pedestal);
+        }
+    }
+}
+
+/// Maximum number of frames sampled when building mean/variance images.
+pub const MEAN_VARIANCE_SAMPLE_FRAMES: usize = 50;
+
+/// Evenly spaced indices of at most `max` frames out of `frame_count`.
+pub fn sample_frames(frame_count: usize, max: usize) -> Vec<usize> {
+    if frame_count <= max {
+        (0..frame_count).collect()
+    } else {
+        (0..max).map(|i| i * frame_count / max).collect()
+    }
+}
+
+/// Per-pixel mean and standard deviation over a sample of frames.
+///
+/// Only trusted pixels (`<= trusted_range_max`) contribute, so a pixel that
+/// is occasionally overloaded still gets statistics from its other frames.
+pub struct MeanVariance {
+    pub frames_used: usize,
+    mean: Vec<f32>,
+    /// `1 / σ` per pixel, or 0 where σ is zero or undefined.
+    inv_std: Vec<f32>,
+}
+
+impl MeanVariance {
+    /// Accumulate the given frames with Welford's online algorithm, so only
+    /// one frame is held in memory at a time. Fails if `cancel` fires.
+    pub fn compute(
+        reader: &dyn Reader,
+        frames: &[usize],
+        trusted_max: f64,
+        cancel: &CancelToken,
+    ) -> Result<Self> {
+        let Some(&first) = frames.first() else {
+            anyhow::bail!("No frames to compute mean/variance from");
+        };
+        let (pixels, width, height) = reader.read_frame(first)?;
+        let n_pixels = width * height;
+        let mut count = vec![0u32; n_pixels];
+        let mut mean = vec![0f32; n_pixels];
+        let mut m2 = vec![0f32; n_pixels];
+
+        let mut accumulate = |pixels: &[u16]| {
+            for (i, &v) in pixels.iter().enumerate() {
+                if f64::from(v) > trusted_max {
+                    continue;
+                }
+                let v = f32::from(v);
+                count[i] += 1;
+                let delta = v - mean[i];
+                mean[i] += delta / count[i] as f32;
+                m2[i] += delta * (v - mean[i]);
+            }
+        };
+
+        accumulate(&pixels);
+        for &frame in &frames[1..] {
+            if cancel.is_cancelled() {
+                anyhow::bail!("Cancelled");
+            }
+            let (pixels, w, h) = reader.read_frame(frame)?;
+            if (w, h) != (width, height) {
+                anyhow::bail!("Frame {frame} is {w}x{h}, expected {width}x{height}");
+            }
+            accumulate(&pixels);
+        }
+
+        let inv_std = m2
+            .iter()
+            .zip(&count)
+            .map(|(&m2, &n)| {
+                if n < 2 || m2 <= 0.0 {
+                    0.0
+                } else {
+                    1.0 / (m2 / (n - 1) as f32).sqrt()
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            frames_used: frames.len(),
+            mean,
+            inv_std,
+        })
+    }
+
+    /// Memory held by the mean and `1 / σ` images.
+    pub fn size_bytes(&self) -> usize {
+        (self.mean.len() + self.inv_std.len()) * std::mem::size_of::<f32>()
+    }
+
+    /// `(frame - mean) / σ` per pixel. Untrusted pixels, and pixels with no
+    /// usable variance, are NaN so the client can draw them as masked.
+    pub fn zscore(&self, pixels: &[u16], trusted_max: f64) -> Result<Vec<f32>> {
+        if pixels.len() != self.mean.len() {
+            anyhow::bail!(
+                "Frame has {} pixels, mean/variance images have {}",
+                pixels.len(),
+                self.mean.len()
+            );
+        }
+        Ok(pixels
+            .iter()
+            .zip(self.mean.iter().zip(&self.inv_std))
+            .map(|(&v, (&mean, &inv_std))| {
+                if f64::from(v) > trusted_max || inv_std == 0.0 {
+                    f32::NAN
+                } else {
+                    (f32::from(v) - mean) * inv_std
+                }
+            })
+            .collect())
+    }
+}
+
+/// How [`project`] combines a pixel's values across frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectionOp {
+    Sum,
+    Mean,
+    Max,
+}
+
+/// One frame combining a range of frames pixel by pixel.
+pub struct Projection {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: ProjectedPixels,
+}
+
+/// Pixels of a [`Projection`], row-major. Pixels with no usable value, i.e.
+/// masked or untrusted in every frame, are the type's `MAX`.
+pub enum ProjectedPixels {
+    /// Mean (rounded) or max.
+    U16(Vec<u16>),
+    /// Sum, saturating just below `u32::MAX`.
+    U32(Vec<u32>),
+}
+
+/// Running sum or max, and count of contributing frames, per pixel.
+#[derive(Default)]
+struct Accumulator {
+    width: usize,
+    height: usize,
+    values: Vec<u32>,
+    counts: Vec<u32>,
+}
+
+impl Accumulator {
+    fn add(
+        &mut self,
+        op: ProjectionOp,
+        pixels: &[u16],
+        width: usize,
+        height: usize,
+        trusted_max: f64,
+    ) -> Result<()> {
+        if self.values.is_empty() {
+            *self = Self {
+                width,
+                height,
+                values: vec![0; pixels.len()],
+                counts: vec![0; pixels.len()],
+            };
+        } else if (width, height) != (self.width, self.height) {
+            anyhow::bail!(
+                "Frame is {width}x{height}, expected {}x{}",
+                self.width,
+                self.height
+            );
+        }
+        for ((&v, value), count) in pixels.iter().zip(&mut self.values).zip(&mut self.counts) {
+            if f64::from(v) > trusted_max {
+                continue;
+            }
+            *value = match op {
+                ProjectionOp::Sum | ProjectionOp::Mean => value.saturating_add(u32::from(v)),
+                ProjectionOp::Max => (*value).max(u32::from(v)),
+            };
+            *count += 1;
+        }
+        Ok(())
+    }
+
+    fn merge(mut self, op: ProjectionOp, other: Self) -> Result<Self> {
+        if other.values.is_empty() {
+            return Ok(self);
+        }
+        if self.values.is_empty() {
+            return Ok(other);
+        }
+        if (other.width, other.height) != (self.width, self.height) {
+            anyhow::bail!(
+                "Frame is {}x{}, expected {}x{}",
+                other.width,
+                other.height,
+                self.width,
+                self.height
+            );
+        }
+        for (value, &other) in self.values.iter_mut().zip(&other.values) {
+            *value = match op {
+                ProjectionOp::Sum | ProjectionOp::Mean => value.saturating_add(other),
+                ProjectionOp::Max => (*value).max(other),
+            };
+        }
+        for (count, &other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        Ok(self)
+    }
+}
+
+/// Runs of consecutive frames of ascending `frames` that share a chunk of
+/// `block` frames.
+fn chunk_runs(frames: &[usize], block: usize) -> Vec<std::ops::Range<usize>> {
+    let mut runs: Vec<std::ops::Range<usize>> = Vec::new();
+    for &frame in frames {
+        match runs.last_mut() {
+            Some(run) if run.end == frame && frame % block != 0 => run.end += 1,
+            _ => runs.push(frame..frame + 1),
+        }
+    }
+    runs
+}
+
+/// Combine `frames`, in ascending order, pixel by pixel with `op`. Only
+/// trusted pixels (`<= trusted_range_max`) that the file's mask doesn't
+/// exclude contribute, so a mean is over the frames where the pixel was
+/// usable.
+///
+/// The frames are split into one contiguous part per rayon thread and each
+/// part is accumulated a frame at a time, so memory is one accumulator per
+/// thread however many frames are combined. Where the reader stores several
+/// frames per chunk, parts are split on chunk boundaries and the frames of
+/// each chunk are read together with [`Reader::read_frames`], rather than
+/// the chunk being decompressed again for every frame in it. Fails if
+/// `cancel` fires.
+pub fn project(
+    reader: &dyn Reader,
+    frames: &[usize],
+    op: ProjectionOp,
+    trusted_max: f64,
+    cancel: &CancelToken,
+) -> Result<Projection> {
+    use rayon::prelude::*;
+
+    if frames.is_empty() {
+        anyhow::bail!("No frames to project");
+    }
+    let block = reader.frames_per_chunk().max(1);
+    // Whole runs per thread, so no chunk is read by two of them.
+    let per_thread = frames.len().div_ceil(rayon::current_num_threads());
+    let mut parts: Vec<Vec<std::ops::Range<usize>>> = vec![Vec::new()];
+    let mut part_len = 0;
+    for run in chunk_runs(frames, block) {
+        if part_len >= per_thread {
+            parts.push(Vec::new());
+            part_len = 0;
+        }
+        part_len += run.len();
+        parts.last_mut().unwrap().push(run);
+    }
+    let total = parts
+        .into_par_iter()
+        .map(|part| {
+            let mut acc = Accumulator::default();
+            for read in part {
+                if cancel.is_cancelled() {
+                    anyhow::bail!("Cancelled");
+                }
+                let (pixels, width, height) = if read.len() == 1 {
+                    reader.read_frame(read.start)?
+                } else {
+                    reader.read_frames(read.clone())?
+                };
+                let frame_len = width * height;
+                if pixels.len() != read.len() * frame_len {
+                    anyhow::bail!(
+                        "Frames {read:?}: expected {} pixels, got {}",
+                        read.len() * frame_len,
+                        pixels.len()
+                    );
+                }
+                for (frame, pixels) in read.zip(pixels.chunks_exact(frame_len.max(1))) {
+                    acc.add(op, pixels, width, height, trusted_max)
+                        .map_err(|e| anyhow::anyhow!("Frame {frame}: {e}"))?;
+                }
+            }
+            Ok(acc)
+        })
+        .try_reduce(Accumulator::default, |a, b| a.merge(op, b))?;
+
+    let Accumulator {
+        width,
+        height,
+        mut values,
+        mut counts,
+    } = total;
+    if let Some((mask, _, _)) = reader
+        .mask()?
+        .filter(|&(_, w, h)| (w, h) == (width, height))
+    {
+        for (count, _) in counts.iter_mut().zip(&mask).filter(|(_, &m)| m != 0) {
+            *count = 0;
+        }
+    }
+    let pixels = match op {
+        ProjectionOp::Sum => {
+            for (value, &count) in values.iter_mut().zip(&counts) {
+                *value = if count == 0 {
+                    u32::MAX
+                } else {
+                    (*value).min(u32::MAX - 1)
+                };
+            }
+            ProjectedPixels::U32(values)
+        }
+        ProjectionOp::Mean | ProjectionOp::Max => ProjectedPixels::U16(
+            values
+                .iter()
+                .zip(&counts)
+                .map(|(&value, &count)| match (op, count) {
+                    (_, 0) => u16::MAX,
+                    (ProjectionOp::Mean, _) => (f64::from(value) / f64::from(count))
+                        .round()
+                        .min(f64::from(u16::MAX - 1))
+                        as u16,
+                    _ => value.min(u32::from(u16::MAX - 1)) as u16,
+                })
+                .collect(),
+        ),
+    };
+    Ok(Projection {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_bins_span_min_to_max() {
+        let pixels = [0, 1, 2, 3, 4, 5, 6, 7];
+        let histogram = Histogram::compute(&pixels, None, 100.0, 4);
+        assert_eq!((histogram.min, histogram.max), (0, 7));
+        assert_eq!(histogram.bins, [2, 2, 2, 2]);
+        assert_eq!(histogram.bin_edges, [0.0, 2.0, 4.0, 6.0, 8.0]);
+        assert_eq!((histogram.overflow, histogram.masked), (0, 0));
+    }
+
+    #[test]
+    fn histogram_counts_overflow_and_masked_pixels_outside_the_bins() {
+        let pixels = [10, 20, 30, 500, 40];
+        let mask = [0, 0, 1, 0, 0];
+        let histogram = Histogram::compute(&pixels, Some(&mask), 100.0, 2);
+        assert_eq!((histogram.min, histogram.max), (10, 40));
+        assert_eq!(histogram.bins, [2, 1]);
+        assert_eq!(histogram.bin_edges, [10.0, 25.5, 41.0]);
+        assert_eq!(histogram.overflow, 1);
+        assert_eq!(histogram.masked, 1);
+    }
+
+    #[test]
+    fn histogram_of_an_all_masked_frame_is_empty() {
+        let pixels = [5, 6, 7];
+        let histogram = Histogram::compute(&pixels, Some(&[1, 1, 1]), 100.0, 3);
+        assert_eq!((histogram.min, histogram.max), (0, 0));
+        assert_eq!(histogram.bins, [0, 0, 0]);
+        assert_eq!(histogram.bin_edges.len(), 4);
+        assert_eq!((histogram.overflow, histogram.masked), (0, 3));
+    }
+
+    #[test]
+    fn histogram_has_at_least_one_bin() {
+        let histogram = Histogram::compute(&[3, 4], None, 100.0, 0);
+        assert_eq!(histogram.bins, [2]);
+        assert_eq!(histogram.bin_edges, [3.0, 5.0]);
+    }
+}